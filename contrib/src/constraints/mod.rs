@@ -1 +1,4 @@
+pub mod process;
+pub mod smtlib2;
+#[cfg(feature = "z3")]
 pub mod z3;
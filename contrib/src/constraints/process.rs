@@ -0,0 +1,820 @@
+// Solver backend that drives any SMT-LIB2 compliant solver binary (z3, cvc5, yices-smt2, ...)
+// over its stdin/stdout.
+//
+// The solver is spawned lazily on the first query. Every declaration and assertion is kept
+// per assertion scope, so if the process has to be killed (e.g. after a timeout) it is
+// transparently restarted and the current assertion stack is replayed on the next query.
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use symbolic_stack_machines_core::constraint::*;
+use symbolic_stack_machines_core::sexpr::SExprError;
+use symbolic_stack_machines_core::value::{CSimpleVal, Sentence, Value};
+use thiserror::Error;
+
+use super::smtlib2::{
+    quote_symbol, unquote_symbol, SExpr, SmtLib2, SmtLib2Error, Sort, DEFAULT_WIDTH,
+};
+
+#[derive(Error, Debug)]
+pub enum ProcessSolverError {
+    #[error("Failed to communicate with solver: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Solver exited unexpectedly")]
+    Exited,
+    #[error("Solver timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Solver error: {0}")]
+    Solver(String),
+    #[error("Unexpected solver response {0}")]
+    UnexpectedResponse(String),
+    #[error("Cannot pop the outermost assertion scope")]
+    PopOutermostScope,
+    #[error(transparent)]
+    SmtLib2(#[from] SmtLib2Error),
+}
+
+pub type ProcessSolverResult<T> = Result<T, ProcessSolverError>;
+
+// Labels are namespaced so they can't clash with the names of declared symbols
+pub(super) const LABEL_PREFIX: &str = "core!";
+
+// How `SolverLimits` are passed to the solver. With `Generic` only the timeout is
+// enforced, by killing the solver once it is exceeded, and unsat cores are not minimized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SolverDialect {
+    Generic,
+    Z3,
+    Cvc5,
+}
+
+pub struct ProcessSolverBuilder {
+    command: String,
+    args: Vec<String>,
+    timeout: Option<Duration>,
+    width: u32,
+    dialect: SolverDialect,
+    limits: SolverLimits,
+}
+
+impl ProcessSolverBuilder {
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            args: vec![],
+            timeout: None,
+            width: DEFAULT_WIDTH,
+            dialect: SolverDialect::Generic,
+            limits: SolverLimits::default(),
+        }
+    }
+
+    pub fn z3() -> Self {
+        Self::new("z3")
+            .args(&["-in", "-smt2"])
+            .dialect(SolverDialect::Z3)
+    }
+
+    pub fn cvc5() -> Self {
+        Self::new("cvc5")
+            .args(&["--lang=smt2", "--incremental"])
+            .dialect(SolverDialect::Cvc5)
+    }
+
+    pub fn yices() -> Self {
+        Self::new("yices-smt2").args(&["--incremental"])
+    }
+
+    pub fn args(mut self, args: &[&str]) -> Self {
+        self.args = args.iter().map(|a| a.to_string()).collect();
+        self
+    }
+
+    // Upper bound on how long any single solver response may take
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn width(mut self, width: u32) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn dialect(mut self, dialect: SolverDialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    // Limits for each `check-sat`, as opposed to `timeout` which bounds every response
+    pub fn limits(mut self, limits: SolverLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn build(self) -> ProcessSolver {
+        ProcessSolver {
+            command: self.command,
+            args: self.args,
+            timeout: self.timeout,
+            dialect: self.dialect,
+            limits: self.limits,
+            smt: SmtLib2::new(self.width),
+            state: RefCell::new(ProcessState {
+                process: None,
+                scopes: vec![AssertionScope::default()],
+            }),
+            last_error: RefCell::new(None),
+            unknown: RefCell::new(None),
+            core: RefCell::new(None),
+        }
+    }
+}
+
+struct SolverProcess {
+    child: Child,
+    stdin: ChildStdin,
+    responses: Receiver<String>,
+}
+
+impl Drop for SolverProcess {
+    fn drop(&mut self) {
+        let _ = writeln!(self.stdin, "(exit)");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[derive(Default)]
+struct AssertionScope {
+    commands: Vec<String>,
+    declared: BTreeSet<String>,
+    labelled: bool,
+    // Number of `commands` already sent to the running process
+    sent: usize,
+    // Whether the `(push 1)` opening this scope was sent to the running process
+    pushed: bool,
+    // A constraint asserted in this scope that couldn't be encoded
+    invalid: Option<SmtLib2Error>,
+}
+
+struct ProcessState {
+    process: Option<SolverProcess>,
+    scopes: Vec<AssertionScope>,
+}
+
+pub struct ProcessSolver {
+    command: String,
+    args: Vec<String>,
+    timeout: Option<Duration>,
+    dialect: SolverDialect,
+    limits: SolverLimits,
+    smt: SmtLib2,
+    state: RefCell<ProcessState>,
+    last_error: RefCell<Option<ProcessSolverError>>,
+    unknown: RefCell<Option<UnknownReason>>,
+    core: RefCell<Option<Vec<String>>>,
+}
+
+impl ProcessSolver {
+    pub fn smt(&self) -> &SmtLib2 {
+        &self.smt
+    }
+
+    // The error behind the most recent `SatResult::Unknown` returned by `solve`, if any
+    pub fn take_last_error(&self) -> Option<ProcessSolverError> {
+        self.last_error.borrow_mut().take()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state.borrow().process.is_some()
+    }
+
+    pub fn limits(&self) -> &SolverLimits {
+        &self.limits
+    }
+
+    // Takes effect from the next query. A running solver is restarted, since not every
+    // limit can be changed once it is up.
+    pub fn set_limits(&mut self, limits: SolverLimits) {
+        self.limits = limits;
+        self.state.get_mut().process = None;
+    }
+
+    // A constraint that can't be encoded is not asserted. Every query fails with the
+    // encoding error instead, until the scope it was asserted in is popped.
+    pub fn assert_constraint(&mut self, constraint: &Constraint) -> ProcessSolverResult<()> {
+        let term = self.encode(constraint)?;
+        self.add_command(format!("(assert {})", term));
+        Ok(())
+    }
+
+    // Asserts `constraint` as a named term, reported by `unsat_core` when it is part of
+    // a contradiction
+    pub fn assert_labelled(
+        &mut self,
+        label: &str,
+        constraint: &Constraint,
+    ) -> ProcessSolverResult<()> {
+        let term = self.encode(constraint)?;
+        let name = quote_symbol(&format!("{}{}", LABEL_PREFIX, label));
+        self.add_command(format!("(assert (! {} :named {}))", term, name));
+        self.state.get_mut().scopes.last_mut().unwrap().labelled = true;
+        Ok(())
+    }
+
+    fn encode(&mut self, constraint: &Constraint) -> ProcessSolverResult<String> {
+        self.smt.try_transpile(constraint).map_err(|e| {
+            let scope = self.state.get_mut().scopes.last_mut().unwrap();
+            scope.invalid.get_or_insert_with(|| e.clone());
+            e.into()
+        })
+    }
+
+    // Labels of the assertions behind the most recent `Unsat` answer, if any were labelled
+    pub fn unsat_core(&self) -> Option<Vec<String>> {
+        self.core.borrow().clone()
+    }
+
+    // Adds `command` to the current scope, declaring any symbols it introduced first
+    fn add_command(&mut self, command: String) {
+        let state = self.state.get_mut();
+
+        let declarations = self.smt.declarations();
+        self.smt.clear_declarations();
+        for (name, sort) in declarations {
+            if !state.scopes.iter().any(|s| s.declared.contains(&name)) {
+                let scope = state.scopes.last_mut().unwrap();
+                scope.commands.push(SmtLib2::declare_command(&name, sort));
+                scope.declared.insert(name);
+            }
+        }
+
+        let scope = state.scopes.last_mut().unwrap();
+        scope.commands.push(command);
+    }
+
+    pub fn push(&mut self) -> ProcessSolverResult<()> {
+        let res = self.push_inner();
+        self.reset_on_error(res)
+    }
+
+    pub fn pop(&mut self) -> ProcessSolverResult<()> {
+        if self.state.get_mut().scopes.len() == 1 {
+            return Err(ProcessSolverError::PopOutermostScope);
+        }
+        let res = self.pop_inner();
+        self.reset_on_error(res)
+    }
+
+    // Uses the solver's own optimizer (`minimize`/`maximize`) with the z3 dialect,
+    // and `bisect` otherwise. The second result is that of popping the objective's scope,
+    // which is popped on every path. A failed pop still drops the scope and restarts the
+    // solver, so it doesn't invalidate the optimum.
+    pub fn optimize(
+        &mut self,
+        objective: &Objective,
+    ) -> (
+        ProcessSolverResult<SatResult<Optimum<Model>>>,
+        ProcessSolverResult<()>,
+    ) {
+        if self.dialect != SolverDialect::Z3 {
+            let width = self.smt.width();
+            return (
+                Ok(bisect::<_, String, String>(self, objective, width)),
+                Ok(()),
+            );
+        }
+
+        // The objective only lives in its own scope. `push` records the scope even when it
+        // fails, so it is always popped.
+        let res = self.push().and_then(|_| {
+            let res = self.optimize_inner(objective);
+            self.reset_on_error(res)
+        });
+        let popped = self.pop();
+        (res, popped)
+    }
+
+    pub fn check_sat(&self) -> ProcessSolverResult<SatResult<Model>> {
+        let res = self.check_sat_inner();
+        self.reset_on_error(res)
+    }
+
+    // Everything currently asserted, as a self-contained script
+    pub fn script(&self) -> String {
+        let state = self.state.borrow();
+        let mut out = String::new();
+        for (i, scope) in state.scopes.iter().enumerate() {
+            if i > 0 {
+                out.push_str("(push 1)\n");
+            }
+            for c in &scope.commands {
+                out.push_str(c);
+                out.push('\n');
+            }
+        }
+        out.push_str("(check-sat)\n");
+        out
+    }
+
+    // Any failure leaves the process in an unknown state, so drop (and kill) it.
+    // The assertion stack is replayed into a fresh process on the next query.
+    fn reset_on_error<T>(&self, res: ProcessSolverResult<T>) -> ProcessSolverResult<T> {
+        if res.is_err() {
+            self.state.borrow_mut().process = None;
+        }
+        res
+    }
+
+    // The scope is recorded even if talking to the solver fails, so pushes and pops stay
+    // balanced for the caller; `sync` sends the `(push 1)` when the process is running
+    fn push_inner(&mut self) -> ProcessSolverResult<()> {
+        self.state.get_mut().scopes.push(AssertionScope::default());
+        if self.is_running() {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    fn pop_inner(&mut self) -> ProcessSolverResult<()> {
+        let scope = self.state.get_mut().scopes.pop().unwrap();
+        if scope.pushed && self.is_running() {
+            self.command("(pop 1)")?;
+        }
+        Ok(())
+    }
+
+    fn optimize_inner(
+        &mut self,
+        objective: &Objective,
+    ) -> ProcessSolverResult<SatResult<Optimum<Model>>> {
+        let term = self.smt.try_term(objective.term())?;
+        let command = match objective {
+            Objective::Minimize(_) => "minimize",
+            Objective::Maximize(_) => "maximize",
+        };
+        self.add_command(format!("({} {})", command, term));
+
+        let model = match self.check_sat_inner()? {
+            SatResult::Sat(model) => model,
+            SatResult::Unsat => return Ok(SatResult::Unsat),
+            SatResult::Unknown => return Ok(SatResult::Unknown),
+        };
+
+        self.send(&format!("(get-value ({}))", term))?;
+        let res = self.read_response()?;
+        let value = match &res {
+            SExpr::List(values) => match values.as_slice() {
+                [SExpr::List(pair)] if pair.len() == 2 => {
+                    self.smt.parse_term(&pair[1].to_string())?
+                }
+                _ => return Err(Self::unexpected(res)),
+            },
+            _ => return Err(Self::unexpected(res)),
+        };
+        match value {
+            Sentence::Basic(Value::Concrete(CSimpleVal::Number(value))) => {
+                Ok(SatResult::Sat(Optimum { model, value }))
+            }
+            _ => Err(Self::unexpected(res)),
+        }
+    }
+
+    fn check_sat_inner(&self) -> ProcessSolverResult<SatResult<Model>> {
+        if let Some(e) = self
+            .state
+            .borrow()
+            .scopes
+            .iter()
+            .find_map(|s| s.invalid.clone())
+        {
+            return Err(e.into());
+        }
+        self.sync()?;
+        self.send("(check-sat)")?;
+        let timeout = match (self.dialect, self.limits.timeout, self.timeout) {
+            (SolverDialect::Generic, Some(limit), Some(t)) => Some(limit.min(t)),
+            (SolverDialect::Generic, Some(limit), None) => Some(limit),
+            (_, _, t) => t,
+        };
+        let res = self.read_response_within(timeout)?;
+
+        match res.as_atom() {
+            Some("sat") => {
+                self.send("(get-model)")?;
+                let model = self.read_response()?;
+                Ok(SatResult::Sat(parse_model(&self.smt, &model)?))
+            }
+            Some("unsat") => {
+                if self.state.borrow().scopes.iter().any(|s| s.labelled) {
+                    *self.core.borrow_mut() = self.unsat_core_inner()?;
+                }
+                Ok(SatResult::Unsat)
+            }
+            Some("unknown") => {
+                *self.unknown.borrow_mut() = Some(self.reason_unknown_inner()?);
+                Ok(SatResult::Unknown)
+            }
+            _ => Err(Self::unexpected(res)),
+        }
+    }
+
+    // A solver that can't produce the core answers with an error, which is not a failure
+    fn unsat_core_inner(&self) -> ProcessSolverResult<Option<Vec<String>>> {
+        self.send("(get-unsat-core)")?;
+        let res = self.read_response()?;
+        let names = match &res {
+            SExpr::List(items) if items.first().and_then(|i| i.as_atom()) == Some("error") => {
+                return Ok(None)
+            }
+            SExpr::List(items) => items,
+            _ => return Err(Self::unexpected(res)),
+        };
+
+        Ok(Some(
+            names
+                .iter()
+                .filter_map(|n| n.as_atom())
+                .filter_map(|n| unquote_symbol(n).strip_prefix(LABEL_PREFIX))
+                .map(|n| n.to_string())
+                .collect(),
+        ))
+    }
+
+    // Not every solver supports `:reason-unknown`, so an error response is not a failure
+    fn reason_unknown_inner(&self) -> ProcessSolverResult<UnknownReason> {
+        self.send("(get-info :reason-unknown)")?;
+        let res = self.read_response()?;
+        let reason = match &res {
+            SExpr::List(items) if items.len() == 2 => {
+                let text = items[1].to_string().to_lowercase();
+                unquote_symbol(text.trim_matches('"')).to_string()
+            }
+            _ => return Ok(UnknownReason::Incomplete(res.to_string())),
+        };
+
+        Ok(unknown_reason(reason))
+    }
+
+    fn limit_args(&self) -> Vec<String> {
+        match (self.dialect, self.limits.memory_mb) {
+            (SolverDialect::Z3, Some(mb)) => vec![format!("-memory:{}", mb)],
+            _ => vec![],
+        }
+    }
+
+    fn dialect_options(&self) -> Vec<String> {
+        let limits = &self.limits;
+        let (timeout, rlimit, minimal_cores) = match self.dialect {
+            SolverDialect::Generic => return vec![],
+            SolverDialect::Z3 => ("timeout", "rlimit", "smt.core.minimize"),
+            SolverDialect::Cvc5 => ("tlimit-per", "rlimit-per", "minimal-unsat-cores"),
+        };
+        let mut options = vec![format!("(set-option :{} true)", minimal_cores)];
+        if let Some(t) = limits.timeout {
+            options.push(format!("(set-option :{} {})", timeout, t.as_millis()));
+        }
+        if let Some(r) = limits.rlimit {
+            options.push(format!("(set-option :{} {})", rlimit, r));
+        }
+        options
+    }
+
+    fn spawn(&self) -> ProcessSolverResult<SolverProcess> {
+        let mut child = Command::new(&self.command)
+            .args(&self.args)
+            .args(self.limit_args())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdin = child.stdin.take().ok_or(ProcessSolverError::Exited)?;
+        let stdout = child.stdout.take().ok_or(ProcessSolverError::Exited)?;
+
+        let (tx, responses) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(SolverProcess {
+            child,
+            stdin,
+            responses,
+        })
+    }
+
+    // Bring the solver process up to date with the assertion stack,
+    // starting it and replaying every scope if it isn't running
+    fn sync(&self) -> ProcessSolverResult<()> {
+        if !self.is_running() {
+            let process = self.spawn()?;
+            let mut state = self.state.borrow_mut();
+            state.process = Some(process);
+            state.scopes.iter_mut().for_each(|s| {
+                s.sent = 0;
+                s.pushed = false;
+            });
+            drop(state);
+
+            self.command("(set-option :print-success true)")?;
+            self.command("(set-option :produce-models true)")?;
+            self.command("(set-option :produce-unsat-cores true)")?;
+            for option in self.dialect_options() {
+                self.command(&option)?;
+            }
+        }
+
+        let num_scopes = self.state.borrow().scopes.len();
+        for i in 0..num_scopes {
+            let (pending, needs_push) = {
+                let state = self.state.borrow();
+                let scope = &state.scopes[i];
+                (
+                    scope.commands[scope.sent..].to_vec(),
+                    i > 0 && !scope.pushed,
+                )
+            };
+            if needs_push {
+                self.command("(push 1)")?;
+                self.state.borrow_mut().scopes[i].pushed = true;
+            }
+            for c in pending {
+                self.command(&c)?;
+                self.state.borrow_mut().scopes[i].sent += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send(&self, cmd: &str) -> ProcessSolverResult<()> {
+        let mut state = self.state.borrow_mut();
+        let process = state.process.as_mut().ok_or(ProcessSolverError::Exited)?;
+        writeln!(process.stdin, "{}", cmd)?;
+        process.stdin.flush()?;
+        Ok(())
+    }
+
+    // Sends a command that is answered with `success` when `:print-success` is on.
+    // Options the solver doesn't know are skipped.
+    fn command(&self, cmd: &str) -> ProcessSolverResult<()> {
+        self.send(cmd)?;
+        let res = self.read_response()?;
+        match res.as_atom() {
+            Some("success") => Ok(()),
+            Some("unsupported") if cmd.starts_with("(set-option") => Ok(()),
+            _ => Err(Self::unexpected(res)),
+        }
+    }
+
+    fn read_response(&self) -> ProcessSolverResult<SExpr> {
+        self.read_response_within(self.timeout)
+    }
+
+    fn read_response_within(&self, timeout: Option<Duration>) -> ProcessSolverResult<SExpr> {
+        let state = self.state.borrow();
+        let process = state.process.as_ref().ok_or(ProcessSolverError::Exited)?;
+        let deadline = timeout.map(|t| Instant::now() + t);
+
+        let mut buf = String::new();
+        loop {
+            let line = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    process
+                        .responses
+                        .recv_timeout(remaining)
+                        .map_err(|e| match e {
+                            RecvTimeoutError::Timeout => {
+                                ProcessSolverError::Timeout(timeout.unwrap())
+                            }
+                            RecvTimeoutError::Disconnected => ProcessSolverError::Exited,
+                        })?
+                }
+                None => process
+                    .responses
+                    .recv()
+                    .map_err(|_| ProcessSolverError::Exited)?,
+            };
+            buf.push_str(&line);
+            buf.push('\n');
+
+            // Responses such as `get-model` span several lines
+            match SExpr::parse_all(&buf) {
+                Ok(mut exprs) if !exprs.is_empty() => return Ok(exprs.remove(0)),
+                Ok(_) | Err(SExprError::UnexpectedEof) => {}
+                Err(e) => return Err(SmtLib2Error::from(e).into()),
+            }
+        }
+    }
+
+    fn unexpected(res: SExpr) -> ProcessSolverError {
+        match &res {
+            SExpr::List(items) if items.first().and_then(|i| i.as_atom()) == Some("error") => {
+                let msg = items.get(1).map(|m| m.to_string()).unwrap_or_default();
+                ProcessSolverError::Solver(msg.trim_matches('"').to_string())
+            }
+            _ => ProcessSolverError::UnexpectedResponse(res.to_string()),
+        }
+    }
+}
+
+// Classifies a solver's `:reason-unknown`. z3 reports a timeout as "canceled".
+pub(super) fn unknown_reason(reason: String) -> UnknownReason {
+    let lower = reason.to_lowercase();
+    if lower.contains("timeout") || lower.contains("canceled") {
+        UnknownReason::Timeout
+    } else if lower.contains("resource") || lower.contains("memout") {
+        UnknownReason::ResourceLimit
+    } else {
+        UnknownReason::Incomplete(reason)
+    }
+}
+
+// Parses a `get-model` response, e.g. `((define-fun x () (_ BitVec 64) #x0000000000000005))`.
+// Older z3 versions wrap the definitions in `(model ...)`. Definitions with arguments and
+// arrays, such as symbolic memory, are skipped.
+pub fn parse_model(smt: &SmtLib2, response: &SExpr) -> ProcessSolverResult<Model> {
+    let defs = match response {
+        SExpr::List(items) => match items.first().and_then(|i| i.as_atom()) {
+            Some("model") => &items[1..],
+            _ => &items[..],
+        },
+        _ => return Err(ProcessSolverError::UnexpectedResponse(response.to_string())),
+    };
+
+    let mut model = Model::new();
+    for def in defs {
+        let parts = match def {
+            SExpr::List(parts) => parts,
+            _ => return Err(ProcessSolverError::UnexpectedResponse(def.to_string())),
+        };
+        match parts.as_slice() {
+            [SExpr::Atom(kw), SExpr::Atom(name), SExpr::List(args), sort, value]
+                if kw == "define-fun" =>
+            {
+                if !args.is_empty() || matches!(SmtLib2::parse_sort(sort), Ok(Sort::Array(..))) {
+                    continue;
+                }
+                match smt.parse_term(&value.to_string())? {
+                    Sentence::Basic(Value::Concrete(v)) => model.insert(unquote_symbol(name), v),
+                    _ => return Err(ProcessSolverError::UnexpectedResponse(def.to_string())),
+                }
+            }
+            _ => return Err(ProcessSolverError::UnexpectedResponse(def.to_string())),
+        }
+    }
+
+    Ok(model)
+}
+
+impl Constrained for ProcessSolver {
+    type Model = Model;
+
+    fn check(&self) -> SatResult<Self::Model> {
+        *self.unknown.borrow_mut() = None;
+        *self.core.borrow_mut() = None;
+        match self.check_sat() {
+            Ok(res) => res,
+            Err(e) => {
+                *self.unknown.borrow_mut() = Some(match e {
+                    ProcessSolverError::Timeout(_) => UnknownReason::Timeout,
+                    _ => UnknownReason::Error(e.to_string()),
+                });
+                *self.last_error.borrow_mut() = Some(e);
+                SatResult::Unknown
+            }
+        }
+    }
+}
+
+impl Solver<String, String> for ProcessSolver {
+    // An encoding error is reported by the next query
+    fn generic_assert(&mut self, constraint: &Constraint) {
+        let _ = self.assert_constraint(constraint);
+    }
+
+    fn solve(&self) -> SatResult<Self::Model> {
+        self.check()
+    }
+
+    fn push(&mut self) {
+        if let Err(e) = ProcessSolver::push(self) {
+            *self.last_error.get_mut() = Some(e);
+        }
+    }
+
+    fn pop(&mut self) {
+        if let Err(e) = ProcessSolver::pop(self) {
+            *self.last_error.get_mut() = Some(e);
+        }
+    }
+
+    fn set_limits(&mut self, limits: &SolverLimits) {
+        ProcessSolver::set_limits(self, limits.clone());
+    }
+
+    fn reason_unknown(&self) -> Option<UnknownReason> {
+        self.unknown.borrow().clone()
+    }
+
+    fn assert_labelled(&mut self, label: &str, constraint: &Constraint) {
+        let _ = ProcessSolver::assert_labelled(self, label, constraint);
+    }
+
+    fn unsat_core(&self) -> Option<Vec<String>> {
+        ProcessSolver::unsat_core(self)
+    }
+}
+
+impl Optimize<String, String> for ProcessSolver {
+    fn optimize(&mut self, objective: &Objective) -> SatResult<Optimum<Model>> {
+        match ProcessSolver::optimize(self, objective) {
+            (Ok(res), popped) => {
+                *self.last_error.get_mut() = popped.err();
+                res
+            }
+            (Err(e), _) => {
+                *self.last_error.get_mut() = Some(e);
+                SatResult::Unknown
+            }
+        }
+    }
+}
+
+impl Transpile<String, String> for ProcessSolver {
+    fn val_to_ground_type(&self, v: Sentence) -> String {
+        self.smt.val_to_ground_type(v)
+    }
+
+    fn ground_type_to_val(&self, g: String) -> Sentence {
+        self.smt.ground_type_to_val(g)
+    }
+
+    fn val_to_bool(&self, v: Sentence) -> String {
+        self.smt.val_to_bool(v)
+    }
+
+    fn assert(&self, c: String) -> String {
+        self.smt.assert(c)
+    }
+
+    fn and(&self, l: String, r: String) -> String {
+        self.smt.and(l, r)
+    }
+
+    fn not(&self, c: String) -> String {
+        self.smt.not(c)
+    }
+
+    fn or(&self, l: String, r: String) -> String {
+        self.smt.or(l, r)
+    }
+
+    fn gt(&self, l: String, r: String) -> String {
+        self.smt.gt(l, r)
+    }
+
+    fn lt(&self, l: String, r: String) -> String {
+        self.smt.lt(l, r)
+    }
+
+    fn lte(&self, l: String, r: String) -> String {
+        self.smt.lte(l, r)
+    }
+
+    fn gte(&self, l: String, r: String) -> String {
+        self.smt.gte(l, r)
+    }
+
+    fn eq(&self, l: String, r: String) -> String {
+        self.smt.eq(l, r)
+    }
+
+    fn neq(&self, l: String, r: String) -> String {
+        self.smt.neq(l, r)
+    }
+
+    fn ite(&self, c: String, t: String, e: String) -> String {
+        self.smt.ite(c, t, e)
+    }
+
+    fn true_(&self) -> String {
+        self.smt.true_()
+    }
+
+    fn false_(&self) -> String {
+        self.smt.false_()
+    }
+}
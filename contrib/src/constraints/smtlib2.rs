@@ -0,0 +1,863 @@
+// SMT-LIB2 text backend.
+//
+// Numbers are encoded as fixed-width bit vectors (64 bits by default), so every
+// `BinOp` has a direct SMT-LIB2 counterpart. Concrete numbers narrower than the
+// configured width are zero-extended, which means a parsed script yields numbers
+// of the configured width rather than the original `CNumber` variant.
+//
+// Byte arrays (`BinOp::Select` and `TernaryOp::Store`) are arrays from bit vectors of the
+// configured width to 8-bit vectors. Vector symbols are declared as such arrays, concrete
+// vectors are stores over an array of zeros, and selected bytes are zero-extended to the
+// configured width.
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Write};
+use std::rc::Rc;
+
+use symbolic_stack_machines_core::constraint::*;
+pub use symbolic_stack_machines_core::sexpr::SExpr;
+use symbolic_stack_machines_core::sexpr::SExprError;
+use symbolic_stack_machines_core::value::*;
+use thiserror::Error;
+
+pub const DEFAULT_WIDTH: u32 = 64;
+
+#[derive(Error, Debug, Clone)]
+pub enum SmtLib2Error {
+    #[error("Unexpected end of input")]
+    UnexpectedEof,
+    #[error("Unexpected token {0}")]
+    UnexpectedToken(String),
+    #[error("Undeclared symbol {0}")]
+    UndeclaredSymbol(String),
+    #[error("Sort mismatch in {0}")]
+    SortMismatch(String),
+    #[error("Unsupported {0}")]
+    Unsupported(String),
+}
+
+impl From<SExprError> for SmtLib2Error {
+    fn from(e: SExprError) -> Self {
+        match e {
+            SExprError::UnexpectedEof => SmtLib2Error::UnexpectedEof,
+            SExprError::UnexpectedToken(t) | SExprError::InvalidSentence(t) => {
+                SmtLib2Error::UnexpectedToken(t)
+            }
+        }
+    }
+}
+
+pub type SmtLib2Result<T> = Result<T, SmtLib2Error>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sort {
+    Bool,
+    BitVec(u32),
+    // Index and element widths
+    Array(u32, u32),
+}
+
+impl Display for Sort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Sort::Bool => write!(f, "Bool"),
+            Sort::BitVec(w) => write!(f, "(_ BitVec {})", w),
+            Sort::Array(i, e) => write!(f, "(Array (_ BitVec {}) (_ BitVec {}))", i, e),
+        }
+    }
+}
+
+pub fn quote_symbol(name: &str) -> String {
+    let simple = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "~!@$%^&*_-+=<>.?/".contains(c))
+        && !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit());
+    if simple {
+        name.to_string()
+    } else {
+        format!("|{}|", name)
+    }
+}
+
+pub fn unquote_symbol(name: &str) -> &str {
+    name.strip_prefix('|')
+        .and_then(|n| n.strip_suffix('|'))
+        .unwrap_or(name)
+}
+
+fn number(v: u128, width: u32) -> Sentence {
+    Sentence::Basic(Value::Concrete(CSimpleVal::Number(
+        CNumber::from_u128_with_width(v, width),
+    )))
+}
+
+fn bin_op(a: Sentence, b: Sentence, op: BinOp) -> Sentence {
+    Sentence::BinOp {
+        a: Val::new(a),
+        b: Val::new(b),
+        op,
+    }
+}
+
+fn bv_op_name(op: &BinOp) -> Option<&'static str> {
+    match op {
+        BinOp::Plus => Some("bvadd"),
+        BinOp::Minus => Some("bvsub"),
+        BinOp::Mul => Some("bvmul"),
+        BinOp::Div => Some("bvudiv"),
+        BinOp::Mod => Some("bvurem"),
+        BinOp::BitOr => Some("bvor"),
+        BinOp::BitAnd => Some("bvand"),
+        BinOp::BitXor => Some("bvxor"),
+        BinOp::LShift => Some("bvshl"),
+        BinOp::RShift => Some("bvlshr"),
+        BinOp::Lt => Some("bvult"),
+        BinOp::Lte => Some("bvule"),
+        BinOp::Gt => Some("bvugt"),
+        BinOp::Gte => Some("bvuge"),
+        BinOp::Eq | BinOp::Neq | BinOp::Select => None,
+    }
+}
+
+fn bv_op_from_name(name: &str) -> Option<BinOp> {
+    let op = match name {
+        "bvadd" => BinOp::Plus,
+        "bvsub" => BinOp::Minus,
+        "bvmul" => BinOp::Mul,
+        "bvudiv" => BinOp::Div,
+        "bvurem" => BinOp::Mod,
+        "bvor" => BinOp::BitOr,
+        "bvand" => BinOp::BitAnd,
+        "bvxor" => BinOp::BitXor,
+        "bvshl" => BinOp::LShift,
+        "bvlshr" => BinOp::RShift,
+        "bvult" => BinOp::Lt,
+        "bvule" => BinOp::Lte,
+        "bvugt" => BinOp::Gt,
+        "bvuge" => BinOp::Gte,
+        _ => return None,
+    };
+    Some(op)
+}
+
+fn is_comparison(op: &BinOp) -> bool {
+    matches!(
+        op,
+        BinOp::Eq | BinOp::Neq | BinOp::Lt | BinOp::Lte | BinOp::Gt | BinOp::Gte
+    )
+}
+
+pub struct SmtLib2 {
+    width: u32,
+    decls: RefCell<BTreeMap<String, Sort>>,
+    // The first term `Transpile` couldn't encode, see `take_error`
+    error: RefCell<Option<SmtLib2Error>>,
+}
+
+impl Default for SmtLib2 {
+    fn default() -> Self {
+        Self::new(DEFAULT_WIDTH)
+    }
+}
+
+impl SmtLib2 {
+    pub fn new(width: u32) -> Self {
+        Self {
+            width,
+            decls: RefCell::new(BTreeMap::new()),
+            error: RefCell::new(None),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    // Every symbol seen so far, either while transpiling or while parsing a script
+    pub fn declarations(&self) -> Vec<(String, Sort)> {
+        self.decls
+            .borrow()
+            .iter()
+            .map(|(n, s)| (n.clone(), *s))
+            .collect()
+    }
+
+    pub fn declare(&self, name: &str, sort: Sort) {
+        self.decls
+            .borrow_mut()
+            .entry(name.to_string())
+            .or_insert(sort);
+    }
+
+    pub fn clear_declarations(&self) {
+        self.decls.borrow_mut().clear();
+    }
+
+    pub fn sort_of(&self, name: &str) -> Option<Sort> {
+        self.decls.borrow().get(name).copied()
+    }
+
+    pub fn declare_command(name: &str, sort: Sort) -> String {
+        format!("(declare-const {} {})", quote_symbol(name), sort)
+    }
+
+    // `Transpile` can't fail, so a term it can't encode is replaced and the first such error
+    // is kept until taken here
+    pub fn take_error(&self) -> Option<SmtLib2Error> {
+        self.error.borrow_mut().take()
+    }
+
+    pub fn try_transpile(&self, constraint: &Constraint) -> SmtLib2Result<String> {
+        self.take_error();
+        let term = self.transpile(constraint);
+        match self.take_error() {
+            Some(e) => Err(e),
+            None => Ok(term),
+        }
+    }
+
+    // A sentence as a bit vector term
+    pub fn try_term(&self, s: &Sentence) -> SmtLib2Result<String> {
+        self.bv_term(s)
+    }
+
+    // A complete, self-contained query: declarations, one assert per constraint and check-sat.
+    // Only the symbols of `constraints` are declared.
+    pub fn script(&self, constraints: &[Constraint]) -> SmtLib2Result<String> {
+        self.clear_declarations();
+        let asserts = constraints
+            .iter()
+            .map(|c| self.try_transpile(c))
+            .collect::<SmtLib2Result<Vec<_>>>()?;
+
+        let mut out = String::new();
+        for (name, sort) in self.declarations() {
+            writeln!(out, "{}", Self::declare_command(&name, sort)).unwrap();
+        }
+        for a in asserts {
+            writeln!(out, "(assert {})", a).unwrap();
+        }
+        writeln!(out, "(check-sat)").unwrap();
+        Ok(out)
+    }
+
+    fn bv_literal(&self, v: u128) -> String {
+        let v = if self.width >= 128 {
+            v
+        } else {
+            v & ((1_u128 << self.width) - 1)
+        };
+        format!("(_ bv{} {})", v, self.width)
+    }
+
+    // Arrays only coerce to the same array sort
+    fn coerce(&self, (term, sort): (String, Sort), to: Sort) -> SmtLib2Result<String> {
+        match (sort, to) {
+            (Sort::Bool, Sort::BitVec(_)) => Ok(format!(
+                "(ite {} {} {})",
+                term,
+                self.bv_literal(1),
+                self.bv_literal(0)
+            )),
+            (Sort::BitVec(_), Sort::Bool) => {
+                Ok(format!("(distinct {} {})", term, self.bv_literal(0)))
+            }
+            (Sort::Array(..), _) | (_, Sort::Array(..)) if sort != to => {
+                Err(SmtLib2Error::SortMismatch(format!("{} as {}", term, to)))
+            }
+            _ => Ok(term),
+        }
+    }
+
+    fn bool_term(&self, s: &Sentence) -> SmtLib2Result<String> {
+        self.coerce(self.term(s)?, Sort::Bool)
+    }
+
+    fn bv_term(&self, s: &Sentence) -> SmtLib2Result<String> {
+        self.coerce(self.term(s)?, Sort::BitVec(self.width))
+    }
+
+    fn array_term(&self, s: &Sentence) -> SmtLib2Result<String> {
+        match self.term(s)? {
+            (t, Sort::Array(..)) => Ok(t),
+            _ => Err(SmtLib2Error::SortMismatch(format!("{} is not an array", s))),
+        }
+    }
+
+    fn byte_array(&self) -> Sort {
+        Sort::Array(self.width, 8)
+    }
+
+    // The low byte of a stored value. Constants are byte literals, and masking with 255 is
+    // left to the extract.
+    fn byte_term(&self, s: &Sentence) -> SmtLib2Result<String> {
+        let byte = |s: &Sentence| match s {
+            Sentence::Basic(Value::Concrete(CSimpleVal::Number(n))) => Some(u128::from(n.clone())),
+            _ => None,
+        };
+        if let Some(b) = byte(s) {
+            return Ok(format!("#x{:02x}", b & 0xff));
+        }
+        let word = match s {
+            Sentence::BinOp {
+                a,
+                b,
+                op: BinOp::BitAnd,
+            } if byte(&b.0) == Some(0xff) => &a.0,
+            s => s,
+        };
+        Ok(Self::resize(self.bv_term(word)?, self.width, 8))
+    }
+
+    // Zero-extends or truncates a bit vector term
+    fn resize(term: String, from: u32, to: u32) -> String {
+        match from.cmp(&to) {
+            std::cmp::Ordering::Less => format!("((_ zero_extend {}) {})", to - from, term),
+            std::cmp::Ordering::Equal => term,
+            std::cmp::Ordering::Greater => format!("((_ extract {} 0) {})", to - 1, term),
+        }
+    }
+
+    fn term(&self, s: &Sentence) -> SmtLib2Result<(String, Sort)> {
+        let bv = Sort::BitVec(self.width);
+        let term = match s {
+            Sentence::Basic(v) => self.value_term(v)?,
+            Sentence::BinOp {
+                a,
+                b,
+                op: BinOp::Select,
+            } => {
+                let select = format!(
+                    "(select {} {})",
+                    self.array_term(&a.inner())?,
+                    self.bv_term(&b.inner())?
+                );
+                (Self::resize(select, 8, self.width), bv)
+            }
+            Sentence::BinOp { a, b, op } => {
+                let (a, b) = (a.inner(), b.inner());
+                let (ta, tb) = (self.term(&a)?, self.term(&b)?);
+                let both_bool = ta.1 == Sort::Bool && tb.1 == Sort::Bool;
+                // Bitwise ops on two booleans are their logical counterparts
+                let (name, operand_sort, result_sort) = match op {
+                    BinOp::Eq if both_bool => ("=", Sort::Bool, Sort::Bool),
+                    BinOp::Neq if both_bool => ("distinct", Sort::Bool, Sort::Bool),
+                    BinOp::Eq => ("=", bv, Sort::Bool),
+                    BinOp::Neq => ("distinct", bv, Sort::Bool),
+                    BinOp::BitAnd if both_bool => ("and", Sort::Bool, Sort::Bool),
+                    BinOp::BitOr if both_bool => ("or", Sort::Bool, Sort::Bool),
+                    BinOp::BitXor if both_bool => ("xor", Sort::Bool, Sort::Bool),
+                    op if is_comparison(op) => (bv_op_name(op).unwrap(), bv, Sort::Bool),
+                    op => (bv_op_name(op).unwrap(), bv, bv),
+                };
+                (
+                    format!(
+                        "({} {} {})",
+                        name,
+                        self.coerce(ta, operand_sort)?,
+                        self.coerce(tb, operand_sort)?
+                    ),
+                    result_sort,
+                )
+            }
+            Sentence::UnaryOp { a, op } => {
+                let ta = self.term(&a.inner())?;
+                match (op, ta.1) {
+                    (UnaryOp::Not, _) | (UnaryOp::BitNot, Sort::Bool) => (
+                        format!("(not {})", self.coerce(ta, Sort::Bool)?),
+                        Sort::Bool,
+                    ),
+                    (UnaryOp::BitNot, _) => (format!("(bvnot {})", self.coerce(ta, bv)?), bv),
+                }
+            }
+            Sentence::TernaryOp { a, b, c, op } => match op {
+                TernaryOp::Ite => {
+                    let cond = self.bool_term(&a.inner())?;
+                    let (tb, tc) = (self.term(&b.inner())?, self.term(&c.inner())?);
+                    let sort = if tb.1 == tc.1 { tb.1 } else { bv };
+                    (
+                        format!(
+                            "(ite {} {} {})",
+                            cond,
+                            self.coerce(tb, sort)?,
+                            self.coerce(tc, sort)?
+                        ),
+                        sort,
+                    )
+                }
+                TernaryOp::Store => (
+                    format!(
+                        "(store {} {} {})",
+                        self.array_term(&a.inner())?,
+                        self.bv_term(&b.inner())?,
+                        self.byte_term(&c.inner())?
+                    ),
+                    self.byte_array(),
+                ),
+            },
+        };
+        Ok(term)
+    }
+
+    fn value_term(&self, v: &Value) -> SmtLib2Result<(String, Sort)> {
+        let term = match v {
+            Value::Concrete(CSimpleVal::Boolean(b)) => {
+                let b: bool = b.clone().into();
+                (b.to_string(), Sort::Bool)
+            }
+            Value::Concrete(CSimpleVal::Number(n)) => {
+                (self.bv_literal(n.clone().into()), Sort::BitVec(self.width))
+            }
+            Value::Symbolic(SSimpleVal::SymbolicBool(id)) => {
+                self.declare(&id.0, Sort::Bool);
+                (quote_symbol(&id.0), Sort::Bool)
+            }
+            Value::Symbolic(SSimpleVal::SymbolicNumber(SNumber(id))) => {
+                self.declare(&id.0, Sort::BitVec(self.width));
+                (quote_symbol(&id.0), Sort::BitVec(self.width))
+            }
+            Value::Concrete(CSimpleVal::Vector(v)) => {
+                let zeros = format!("((as const {}) #x00)", self.byte_array());
+                let term = v.0.iter().enumerate().fold(zeros, |a, (i, b)| {
+                    format!("(store {} {} #x{:02x})", a, self.bv_literal(i as u128), b)
+                });
+                (term, self.byte_array())
+            }
+            Value::Symbolic(SSimpleVal::SymbolicVector(v)) => {
+                self.declare(&v.0 .0, self.byte_array());
+                (quote_symbol(&v.0 .0), self.byte_array())
+            }
+            Value::Either(_) => {
+                return Err(SmtLib2Error::Unsupported(
+                    "value that is neither concrete nor symbolic".to_string(),
+                ))
+            }
+        };
+        Ok(term)
+    }
+
+    // Symbols must be declared by the script itself
+    pub fn parse_script(&self, src: &str) -> SmtLib2Result<Vec<Constraint>> {
+        self.clear_declarations();
+        let mut constraints = vec![];
+
+        for cmd in SExpr::parse_all(src)? {
+            let items = match &cmd {
+                SExpr::List(items) if !items.is_empty() => items,
+                _ => return Err(SmtLib2Error::UnexpectedToken(cmd.to_string())),
+            };
+            match (items[0].as_atom(), &items[1..]) {
+                (
+                    Some(
+                        "set-logic" | "set-option" | "set-info" | "check-sat" | "get-model"
+                        | "exit",
+                    ),
+                    _,
+                ) => {}
+                (Some("declare-const"), [name, sort]) => {
+                    let name = name
+                        .as_atom()
+                        .ok_or_else(|| SmtLib2Error::UnexpectedToken(name.to_string()))?;
+                    self.declare(unquote_symbol(name), Self::parse_sort(sort)?);
+                }
+                (Some("declare-fun"), [name, SExpr::List(args), sort]) if args.is_empty() => {
+                    let name = name
+                        .as_atom()
+                        .ok_or_else(|| SmtLib2Error::UnexpectedToken(name.to_string()))?;
+                    self.declare(unquote_symbol(name), Self::parse_sort(sort)?);
+                }
+                (Some("assert"), [t]) => constraints.push(self.parse_constraint(t)?),
+                _ => return Err(SmtLib2Error::Unsupported(cmd.to_string())),
+            }
+        }
+
+        Ok(constraints)
+    }
+
+    pub fn parse_term(&self, src: &str) -> SmtLib2Result<Sentence> {
+        match SExpr::parse_all(src)?.as_slice() {
+            [t] => Ok(self.parse_sentence(t)?.0),
+            [] => Err(SmtLib2Error::UnexpectedEof),
+            [_, t, ..] => Err(SmtLib2Error::UnexpectedToken(t.to_string())),
+        }
+    }
+
+    pub fn parse_sort(s: &SExpr) -> SmtLib2Result<Sort> {
+        match s {
+            SExpr::Atom(a) if a == "Bool" => Ok(Sort::Bool),
+            SExpr::List(items) => match items.as_slice() {
+                [SExpr::Atom(u), SExpr::Atom(bv), SExpr::Atom(w)] if u == "_" && bv == "BitVec" => {
+                    let w = w
+                        .parse()
+                        .map_err(|_| SmtLib2Error::UnexpectedToken(w.clone()))?;
+                    Ok(Sort::BitVec(w))
+                }
+                [SExpr::Atom(a), i, e] if a == "Array" => {
+                    match (Self::parse_sort(i)?, Self::parse_sort(e)?) {
+                        (Sort::BitVec(i), Sort::BitVec(e)) => Ok(Sort::Array(i, e)),
+                        _ => Err(SmtLib2Error::Unsupported(format!("sort {}", s))),
+                    }
+                }
+                _ => Err(SmtLib2Error::Unsupported(format!("sort {}", s))),
+            },
+            _ => Err(SmtLib2Error::Unsupported(format!("sort {}", s))),
+        }
+    }
+
+    fn parse_constraint(&self, t: &SExpr) -> SmtLib2Result<Constraint> {
+        let compound = |t: &SExpr| -> SmtLib2Result<Node> {
+            Ok(Node::new_compound(self.parse_constraint(t)?))
+        };
+
+        if let SExpr::List(items) = t {
+            match (items.first().and_then(|h| h.as_atom()), &items[1..]) {
+                (Some("not"), [a]) => return Ok(Constraint::Not(compound(a)?)),
+                (Some(op @ ("and" | "or")), [first, rest @ ..]) if !rest.is_empty() => {
+                    let mut acc = self.parse_constraint(first)?;
+                    for r in rest {
+                        let r = self.parse_constraint(r)?;
+                        acc = if op == "and" { acc.and(r) } else { acc.or(r) };
+                    }
+                    return Ok(acc);
+                }
+                (Some("ite"), [c, a, b]) => {
+                    let (_, sa) = self.parse_sentence(a)?;
+                    if sa == Sort::Bool {
+                        return Ok(Constraint::Ite(
+                            Rc::new(self.parse_constraint(c)?),
+                            compound(a)?,
+                            compound(b)?,
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let (s, sort) = self.parse_sentence(t)?;
+        if sort != Sort::Bool {
+            return Err(SmtLib2Error::SortMismatch(t.to_string()));
+        }
+
+        let c = match s {
+            Sentence::Basic(Value::Concrete(CSimpleVal::Boolean(Bool::True))) => Constraint::True,
+            Sentence::Basic(Value::Concrete(CSimpleVal::Boolean(Bool::False))) => Constraint::False,
+            Sentence::BinOp {
+                ref a,
+                ref b,
+                ref op,
+            } if is_comparison(op) && !self.is_bool(a) => {
+                let (l, r) = (a.inner().as_ref().clone(), b.inner().as_ref().clone());
+                match op {
+                    BinOp::Eq => Constraint::eq(l, r),
+                    BinOp::Neq => Constraint::neq(l, r),
+                    BinOp::Lt => Constraint::lt(l, r),
+                    BinOp::Lte => Constraint::lte(l, r),
+                    BinOp::Gt => Constraint::gt(l, r),
+                    BinOp::Gte => Constraint::gte(l, r),
+                    _ => unreachable!(),
+                }
+            }
+            s => Constraint::Assert(Node::new_simple(s)),
+        };
+        Ok(c)
+    }
+
+    fn is_bool(&self, s: &Val<Sentence>) -> bool {
+        matches!(self.term(&s.inner()), Ok((_, Sort::Bool)))
+    }
+
+    fn parse_sentence(&self, t: &SExpr) -> SmtLib2Result<(Sentence, Sort)> {
+        let bv = |w| Sort::BitVec(w);
+        match t {
+            SExpr::Atom(a) => match a.as_str() {
+                "true" => Ok((
+                    Sentence::Basic(Value::Concrete(CSimpleVal::Boolean(Bool::True))),
+                    Sort::Bool,
+                )),
+                "false" => Ok((
+                    Sentence::Basic(Value::Concrete(CSimpleVal::Boolean(Bool::False))),
+                    Sort::Bool,
+                )),
+                a if a.starts_with("#x") || a.starts_with("#b") => {
+                    let (radix, bits_per_digit) =
+                        if a.starts_with("#x") { (16, 4) } else { (2, 1) };
+                    let digits = &a[2..];
+                    let width = digits.len() as u32 * bits_per_digit;
+                    if width > 128 {
+                        return Err(SmtLib2Error::Unsupported(format!("literal {}", a)));
+                    }
+                    let v = u128::from_str_radix(digits, radix)
+                        .map_err(|_| SmtLib2Error::UnexpectedToken(a.to_string()))?;
+                    Ok((number(v, width), bv(width)))
+                }
+                a => {
+                    let name = unquote_symbol(a);
+                    let sort = self
+                        .sort_of(name)
+                        .ok_or_else(|| SmtLib2Error::UndeclaredSymbol(name.to_string()))?;
+                    let v = match sort {
+                        Sort::Bool => SSimpleVal::SymbolicBool(SymbolId(name.to_string(), None)),
+                        Sort::BitVec(_) => {
+                            SSimpleVal::SymbolicNumber(SNumber(SymbolId(name.to_string(), None)))
+                        }
+                        Sort::Array(..) => SSimpleVal::SymbolicVector(SymbolicVecc(SymbolId(
+                            name.to_string(),
+                            None,
+                        ))),
+                    };
+                    Ok((Sentence::Basic(Value::Symbolic(v)), sort))
+                }
+            },
+            SExpr::List(items) => {
+                if let [SExpr::List(head), arg] = items.as_slice() {
+                    return self.parse_indexed(head, arg, t);
+                }
+                let head = items
+                    .first()
+                    .and_then(|h| h.as_atom())
+                    .ok_or_else(|| SmtLib2Error::Unsupported(t.to_string()))?;
+                let args = &items[1..];
+
+                if head == "_" {
+                    return match args {
+                        [SExpr::Atom(v), SExpr::Atom(w)] if v.starts_with("bv") => {
+                            let v = v[2..]
+                                .parse::<u128>()
+                                .map_err(|_| SmtLib2Error::UnexpectedToken(v.clone()))?;
+                            let w = w
+                                .parse::<u32>()
+                                .map_err(|_| SmtLib2Error::UnexpectedToken(w.clone()))?;
+                            Ok((number(v, w), bv(w)))
+                        }
+                        _ => Err(SmtLib2Error::Unsupported(t.to_string())),
+                    };
+                }
+
+                let args = args
+                    .iter()
+                    .map(|a| self.parse_sentence(a))
+                    .collect::<SmtLib2Result<Vec<_>>>()?;
+                let mismatch = || SmtLib2Error::SortMismatch(t.to_string());
+
+                match (head, args.as_slice()) {
+                    ("not", [(a, Sort::Bool)]) => Ok((
+                        Sentence::UnaryOp {
+                            a: Val::new(a.clone()),
+                            op: UnaryOp::Not,
+                        },
+                        Sort::Bool,
+                    )),
+                    ("bvnot", [(a, s @ Sort::BitVec(_))]) => Ok((
+                        Sentence::UnaryOp {
+                            a: Val::new(a.clone()),
+                            op: UnaryOp::BitNot,
+                        },
+                        *s,
+                    )),
+                    ("and" | "or" | "xor", [first, rest @ ..]) if !rest.is_empty() => {
+                        let op = match head {
+                            "and" => BinOp::BitAnd,
+                            "or" => BinOp::BitOr,
+                            _ => BinOp::BitXor,
+                        };
+                        let mut acc = first.clone();
+                        for r in rest {
+                            if acc.1 != Sort::Bool || r.1 != Sort::Bool {
+                                return Err(mismatch());
+                            }
+                            acc = (bin_op(acc.0, r.0.clone(), op.clone()), Sort::Bool);
+                        }
+                        Ok(acc)
+                    }
+                    ("=" | "distinct", [(a, sa), (b, sb)]) => {
+                        if sa != sb {
+                            return Err(mismatch());
+                        }
+                        let op = if head == "=" { BinOp::Eq } else { BinOp::Neq };
+                        Ok((bin_op(a.clone(), b.clone(), op), Sort::Bool))
+                    }
+                    ("ite", [(c, Sort::Bool), (a, sa), (b, sb)]) => {
+                        if sa != sb {
+                            return Err(mismatch());
+                        }
+                        Ok((
+                            Sentence::TernaryOp {
+                                a: Val::new(c.clone()),
+                                b: Val::new(a.clone()),
+                                c: Val::new(b.clone()),
+                                op: TernaryOp::Ite,
+                            },
+                            *sa,
+                        ))
+                    }
+                    (name, [(a, sa @ Sort::BitVec(_)), (b, sb)])
+                        if bv_op_from_name(name).is_some() =>
+                    {
+                        if sa != sb {
+                            return Err(mismatch());
+                        }
+                        let op = bv_op_from_name(name).unwrap();
+                        let sort = if is_comparison(&op) { Sort::Bool } else { *sa };
+                        Ok((bin_op(a.clone(), b.clone(), op), sort))
+                    }
+                    ("select", [(a, Sort::Array(iw, ew)), (i, Sort::BitVec(w))]) if iw == w => {
+                        Ok((
+                            bin_op(a.clone(), i.clone(), BinOp::Select),
+                            Sort::BitVec(*ew),
+                        ))
+                    }
+                    (
+                        "store",
+                        [(a, s @ Sort::Array(iw, ew)), (i, Sort::BitVec(w)), (v, Sort::BitVec(vw))],
+                    ) if iw == w && ew == vw => Ok((
+                        Sentence::TernaryOp {
+                            a: Val::new(a.clone()),
+                            b: Val::new(i.clone()),
+                            c: Val::new(v.clone()),
+                            op: TernaryOp::Store,
+                        },
+                        *s,
+                    )),
+                    _ => Err(SmtLib2Error::Unsupported(t.to_string())),
+                }
+            }
+        }
+    }
+}
+
+impl SmtLib2 {
+    // `((_ zero_extend k) t)`, `((_ extract h 0) t)` and `((as const (Array ..)) #x00)`.
+    // Numbers don't carry a width, so extending keeps the term and extracting the low bits
+    // masks it; a constant array of zeros is the empty concrete vector.
+    fn parse_indexed(
+        &self,
+        head: &[SExpr],
+        arg: &SExpr,
+        t: &SExpr,
+    ) -> SmtLib2Result<(Sentence, Sort)> {
+        let unsupported = || SmtLib2Error::Unsupported(t.to_string());
+        let int = |e: &SExpr| -> SmtLib2Result<u32> {
+            e.as_atom()
+                .and_then(|a| a.parse().ok())
+                .ok_or_else(|| SmtLib2Error::UnexpectedToken(e.to_string()))
+        };
+
+        match head {
+            [SExpr::Atom(u), SExpr::Atom(f), k] if u == "_" && f == "zero_extend" => {
+                match self.parse_sentence(arg)? {
+                    (a, Sort::BitVec(w)) => Ok((a, Sort::BitVec(w + int(k)?))),
+                    _ => Err(SmtLib2Error::SortMismatch(t.to_string())),
+                }
+            }
+            [SExpr::Atom(u), SExpr::Atom(f), h, l] if u == "_" && f == "extract" => {
+                let (h, l) = (int(h)?, int(l)?);
+                match self.parse_sentence(arg)? {
+                    (a, Sort::BitVec(w)) if l == 0 && h < w && h < 128 => {
+                        let mask = u128::MAX >> (127 - h);
+                        Ok((
+                            bin_op(a, number(mask, w), BinOp::BitAnd),
+                            Sort::BitVec(h + 1),
+                        ))
+                    }
+                    (_, Sort::BitVec(_)) => Err(unsupported()),
+                    _ => Err(SmtLib2Error::SortMismatch(t.to_string())),
+                }
+            }
+            [SExpr::Atom(a), SExpr::Atom(c), sort] if a == "as" && c == "const" => {
+                let sort = Self::parse_sort(sort)?;
+                match (sort, self.parse_sentence(arg)?) {
+                    (Sort::Array(_, e), (v, Sort::BitVec(w))) if e == w => {
+                        if v != number(0, w) {
+                            return Err(unsupported());
+                        }
+                        Ok((
+                            Sentence::Basic(Value::Concrete(CSimpleVal::Vector(Vecc(vec![])))),
+                            sort,
+                        ))
+                    }
+                    _ => Err(SmtLib2Error::SortMismatch(t.to_string())),
+                }
+            }
+            _ => Err(unsupported()),
+        }
+    }
+}
+
+impl SmtLib2 {
+    // Keeps the first error for `take_error` and falls back to `default`
+    fn recover<T>(&self, res: SmtLib2Result<T>, default: impl FnOnce() -> T) -> T {
+        res.unwrap_or_else(|e| {
+            self.error.borrow_mut().get_or_insert(e);
+            default()
+        })
+    }
+}
+
+impl Transpile<String, String> for SmtLib2 {
+    fn val_to_ground_type(&self, v: Sentence) -> String {
+        self.recover(self.bv_term(&v), || self.bv_literal(0))
+    }
+
+    fn ground_type_to_val(&self, g: String) -> Sentence {
+        self.recover(self.parse_term(&g), || number(0, self.width))
+    }
+
+    fn val_to_bool(&self, v: Sentence) -> String {
+        self.recover(self.bool_term(&v), || "false".to_string())
+    }
+
+    // The `(assert ...)` command is emitted per top-level constraint by `script`
+    fn assert(&self, c: String) -> String {
+        c
+    }
+
+    fn and(&self, l: String, r: String) -> String {
+        format!("(and {} {})", l, r)
+    }
+
+    fn not(&self, c: String) -> String {
+        format!("(not {})", c)
+    }
+
+    fn or(&self, l: String, r: String) -> String {
+        format!("(or {} {})", l, r)
+    }
+
+    fn gt(&self, l: String, r: String) -> String {
+        format!("(bvugt {} {})", l, r)
+    }
+
+    fn lt(&self, l: String, r: String) -> String {
+        format!("(bvult {} {})", l, r)
+    }
+
+    fn lte(&self, l: String, r: String) -> String {
+        format!("(bvule {} {})", l, r)
+    }
+
+    fn gte(&self, l: String, r: String) -> String {
+        format!("(bvuge {} {})", l, r)
+    }
+
+    fn eq(&self, l: String, r: String) -> String {
+        format!("(= {} {})", l, r)
+    }
+
+    fn neq(&self, l: String, r: String) -> String {
+        format!("(distinct {} {})", l, r)
+    }
+
+    fn ite(&self, c: String, t: String, e: String) -> String {
+        format!("(ite {} {} {})", c, t, e)
+    }
+
+    fn true_(&self) -> String {
+        "true".to_string()
+    }
+
+    fn false_(&self) -> String {
+        "false".to_string()
+    }
+}
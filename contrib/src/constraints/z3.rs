@@ -1,228 +1,760 @@
+// Solver backend on top of the z3 library.
+//
+// Constraints are encoded like the SMT-LIB2 backend encodes them: numbers are bit vectors of
+// the configured width (64 bits by default) and booleans are coerced to and from bit vectors
+// where an operator needs them. Byte arrays (`BinOp::Select` and `TernaryOp::Store`) are z3
+// arrays from bit vectors of the configured width to 8-bit vectors, as in the SMT-LIB2 backend.
+//
+// Every encoded assertion is also kept per assertion scope, so that `optimize` can replay them
+// into a z3 `Optimize` and leave the solver itself untouched.
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::thread;
+
 use symbolic_stack_machines_core::constraint::*;
-use symbolic_stack_machines_core::value::Number as AbstractInt;
-use z3::ast::Int;
-use z3::{Config, Context, Model, Solver as Z3InnerSolver};
+use symbolic_stack_machines_core::value::*;
+use thiserror::Error;
+use z3::ast::{self, Ast};
+use z3::{Context, Params};
+
+use super::process::{unknown_reason, LABEL_PREFIX};
+use super::smtlib2::{Sort, DEFAULT_WIDTH};
+
+#[derive(Error, Debug, Clone)]
+pub enum Z3Error {
+    #[error("Sort mismatch in {0}")]
+    SortMismatch(String),
+    #[error("Unsupported {0}")]
+    Unsupported(String),
+    #[error("Solver error: {0}")]
+    Solver(String),
+}
+
+pub type Z3Result<T> = Result<T, Z3Error>;
 
-pub type ValInt = AbstractInt;
+#[derive(Clone, Debug)]
+enum Term<'ctx> {
+    Bool(ast::Bool<'ctx>),
+    BV(ast::BV<'ctx>),
+    Array(ast::Array<'ctx>),
+}
+
+impl<'ctx> Term<'ctx> {
+    fn sort(&self) -> z3::Sort<'ctx> {
+        match self {
+            Term::Bool(b) => b.get_sort(),
+            Term::BV(bv) => bv.get_sort(),
+            Term::Array(a) => a.get_sort(),
+        }
+    }
+}
+
+impl std::fmt::Display for Term<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Term::Bool(b) => write!(f, "{}", b),
+            Term::BV(bv) => write!(f, "{}", bv),
+            Term::Array(a) => write!(f, "{}", a),
+        }
+    }
+}
 
 pub struct Z3SolverBuilder {
-    ctx: Option<Context>,
+    width: u32,
+    limits: SolverLimits,
+}
+
+impl Default for Z3SolverBuilder {
+    fn default() -> Self {
+        Self {
+            width: DEFAULT_WIDTH,
+            limits: SolverLimits::default(),
+        }
+    }
 }
 
-impl<'a> Z3SolverBuilder {
+impl Z3SolverBuilder {
     pub fn new() -> Self {
-        Self { ctx: None }
+        Self::default()
     }
 
-    pub fn ctx(mut self, cfg: Option<Config>) -> Self {
-        self.ctx = Some(Context::new(&cfg.unwrap_or_default()));
+    pub fn width(mut self, width: u32) -> Self {
+        self.width = width;
         self
     }
 
-    pub fn build(self) -> Z3Solver<'a> {
-        Z3Solver {
-            inner: None,
-            constraints: vec![],
-            ctx: self.ctx.unwrap(),
-        }
+    pub fn limits(mut self, limits: SolverLimits) -> Self {
+        self.limits = limits;
+        self
     }
+
+    pub fn build(self, ctx: &Context) -> Z3Solver<'_> {
+        let mut solver = Z3Solver {
+            ctx,
+            solver: z3::Solver::new(ctx),
+            width: self.width,
+            limits: SolverLimits::default(),
+            scopes: vec![AssertionScope::default()],
+            symbols: RefCell::new(BTreeMap::new()),
+            fresh: RefCell::new(vec![]),
+            error: RefCell::new(None),
+            unknown: RefCell::new(None),
+            core: RefCell::new(None),
+        };
+        solver.set_limits(self.limits);
+        solver
+    }
+}
+
+#[derive(Default)]
+struct AssertionScope<'ctx> {
+    asserted: Vec<ast::Bool<'ctx>>,
+    // Symbols first used in this scope
+    declared: Vec<String>,
+    labelled: bool,
+    // A constraint asserted in this scope that couldn't be encoded
+    invalid: Option<Z3Error>,
 }
-pub struct Z3Solver<'a> {
-    inner: Option<Z3InnerSolver<'a>>,
-    constraints: Vec<Constraint>,
-    ctx: Context,
+
+pub struct Z3Solver<'ctx> {
+    ctx: &'ctx Context,
+    solver: z3::Solver<'ctx>,
+    width: u32,
+    limits: SolverLimits,
+    scopes: Vec<AssertionScope<'ctx>>,
+    // The constant of every symbol used in `scopes`, with the names added since they were
+    // last assigned to a scope
+    symbols: RefCell<BTreeMap<String, Term<'ctx>>>,
+    fresh: RefCell<Vec<String>>,
+    // The first term `Transpile` couldn't encode, see `take_error`
+    error: RefCell<Option<Z3Error>>,
+    unknown: RefCell<Option<UnknownReason>>,
+    core: RefCell<Option<Vec<String>>>,
 }
 
-impl<'a> Z3Solver<'a> {
-    pub fn inner(&self) -> &Z3InnerSolver<'a> {
-        assert!(self.inner.is_some());
-        self.inner.as_ref().unwrap()
+impl<'ctx> Z3Solver<'ctx> {
+    pub fn ctx(&self) -> &'ctx Context {
+        self.ctx
+    }
+
+    pub fn inner(&self) -> &z3::Solver<'ctx> {
+        &self.solver
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
     }
-    pub fn get_ctx(&self) -> &'a Context {
-        self.inner().get_context()
+
+    pub fn limits(&self) -> &SolverLimits {
+        &self.limits
+    }
+
+    // Takes effect from the next query. z3 has no per-solver memory limit, so `memory_mb`
+    // is ignored, and `optimize` only enforces the timeout.
+    pub fn set_limits(&mut self, limits: SolverLimits) {
+        let mut params = Params::new(self.ctx);
+        params.set_bool("core.minimize", true);
+        if let Some(t) = limits.timeout {
+            params.set_u32("timeout", t.as_millis().min(u32::MAX.into()) as u32);
+        }
+        if let Some(r) = limits.rlimit {
+            params.set_u32("rlimit", r.min(u32::MAX.into()) as u32);
+        }
+        self.solver.set_params(&params);
+        self.limits = limits;
     }
 
-    pub fn get_constraints(&self) -> &Vec<Constraint> {
-        &self.constraints
+    // A constraint that can't be encoded is not asserted. Every query fails with the
+    // encoding error instead, until the scope it was asserted in is popped.
+    pub fn assert_constraint(&mut self, constraint: &Constraint) -> Z3Result<()> {
+        let term = self.encode(constraint)?;
+        self.solver.assert(&term);
+        self.scopes.last_mut().unwrap().asserted.push(term);
+        Ok(())
     }
 
-    pub fn set_solver(&'a mut self) {
-        self.inner = Some(Z3InnerSolver::new(&self.ctx));
+    // Asserts `constraint` tracked by a named constant, reported by `unsat_core` when it is
+    // part of a contradiction
+    pub fn assert_labelled(&mut self, label: &str, constraint: &Constraint) -> Z3Result<()> {
+        let term = self.encode(constraint)?;
+        let name = ast::Bool::new_const(self.ctx, format!("{}{}", LABEL_PREFIX, label));
+        self.solver.assert_and_track(&term, &name);
+        let scope = self.scopes.last_mut().unwrap();
+        scope.asserted.push(term);
+        scope.labelled = true;
+        Ok(())
+    }
+
+    fn encode(&mut self, constraint: &Constraint) -> Z3Result<ast::Bool<'ctx>> {
+        let res = self.try_transpile(constraint);
+        let scope = self.scopes.last_mut().unwrap();
+        scope.declared.append(self.fresh.get_mut());
+        res.inspect_err(|e| {
+            scope.invalid.get_or_insert_with(|| e.clone());
+        })
+    }
+
+    // `Transpile` can't fail, so a term it can't encode is replaced and the first such error
+    // is kept until taken here
+    pub fn take_error(&self) -> Option<Z3Error> {
+        self.error.borrow_mut().take()
+    }
+
+    pub fn try_transpile(&self, constraint: &Constraint) -> Z3Result<ast::Bool<'ctx>> {
+        self.take_error();
+        let term = self.transpile(constraint);
+        match self.take_error() {
+            Some(e) => Err(e),
+            None => Ok(term),
+        }
+    }
+
+    // A sentence as a bit vector term
+    pub fn try_term(&self, s: &Sentence) -> Z3Result<ast::BV<'ctx>> {
+        self.bv_term(s)
+    }
+
+    // Labels of the assertions behind the most recent `Unsat` answer, if any were labelled
+    pub fn unsat_core(&self) -> Option<Vec<String>> {
+        self.core.borrow().clone()
+    }
+
+    pub fn check_sat(&self) -> Z3Result<SatResult<Model>> {
+        self.check_valid()?;
+        match self.solver.check() {
+            z3::SatResult::Sat => {
+                let model = self.solver.get_model().ok_or_else(Self::no_model)?;
+                Ok(SatResult::Sat(self.model(&model)))
+            }
+            z3::SatResult::Unsat => {
+                if self.scopes.iter().any(|s| s.labelled) {
+                    let core = self.solver.get_unsat_core();
+                    *self.core.borrow_mut() = Some(
+                        core.iter()
+                            .filter_map(|b| {
+                                b.decl().name().strip_prefix(LABEL_PREFIX).map(String::from)
+                            })
+                            .collect(),
+                    );
+                }
+                Ok(SatResult::Unsat)
+            }
+            z3::SatResult::Unknown => {
+                let reason = self.solver.get_reason_unknown().unwrap_or_default();
+                *self.unknown.borrow_mut() = Some(unknown_reason(reason));
+                Ok(SatResult::Unknown)
+            }
+        }
+    }
+
+    // Replays the assertion stack into a z3 `Optimize`. Symbols only the objective uses are
+    // part of the model, but are not kept as declared.
+    pub fn optimize(&self, objective: &Objective) -> Z3Result<SatResult<Optimum<Model>>> {
+        self.check_valid()?;
+        let term = self.try_term(objective.term());
+        let fresh = self.fresh.take();
+        let res = term.and_then(|term| self.optimize_term(objective, &term));
+        let mut symbols = self.symbols.borrow_mut();
+        for name in fresh {
+            symbols.remove(&name);
+        }
+        res
+    }
+
+    fn optimize_term(
+        &self,
+        objective: &Objective,
+        term: &ast::BV<'ctx>,
+    ) -> Z3Result<SatResult<Optimum<Model>>> {
+        let opt = z3::Optimize::new(self.ctx);
+        for t in self.scopes.iter().flat_map(|s| &s.asserted) {
+            opt.assert(t);
+        }
+        match objective {
+            Objective::Minimize(_) => opt.minimize(term),
+            Objective::Maximize(_) => opt.maximize(term),
+        }
+
+        match self.within_timeout(|| opt.check(&[])) {
+            z3::SatResult::Sat => {
+                let model = opt.get_model().ok_or_else(Self::no_model)?;
+                let value = model
+                    .eval(term, true)
+                    .and_then(|v| bv_value(&v))
+                    .ok_or_else(|| Z3Error::Solver(format!("no value for {}", term)))?;
+                Ok(SatResult::Sat(Optimum {
+                    model: self.model(&model),
+                    value: CNumber::from_u128_with_width(value, self.width),
+                }))
+            }
+            z3::SatResult::Unsat => Ok(SatResult::Unsat),
+            z3::SatResult::Unknown => {
+                let reason = opt.get_reason_unknown().unwrap_or_default();
+                *self.unknown.borrow_mut() = Some(unknown_reason(reason));
+                Ok(SatResult::Unknown)
+            }
+        }
+    }
+
+    // `Optimize` takes no parameters, so the timeout interrupts the context instead
+    fn within_timeout<T>(&self, f: impl FnOnce() -> T) -> T {
+        let timeout = match self.limits.timeout {
+            Some(t) => t,
+            None => return f(),
+        };
+        let handle = self.ctx.handle();
+        let (done, finished) = channel::<()>();
+        thread::scope(|s| {
+            s.spawn(move || {
+                if let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(timeout) {
+                    handle.interrupt();
+                }
+            });
+            let res = f();
+            let _ = done.send(());
+            res
+        })
+    }
+
+    fn check_valid(&self) -> Z3Result<()> {
+        match self.scopes.iter().find_map(|s| s.invalid.clone()) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn no_model() -> Z3Error {
+        Z3Error::Solver("no model available".to_string())
+    }
+
+    // Values of every declared symbol, unconstrained ones included. Arrays, such as symbolic
+    // memory, are skipped.
+    fn model(&self, model: &z3::Model<'ctx>) -> Model {
+        let mut res = Model::new();
+        for (name, term) in self.symbols.borrow().iter() {
+            let value = match term {
+                Term::Bool(b) => model
+                    .eval(b, true)
+                    .and_then(|b| b.as_bool())
+                    .map(|b| CSimpleVal::Boolean(b.into())),
+                Term::BV(bv) => model
+                    .eval(bv, true)
+                    .and_then(|v| bv_value(&v))
+                    .map(|v| CSimpleVal::Number(CNumber::from_u128_with_width(v, self.width))),
+                Term::Array(_) => None,
+            };
+            if let Some(v) = value {
+                res.insert(name, v);
+            }
+        }
+        res
+    }
+
+    fn bv_literal(&self, v: u128) -> ast::BV<'ctx> {
+        if self.width <= 64 {
+            return ast::BV::from_u64(self.ctx, v as u64, self.width);
+        }
+        let high = ast::BV::from_u64(self.ctx, (v >> 64) as u64, self.width - 64);
+        high.concat(&ast::BV::from_u64(self.ctx, v as u64, 64))
+    }
+
+    fn number(&self, v: u128) -> Sentence {
+        Sentence::Basic(Value::Concrete(CSimpleVal::Number(
+            CNumber::from_u128_with_width(v, self.width),
+        )))
+    }
+
+    fn z3_sort(&self, sort: Sort) -> z3::Sort<'ctx> {
+        match sort {
+            Sort::Bool => z3::Sort::bool(self.ctx),
+            Sort::BitVec(w) => z3::Sort::bitvector(self.ctx, w),
+            Sort::Array(i, e) => z3::Sort::array(
+                self.ctx,
+                &z3::Sort::bitvector(self.ctx, i),
+                &z3::Sort::bitvector(self.ctx, e),
+            ),
+        }
+    }
+
+    // The constant of `name`, with the sort of its first use
+    fn symbol(&self, name: &str, sort: Sort) -> Z3Result<Term<'ctx>> {
+        let mut symbols = self.symbols.borrow_mut();
+        if let Some(t) = symbols.get(name) {
+            if t.sort() != self.z3_sort(sort) {
+                return Err(Z3Error::SortMismatch(format!("{} as {}", name, sort)));
+            }
+            return Ok(t.clone());
+        }
+        let term = match sort {
+            Sort::Bool => Term::Bool(ast::Bool::new_const(self.ctx, name)),
+            Sort::BitVec(w) => Term::BV(ast::BV::new_const(self.ctx, name, w)),
+            Sort::Array(i, e) => Term::Array(ast::Array::new_const(
+                self.ctx,
+                name,
+                &z3::Sort::bitvector(self.ctx, i),
+                &z3::Sort::bitvector(self.ctx, e),
+            )),
+        };
+        symbols.insert(name.to_string(), term.clone());
+        self.fresh.borrow_mut().push(name.to_string());
+        Ok(term)
+    }
+
+    // Arrays don't coerce to anything
+    fn coerce_bool(&self, term: Term<'ctx>) -> Z3Result<ast::Bool<'ctx>> {
+        match term {
+            Term::Bool(b) => Ok(b),
+            Term::BV(bv) => Ok(bv._eq(&self.bv_literal(0)).not()),
+            Term::Array(a) => Err(Z3Error::SortMismatch(format!("{} as {}", a, Sort::Bool))),
+        }
+    }
+
+    fn coerce_bv(&self, term: Term<'ctx>) -> Z3Result<ast::BV<'ctx>> {
+        match term {
+            Term::Bool(b) => Ok(b.ite(&self.bv_literal(1), &self.bv_literal(0))),
+            Term::BV(bv) => Ok(bv),
+            Term::Array(a) => Err(Z3Error::SortMismatch(format!(
+                "{} as {}",
+                a,
+                Sort::BitVec(self.width)
+            ))),
+        }
+    }
+
+    fn array_term(&self, s: &Sentence) -> Z3Result<ast::Array<'ctx>> {
+        match self.term(s)? {
+            Term::Array(a) => Ok(a),
+            _ => Err(Z3Error::SortMismatch(format!("{} is not an array", s))),
+        }
+    }
+
+    fn byte_array(&self) -> Sort {
+        Sort::Array(self.width, 8)
+    }
+
+    fn byte(&self, b: u8) -> ast::BV<'ctx> {
+        ast::BV::from_u64(self.ctx, b.into(), 8)
+    }
+
+    // The low byte of a stored value. Constants are byte literals, and masking with 255 is
+    // left to the extract.
+    fn byte_term(&self, s: &Sentence) -> Z3Result<ast::BV<'ctx>> {
+        let byte = |s: &Sentence| match s {
+            Sentence::Basic(Value::Concrete(CSimpleVal::Number(n))) => Some(u128::from(n.clone())),
+            _ => None,
+        };
+        if let Some(b) = byte(s) {
+            return Ok(self.byte(b as u8));
+        }
+        let word = match s {
+            Sentence::BinOp {
+                a,
+                b,
+                op: BinOp::BitAnd,
+            } if byte(&b.0) == Some(0xff) => &a.0,
+            s => s,
+        };
+        Ok(resize(&self.bv_term(word)?, 8))
+    }
+
+    fn bool_term(&self, s: &Sentence) -> Z3Result<ast::Bool<'ctx>> {
+        self.coerce_bool(self.term(s)?)
+    }
+
+    fn bv_term(&self, s: &Sentence) -> Z3Result<ast::BV<'ctx>> {
+        self.coerce_bv(self.term(s)?)
+    }
+
+    fn term(&self, s: &Sentence) -> Z3Result<Term<'ctx>> {
+        let term = match s {
+            Sentence::Basic(v) => self.value_term(v)?,
+            Sentence::BinOp {
+                a,
+                b,
+                op: BinOp::Select,
+            } => {
+                let select = self
+                    .array_term(&a.inner())?
+                    .select(&self.bv_term(&b.inner())?);
+                let byte = select
+                    .as_bv()
+                    .ok_or_else(|| Z3Error::SortMismatch(format!("{} as a byte", select)))?;
+                Term::BV(resize(&byte, self.width))
+            }
+            Sentence::TernaryOp {
+                a,
+                b,
+                c,
+                op: TernaryOp::Store,
+            } => Term::Array(
+                self.array_term(&a.inner())?
+                    .store(&self.bv_term(&b.inner())?, &self.byte_term(&c.inner())?),
+            ),
+            Sentence::BinOp { a, b, op } => {
+                let (ta, tb) = (self.term(&a.inner())?, self.term(&b.inner())?);
+                match (ta, tb) {
+                    // Bitwise ops on two booleans are their logical counterparts
+                    (Term::Bool(a), Term::Bool(b))
+                        if matches!(
+                            op,
+                            BinOp::Eq | BinOp::Neq | BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor
+                        ) =>
+                    {
+                        Term::Bool(match op {
+                            BinOp::Eq => a._eq(&b),
+                            BinOp::Neq => a._eq(&b).not(),
+                            BinOp::BitAnd => ast::Bool::and(self.ctx, &[&a, &b]),
+                            BinOp::BitOr => ast::Bool::or(self.ctx, &[&a, &b]),
+                            _ => a.xor(&b),
+                        })
+                    }
+                    (ta, tb) => bv_op(op, &self.coerce_bv(ta)?, &self.coerce_bv(tb)?),
+                }
+            }
+            Sentence::UnaryOp { a, op } => match (op, self.term(&a.inner())?) {
+                (UnaryOp::BitNot, Term::BV(bv)) => Term::BV(bv.bvnot()),
+                (_, t) => Term::Bool(self.coerce_bool(t)?.not()),
+            },
+            Sentence::TernaryOp { a, b, c, .. } => {
+                let cond = self.bool_term(&a.inner())?;
+                match (self.term(&b.inner())?, self.term(&c.inner())?) {
+                    (Term::Bool(t), Term::Bool(e)) => Term::Bool(cond.ite(&t, &e)),
+                    (Term::Array(t), Term::Array(e)) => Term::Array(cond.ite(&t, &e)),
+                    (t, e) => Term::BV(cond.ite(&self.coerce_bv(t)?, &self.coerce_bv(e)?)),
+                }
+            }
+        };
+        Ok(term)
+    }
+
+    fn value_term(&self, v: &Value) -> Z3Result<Term<'ctx>> {
+        let term = match v {
+            Value::Concrete(CSimpleVal::Boolean(b)) => {
+                Term::Bool(ast::Bool::from_bool(self.ctx, b.clone().into()))
+            }
+            Value::Concrete(CSimpleVal::Number(n)) => Term::BV(self.bv_literal(n.clone().into())),
+            Value::Symbolic(SSimpleVal::SymbolicBool(id)) => self.symbol(&id.0, Sort::Bool)?,
+            Value::Symbolic(SSimpleVal::SymbolicNumber(SNumber(id))) => {
+                self.symbol(&id.0, Sort::BitVec(self.width))?
+            }
+            Value::Concrete(CSimpleVal::Vector(v)) => {
+                let zeros = ast::Array::const_array(
+                    self.ctx,
+                    &z3::Sort::bitvector(self.ctx, self.width),
+                    &self.byte(0),
+                );
+                let array = v.0.iter().enumerate().fold(zeros, |a, (i, b)| {
+                    a.store(&self.bv_literal(i as u128), &self.byte(*b))
+                });
+                Term::Array(array)
+            }
+            Value::Symbolic(SSimpleVal::SymbolicVector(v)) => {
+                self.symbol(&v.0 .0, self.byte_array())?
+            }
+            Value::Either(_) => {
+                return Err(Z3Error::Unsupported(
+                    "value that is neither concrete nor symbolic".to_string(),
+                ))
+            }
+        };
+        Ok(term)
+    }
+
+    fn recover<T>(&self, res: Z3Result<T>, default: impl FnOnce() -> T) -> T {
+        res.unwrap_or_else(|e| {
+            self.error.borrow_mut().get_or_insert(e);
+            default()
+        })
     }
 }
 
-pub fn z3_int<'a>(i: u64, ctxt: &'a Context) -> z3::ast::Int<'a> {
-    Int::from_u64(ctxt, i)
+fn bv_op<'ctx>(op: &BinOp, a: &ast::BV<'ctx>, b: &ast::BV<'ctx>) -> Term<'ctx> {
+    match op {
+        BinOp::Plus => Term::BV(a.bvadd(b)),
+        BinOp::Minus => Term::BV(a.bvsub(b)),
+        BinOp::Mul => Term::BV(a.bvmul(b)),
+        BinOp::Div => Term::BV(a.bvudiv(b)),
+        BinOp::Mod => Term::BV(a.bvurem(b)),
+        BinOp::BitOr => Term::BV(a.bvor(b)),
+        BinOp::BitAnd => Term::BV(a.bvand(b)),
+        BinOp::BitXor => Term::BV(a.bvxor(b)),
+        BinOp::LShift => Term::BV(a.bvshl(b)),
+        BinOp::RShift => Term::BV(a.bvlshr(b)),
+        BinOp::Lt => Term::Bool(a.bvult(b)),
+        BinOp::Lte => Term::Bool(a.bvule(b)),
+        BinOp::Gt => Term::Bool(a.bvugt(b)),
+        BinOp::Gte => Term::Bool(a.bvuge(b)),
+        BinOp::Eq => Term::Bool(a._eq(b)),
+        BinOp::Neq => Term::Bool(a._eq(b).not()),
+        BinOp::Select => unreachable!("selects are encoded by `term`"),
+    }
 }
 
-pub fn z3_int_var<'a>(i: &str, ctxt: &'a Context) -> z3::ast::Int<'a> {
-    Int::new_const(ctxt, i)
+// Zero-extends or truncates a bit vector
+fn resize<'ctx>(bv: &ast::BV<'ctx>, to: u32) -> ast::BV<'ctx> {
+    let from = bv.get_size();
+    match from.cmp(&to) {
+        std::cmp::Ordering::Less => bv.zero_ext(to - from),
+        std::cmp::Ordering::Equal => bv.clone(),
+        std::cmp::Ordering::Greater => bv.extract(to - 1, 0),
+    }
 }
 
-impl<'a> Constrained for Z3Solver<'a> {
-    type Model = Model<'a>;
+// z3 only converts numerals of up to 64 bits
+fn bv_value(bv: &ast::BV) -> Option<u128> {
+    let size = bv.get_size();
+    if size <= 64 {
+        return bv.as_u64().map(u128::from);
+    }
+    if size > 128 {
+        return None;
+    }
+    let low = bv.extract(63, 0).simplify().as_u64()?;
+    let high = bv.extract(size - 1, 64).simplify().as_u64()?;
+    Some((u128::from(high) << 64) | u128::from(low))
+}
+
+impl<'ctx> Constrained for Z3Solver<'ctx> {
+    type Model = Model;
 
     fn check(&self) -> SatResult<Self::Model> {
-        todo!()
+        *self.unknown.borrow_mut() = None;
+        *self.core.borrow_mut() = None;
+        match self.check_sat() {
+            Ok(res) => res,
+            Err(e) => {
+                *self.unknown.borrow_mut() = Some(UnknownReason::Error(e.to_string()));
+                SatResult::Unknown
+            }
+        }
+    }
+}
+
+impl<'ctx> Solver<ast::Bool<'ctx>, ast::BV<'ctx>> for Z3Solver<'ctx> {
+    // An encoding error is reported by the next query
+    fn generic_assert(&mut self, constraint: &Constraint) {
+        let _ = self.assert_constraint(constraint);
+    }
+
+    fn solve(&self) -> SatResult<Self::Model> {
+        self.check()
+    }
+
+    fn push(&mut self) {
+        self.solver.push();
+        self.scopes.push(AssertionScope::default());
+    }
+
+    // The outermost scope is never popped
+    fn pop(&mut self) {
+        if self.scopes.len() == 1 {
+            return;
+        }
+        let scope = self.scopes.pop().unwrap();
+        self.solver.pop(1);
+        let symbols = self.symbols.get_mut();
+        for name in scope.declared {
+            symbols.remove(&name);
+        }
+    }
+
+    fn set_limits(&mut self, limits: &SolverLimits) {
+        Z3Solver::set_limits(self, limits.clone());
+    }
+
+    fn reason_unknown(&self) -> Option<UnknownReason> {
+        self.unknown.borrow().clone()
+    }
+
+    fn assert_labelled(&mut self, label: &str, constraint: &Constraint) {
+        let _ = Z3Solver::assert_labelled(self, label, constraint);
+    }
+
+    fn unsat_core(&self) -> Option<Vec<String>> {
+        Z3Solver::unsat_core(self)
+    }
+}
+
+impl<'ctx> Optimize<ast::Bool<'ctx>, ast::BV<'ctx>> for Z3Solver<'ctx> {
+    fn optimize(&mut self, objective: &Objective) -> SatResult<Optimum<Model>> {
+        *self.unknown.get_mut() = None;
+        match Z3Solver::optimize(self, objective) {
+            Ok(res) => res,
+            Err(e) => {
+                *self.unknown.get_mut() = Some(UnknownReason::Error(e.to_string()));
+                SatResult::Unknown
+            }
+        }
+    }
+}
+
+impl<'ctx> Transpile<ast::Bool<'ctx>, ast::BV<'ctx>> for Z3Solver<'ctx> {
+    fn val_to_ground_type(&self, v: Sentence) -> ast::BV<'ctx> {
+        self.recover(self.bv_term(&v), || self.bv_literal(0))
+    }
+
+    fn ground_type_to_val(&self, g: ast::BV<'ctx>) -> Sentence {
+        let v = bv_value(&g.simplify())
+            .map(|v| self.number(v))
+            .ok_or_else(|| Z3Error::Unsupported(format!("non-constant term {}", g)));
+        self.recover(v, || self.number(0))
+    }
+
+    fn val_to_bool(&self, v: Sentence) -> ast::Bool<'ctx> {
+        self.recover(self.bool_term(&v), || self.false_())
+    }
+
+    // Assertion is left to `generic_assert`, which also records the term for `optimize`
+    fn assert(&self, c: ast::Bool<'ctx>) -> ast::Bool<'ctx> {
+        c
+    }
+
+    fn and(&self, l: ast::Bool<'ctx>, r: ast::Bool<'ctx>) -> ast::Bool<'ctx> {
+        ast::Bool::and(self.ctx, &[&l, &r])
+    }
+
+    fn not(&self, c: ast::Bool<'ctx>) -> ast::Bool<'ctx> {
+        c.not()
+    }
+
+    fn or(&self, l: ast::Bool<'ctx>, r: ast::Bool<'ctx>) -> ast::Bool<'ctx> {
+        ast::Bool::or(self.ctx, &[&l, &r])
+    }
+
+    fn gt(&self, l: ast::BV<'ctx>, r: ast::BV<'ctx>) -> ast::Bool<'ctx> {
+        l.bvugt(&r)
+    }
+
+    fn lt(&self, l: ast::BV<'ctx>, r: ast::BV<'ctx>) -> ast::Bool<'ctx> {
+        l.bvult(&r)
+    }
+
+    fn lte(&self, l: ast::BV<'ctx>, r: ast::BV<'ctx>) -> ast::Bool<'ctx> {
+        l.bvule(&r)
+    }
+
+    fn gte(&self, l: ast::BV<'ctx>, r: ast::BV<'ctx>) -> ast::Bool<'ctx> {
+        l.bvuge(&r)
+    }
+
+    fn eq(&self, l: ast::BV<'ctx>, r: ast::BV<'ctx>) -> ast::Bool<'ctx> {
+        l._eq(&r)
+    }
+
+    fn neq(&self, l: ast::BV<'ctx>, r: ast::BV<'ctx>) -> ast::Bool<'ctx> {
+        l._eq(&r).not()
+    }
+
+    fn ite(&self, c: ast::Bool<'ctx>, t: ast::Bool<'ctx>, e: ast::Bool<'ctx>) -> ast::Bool<'ctx> {
+        c.ite(&t, &e)
+    }
+
+    fn true_(&self) -> ast::Bool<'ctx> {
+        ast::Bool::from_bool(self.ctx, true)
+    }
+
+    fn false_(&self) -> ast::Bool<'ctx> {
+        ast::Bool::from_bool(self.ctx, false)
     }
 }
-
-// impl<'a> Solver<ValInt, Int<'a>> for Z3Solver<'a> {
-//     fn solve(&self) -> SatResult<Self::Model> {
-//         match self.inner().check() {
-//             Z3SatResult::Sat => SatResult::Sat(self.inner().get_model().unwrap()),
-//             Z3SatResult::Unsat => SatResult::Unsat,
-//             Z3SatResult::Unknown => SatResult::Unknown,
-//         }
-//     }
-
-//     fn generic_assert(&mut self, constraint: &Constraint) {
-//         self.inner().assert(&self.transpile(constraint));
-//     }
-// }
-
-// impl<'a> Solver<u64, Int<'a>> for Z3Solver<'a> {
-//     fn solve(&self) -> SatResult<Self::Model> {
-//         match self.inner().check() {
-//             Z3SatResult::Sat => SatResult::Sat(self.inner().get_model().unwrap()),
-//             Z3SatResult::Unsat => SatResult::Unsat,
-//             Z3SatResult::Unknown => SatResult::Unknown,
-//         }
-//     }
-
-//     fn generic_assert(&mut self, constraint: &Constraint) {
-//         self.inner().assert(&self.transpile(constraint));
-//     }
-// }
-
-// TO DO: Generic impl
-
-// impl<'a, T, A> Transpile<T, Bool<'a>, A> for Z3Solver<'a, T>
-// where A: From<T> + Ast<'a>
-// {
-
-// }
-
-// TODO(tannr): Impl Transpile from
-
-// impl<'a> Transpile<u64, Int<'a>> for Z3Solver<'a> {
-//     fn val_to_ground_type(&self, v: u64) -> Int<'a> {
-//         z3_int(v, self.get_ctx())
-//     }
-
-//     fn ground_type_to_val(&self, g: Int<'a>) -> u64 {
-//         g.as_u64().unwrap()
-//     }
-
-//     fn assert(&self, c: Bool<'a>) -> Bool<'a> {
-//         self.inner().assert(&c);
-//         c
-//     }
-
-//     fn and(&self, l: Bool<'a>, r: Bool<'a>) -> Bool<'a> {
-//         z3::ast::Bool::and(l.get_ctx(), &vec![&l, &r])
-//     }
-
-//     fn not(&self, c: Bool<'a>) -> Bool<'a> {
-//         z3::ast::Bool::not(&c)
-//     }
-
-//     fn or(&self, l: Bool<'a>, r: Bool<'a>) -> Bool<'a> {
-//         z3::ast::Bool::or(l.get_ctx(), &vec![&l, &r])
-//     }
-
-//     fn gt(&self, l: Int<'a>, r: Int<'a>) -> Bool<'a> {
-//         l.gt(&r)
-//     }
-
-//     fn lt(&self, l: Int<'a>, r: Int<'a>) -> Bool<'a> {
-//         l.lt(&r)
-//     }
-
-//     fn lte(&self, l: Int<'a>, r: Int<'a>) -> Bool<'a> {
-//         l.le(&r)
-//     }
-
-//     fn gte(&self, l: Int<'a>, r: Int<'a>) -> Bool<'a> {
-//         l.ge(&r)
-//     }
-
-//     fn eq(&self, l: Int<'a>, r: Int<'a>) -> Bool<'a> {
-//         l._eq(&r)
-//     }
-
-//     fn neq(&self, l: Int<'a>, r: Int<'a>) -> Bool<'a> {
-//         self.not(self.eq(l, r))
-//     }
-
-//     fn true_(&self) -> Bool<'a> {
-//         Bool::from_bool(&self.get_ctx(), true)
-//     }
-
-//     fn false_(&self) -> Bool<'a> {
-//         Bool::from_bool(&self.get_ctx(), false)
-//     }
-// }
-
-// impl<'a> Transpile<Bool<'a>, Int<'a>> for Z3Solver<'a> {
-//     fn val_to_ground_type(&self, v: ValInt) -> Int<'a> {
-//         if let Some(val) = v.inner() {
-//             z3_int(val, self.get_ctx())
-//         } else {
-//             z3_int_var(v.id(), self.get_ctx())
-//         }
-//     }
-
-//     fn ground_type_to_val(&self, g: Int<'a>) -> ValInt {
-//         ValInt::new(g.as_u64().unwrap(), Some(g.to_string()))
-//     }
-
-//     fn assert(&self, c: Bool<'a>) -> Bool<'a> {
-//         self.inner().assert(&c);
-//         c
-//     }
-
-//     fn and(&self, l: Bool<'a>, r: Bool<'a>) -> Bool<'a> {
-//         z3::ast::Bool::and(l.get_ctx(), &vec![&l, &r])
-//     }
-
-//     fn not(&self, c: Bool<'a>) -> Bool<'a> {
-//         z3::ast::Bool::not(&c)
-//     }
-
-//     fn or(&self, l: Bool<'a>, r: Bool<'a>) -> Bool<'a> {
-//         z3::ast::Bool::or(l.get_ctx(), &vec![&l, &r])
-//     }
-
-//     fn gt(&self, l: Int<'a>, r: Int<'a>) -> Bool<'a> {
-//         l.gt(&r)
-//     }
-
-//     fn lt(&self, l: Int<'a>, r: Int<'a>) -> Bool<'a> {
-//         l.lt(&r)
-//     }
-
-//     fn lte(&self, l: Int<'a>, r: Int<'a>) -> Bool<'a> {
-//         l.le(&r)
-//     }
-
-//     fn gte(&self, l: Int<'a>, r: Int<'a>) -> Bool<'a> {
-//         l.ge(&r)
-//     }
-
-//     fn eq(&self, l: Int<'a>, r: Int<'a>) -> Bool<'a> {
-//         l._eq(&r)
-//     }
-
-//     fn neq(&self, l: Int<'a>, r: Int<'a>) -> Bool<'a> {
-//         self.not(self.eq(l, r))
-//     }
-
-//     fn true_(&self) -> Bool<'a> {
-//         Bool::from_bool(&self.get_ctx(), true)
-//     }
-
-//     fn false_(&self) -> Bool<'a> {
-//         Bool::from_bool(&self.get_ctx(), false)
-//     }
-// }
@@ -3,6 +3,7 @@ use symbolic_stack_machines_core::{
     instructions::{AbstractExecRecord, AbstractInstruction, InstructionResult},
     memory::Memory,
     stack::{Stack, StackOpRecord, StackRecord},
+    storage::Storage,
 };
 
 pub struct ADD;
@@ -13,6 +14,7 @@ impl AbstractInstruction<AbstractExecRecord> for ADD {
         stack: &Stack,
         _mem: &Memory,
         _ext: &Env,
+        _storage: &Storage,
     ) -> InstructionResult<AbstractExecRecord> {
         let mut change_log = AbstractExecRecord::default();
 
@@ -40,6 +42,7 @@ impl AbstractInstruction<AbstractExecRecord> for SUB {
         stack: &Stack,
         _mem: &Memory,
         _ext: &Env,
+        _storage: &Storage,
     ) -> InstructionResult<AbstractExecRecord> {
         let mut change_log = AbstractExecRecord::default();
 
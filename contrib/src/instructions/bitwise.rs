@@ -2,8 +2,8 @@ use symbolic_stack_machines_core::{
     environment::{Env},
     instructions::{AbstractExecRecord, AbstractInstruction, InstructionResult},
     memory::{Memory},
-    stack::{Stack, StackOpRecord, StackRecord, StackVal}, value::{Sentence, CSimpleVal, CNumber, Value},
-    
+    stack::{Stack, StackOpRecord, StackRecord, StackVal},
+    storage::Storage,
 };
 
 use super::{ZERO, ONE};
@@ -17,6 +17,7 @@ impl AbstractInstruction<AbstractExecRecord> for ISZERO {
         stack: &Stack,
         _memory: &Memory,
         _ext: &Env,
+        _storage: &Storage,
     ) -> InstructionResult<AbstractExecRecord> {
         let mut change_log = AbstractExecRecord::default();
 
@@ -3,6 +3,7 @@ use symbolic_stack_machines_core::{
     instructions::{AbstractExecRecord, AbstractInstruction, InstructionResult},
     memory::{MemOpRecord, MemRecord, Memory},
     stack::{Stack, StackOpRecord, StackRecord, StackVal},
+    storage::{Storage, StorageOpRecord, StorageRecord},
 };
 use super::ZERO;
 
@@ -14,12 +15,14 @@ impl AbstractInstruction<AbstractExecRecord> for PUSH {
         _stack: &Stack,
         _memory: &Memory,
         _ext: &Env,
+        _storage: &Storage,
     ) -> InstructionResult<AbstractExecRecord> {
-        let mut change_log = AbstractExecRecord::default();
-
-        change_log.stack_diff = Some(StackRecord {
-            changed: vec![StackOpRecord::Push(self.0.clone())],
-        });
+        let change_log = AbstractExecRecord {
+            stack_diff: Some(StackRecord {
+                changed: vec![StackOpRecord::Push(self.0.clone())],
+            }),
+            ..Default::default()
+        };
 
         Ok(change_log)
     }
@@ -33,10 +36,12 @@ impl AbstractInstruction<AbstractExecRecord> for STOP {
         _stack: &Stack,
         _memory: &Memory,
         _ext: &Env,
+        _storage: &Storage,
     ) -> InstructionResult<AbstractExecRecord> {
-        let mut change_log = AbstractExecRecord::default();
-
-        change_log.halt = true;
+        let change_log = AbstractExecRecord {
+            halt: true,
+            ..Default::default()
+        };
 
         Ok(change_log)
     }
@@ -50,6 +55,7 @@ impl AbstractInstruction<AbstractExecRecord> for JUMPI {
         stack: &Stack,
         _memory: &Memory,
         _ext: &Env,
+        _storage: &Storage,
     ) -> InstructionResult<AbstractExecRecord> {
         let mut change_log = AbstractExecRecord::default();
 
@@ -73,11 +79,12 @@ impl AbstractInstruction<AbstractExecRecord> for MLOAD {
         stack: &Stack,
         memory: &Memory,
         _ext: &Env,
+        _storage: &Storage,
     ) -> InstructionResult<AbstractExecRecord> {
         let mut change_log = AbstractExecRecord::default();
 
         let mem_idx = stack.peek(0).unwrap();
-        let mem_val = memory.read_word(mem_idx.clone()).unwrap();
+        let mem_val = memory.load_word(mem_idx)?;
 
         change_log.stack_diff = Some(StackRecord {
             changed: vec![StackOpRecord::Pop, StackOpRecord::Push(mem_val)],
@@ -95,6 +102,7 @@ impl AbstractInstruction<AbstractExecRecord> for MSTORE {
         stack: &Stack,
         _memory: &Memory,
         _ext: &Env,
+        _storage: &Storage,
     ) -> InstructionResult<AbstractExecRecord> {
         let mut change_log = AbstractExecRecord::default();
 
@@ -112,3 +120,58 @@ impl AbstractInstruction<AbstractExecRecord> for MSTORE {
         Ok(change_log)
     }
 }
+
+pub struct SLOAD;
+
+impl AbstractInstruction<AbstractExecRecord> for SLOAD {
+    fn exec(
+        &self,
+        stack: &Stack,
+        _memory: &Memory,
+        _ext: &Env,
+        storage: &Storage,
+    ) -> InstructionResult<AbstractExecRecord> {
+        let mut change_log = AbstractExecRecord::default();
+
+        let key = stack.peek(0).unwrap();
+        let val = storage.load(key)?;
+
+        change_log.stack_diff = Some(StackRecord {
+            changed: vec![StackOpRecord::Pop, StackOpRecord::Push(val)],
+        });
+
+        // Lets symbolic storage tie this read to later reads at equal keys
+        change_log.storage_diff = Some(StorageRecord {
+            changed: vec![StorageOpRecord::Load(key.clone())],
+        });
+
+        Ok(change_log)
+    }
+}
+
+pub struct SSTORE;
+
+impl AbstractInstruction<AbstractExecRecord> for SSTORE {
+    fn exec(
+        &self,
+        stack: &Stack,
+        _memory: &Memory,
+        _ext: &Env,
+        _storage: &Storage,
+    ) -> InstructionResult<AbstractExecRecord> {
+        let mut change_log = AbstractExecRecord::default();
+
+        let key = stack.peek(0).unwrap();
+        let val = stack.peek(1).unwrap();
+
+        change_log.stack_diff = Some(StackRecord {
+            changed: vec![StackOpRecord::Pop, StackOpRecord::Pop],
+        });
+
+        change_log.storage_diff = Some(StorageRecord {
+            changed: vec![StorageOpRecord::Store(key.clone(), val.clone())],
+        });
+
+        Ok(change_log)
+    }
+}
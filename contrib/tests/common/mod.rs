@@ -1,3 +1,6 @@
+// Each test crate uses only some of these
+#![allow(dead_code)]
+
 use symbolic_stack_machines_contrib::instructions::{arith::*, misc::*};
 
 pub mod simple_lang {
@@ -6,6 +9,7 @@ pub mod simple_lang {
         instructions::{AbstractExecRecord, AbstractInstruction},
         memory::Memory,
         stack::Stack,
+        storage::Storage,
     };
 
     use super::*;
@@ -13,6 +17,8 @@ pub mod simple_lang {
         Add(ADD),
         Sub(SUB),
         Push(PUSH),
+        SLoad(SLOAD),
+        SStore(SSTORE),
     }
 
     pub fn add() -> SimpleLang {
@@ -27,19 +33,52 @@ pub mod simple_lang {
         SimpleLang::Push(PUSH(val.into()))
     }
 
+    pub fn sload() -> SimpleLang {
+        SimpleLang::SLoad(SLOAD)
+    }
+
+    pub fn sstore() -> SimpleLang {
+        SimpleLang::SStore(SSTORE)
+    }
+
     impl AbstractInstruction<AbstractExecRecord> for SimpleLang {
         fn exec(
             &self,
             stack: &Stack,
             mem: &Memory,
             ext: &Env,
+            storage: &Storage,
         ) -> symbolic_stack_machines_core::instructions::InstructionResult<AbstractExecRecord>
         {
             match self {
-                Self::Add(a) => a.exec(stack, mem, ext),
-                Self::Sub(s) => s.exec(stack, mem, ext),
-                Self::Push(p) => p.exec(stack, mem, ext),
+                Self::Add(a) => a.exec(stack, mem, ext, storage),
+                Self::Sub(s) => s.exec(stack, mem, ext, storage),
+                Self::Push(p) => p.exec(stack, mem, ext, storage),
+                Self::SLoad(l) => l.exec(stack, mem, ext, storage),
+                Self::SStore(s) => s.exec(stack, mem, ext, storage),
             }
         }
     }
 }
+
+pub mod values {
+    use symbolic_stack_machines_core::value::*;
+
+    pub fn sym(name: &str) -> Sentence {
+        Sentence::Basic(Value::Symbolic(SSimpleVal::SymbolicNumber(SNumber(
+            SymbolId(name.to_string(), None),
+        ))))
+    }
+
+    pub fn num(v: u64) -> Sentence {
+        Sentence::Basic(Value::Concrete(CSimpleVal::Number(CNumber::U64(v))))
+    }
+
+    pub fn plus(a: Sentence, b: Sentence) -> Sentence {
+        Sentence::BinOp {
+            a: Val::new(a),
+            b: Val::new(b),
+            op: BinOp::Plus,
+        }
+    }
+}
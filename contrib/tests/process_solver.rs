@@ -0,0 +1,377 @@
+use std::time::Duration;
+
+use symbolic_stack_machines_contrib::constraints::process::*;
+use symbolic_stack_machines_contrib::constraints::smtlib2::{SExpr, SmtLib2};
+use symbolic_stack_machines_core::constraint::{
+    enumerate_models, unique_value, Constrained, Constraint, Model, Objective, Optimize, SatResult,
+    Solver, SolverLimits, UnknownReason,
+};
+use symbolic_stack_machines_core::memory::{MemOpRecord, MemRecord, Memory, MemoryConfig};
+use symbolic_stack_machines_core::stack::StackVal;
+use symbolic_stack_machines_core::value::*;
+
+mod common;
+use common::values::*;
+
+// A stand-in for a real solver: logs every command it receives and answers
+// `check-sat` by running the `check_sat` shell snippet
+fn fake_solver(log: &std::path::Path, check_sat: &str) -> ProcessSolverBuilder {
+    let script = format!(
+        r#"while IFS= read -r line; do
+  echo "$line" >> '{}'
+  case "$line" in
+    "(check-sat)") {} ;;
+    "(get-model)") printf '(\n  (define-fun x () (_ BitVec 64)\n    #x0000000000000005)\n  (define-fun f ((a Bool)) Bool a)\n)\n' ;;
+    "(assert false)") echo '(error "boom")' ;;
+    "(get-info :reason-unknown)") echo '(:reason-unknown "canceled")' ;;
+    "(get-unsat-core)") echo '(|core!upper bound|)' ;;
+    "(get-value (x))") echo '((x #x0000000000000005))' ;;
+    "(pop 1)") if [ -n "$pop_fails" ]; then echo '(error "cannot pop")'; else echo success; fi ;;
+    "(exit)") exit 0 ;;
+    *) echo success ;;
+  esac
+done"#,
+        log.display(),
+        check_sat
+    );
+    ProcessSolverBuilder::new("sh").args(&["-c", &script])
+}
+
+fn log_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "ssm-process-solver-{}-{}.log",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn x_model() -> Model {
+    let mut model = Model::new();
+    model.insert("x", CSimpleVal::Number(CNumber::U64(5)));
+    model
+}
+
+#[test]
+fn test_check_sat_and_model() {
+    let log = log_path("model");
+    let mut solver = fake_solver(&log, "echo sat").build();
+
+    solver.generic_assert(&Constraint::gt(sym("x"), num(3)));
+    let res = solver.solve();
+
+    assert!(matches!(res, SatResult::Sat(m) if m == x_model()));
+    assert_eq!(
+        std::fs::read_to_string(&log).unwrap(),
+        "(set-option :print-success true)\n\
+         (set-option :produce-models true)\n\
+         (set-option :produce-unsat-cores true)\n\
+         (declare-const x (_ BitVec 64))\n\
+         (assert (bvugt x (_ bv3 64)))\n\
+         (check-sat)\n\
+         (get-model)\n"
+    );
+}
+
+#[test]
+fn test_push_pop_are_incremental() {
+    let log = log_path("push-pop");
+    let mut solver = fake_solver(&log, "echo unsat").build();
+
+    solver.generic_assert(&Constraint::gt(sym("x"), num(3)));
+    solver.push().unwrap();
+    solver.generic_assert(&Constraint::lt(sym("y"), sym("x")));
+    assert!(matches!(solver.solve(), SatResult::Unsat));
+
+    solver.pop().unwrap();
+    solver.generic_assert(&Constraint::eq(sym("y"), num(1)));
+    assert!(matches!(solver.solve(), SatResult::Unsat));
+
+    // `y` was declared inside the popped scope, so it is declared again
+    assert_eq!(
+        std::fs::read_to_string(&log).unwrap(),
+        "(set-option :print-success true)\n\
+         (set-option :produce-models true)\n\
+         (set-option :produce-unsat-cores true)\n\
+         (declare-const x (_ BitVec 64))\n\
+         (assert (bvugt x (_ bv3 64)))\n\
+         (push 1)\n\
+         (declare-const y (_ BitVec 64))\n\
+         (assert (bvult y x))\n\
+         (check-sat)\n\
+         (pop 1)\n\
+         (declare-const y (_ BitVec 64))\n\
+         (assert (= y (_ bv1 64)))\n\
+         (check-sat)\n"
+    );
+    assert!(matches!(
+        solver.pop(),
+        Err(ProcessSolverError::PopOutermostScope)
+    ));
+}
+
+#[test]
+fn test_timeout_restarts_and_replays() {
+    let log = log_path("timeout");
+    let mut solver = fake_solver(&log, "sleep 5")
+        .timeout(Duration::from_millis(200))
+        .build();
+
+    solver.generic_assert(&Constraint::gt(sym("x"), num(3)));
+    solver.push().unwrap();
+    solver.generic_assert(&Constraint::lt(sym("x"), num(9)));
+
+    assert!(matches!(solver.check(), SatResult::Unknown));
+    assert!(matches!(
+        solver.take_last_error(),
+        Some(ProcessSolverError::Timeout(_))
+    ));
+    assert!(!solver.is_running());
+
+    assert!(matches!(solver.check(), SatResult::Unknown));
+    let log = std::fs::read_to_string(&log).unwrap();
+    assert_eq!(log.matches("(push 1)").count(), 2);
+    assert_eq!(log.matches("(assert (bvult x (_ bv9 64)))").count(), 2);
+}
+
+#[test]
+fn test_solver_errors() {
+    let log = log_path("errors");
+    let mut solver = fake_solver(&log, "echo sat").build();
+
+    solver.generic_assert(&Constraint::False);
+
+    assert!(matches!(solver.check(), SatResult::Unknown));
+    assert!(matches!(
+        solver.take_last_error(),
+        Some(ProcessSolverError::Solver(msg)) if msg == "boom"
+    ));
+
+    let solver = ProcessSolverBuilder::new("this-solver-does-not-exist").build();
+    assert!(matches!(solver.check(), SatResult::Unknown));
+    assert!(matches!(
+        solver.take_last_error(),
+        Some(ProcessSolverError::Io(_))
+    ));
+}
+
+#[test]
+fn test_limits_are_passed_to_the_solver() {
+    let log = log_path("limits");
+    let mut solver = fake_solver(&log, "echo unknown")
+        .dialect(SolverDialect::Z3)
+        .limits(SolverLimits::default().timeout(Duration::from_millis(1500)))
+        .build();
+
+    solver.generic_assert(&Constraint::gt(sym("x"), num(3)));
+    assert!(matches!(solver.solve(), SatResult::Unknown));
+    assert_eq!(solver.reason_unknown(), Some(UnknownReason::Timeout));
+    assert!(solver.take_last_error().is_none());
+
+    // Changing the limits restarts the solver with the new options
+    Solver::set_limits(&mut solver, &SolverLimits::default().rlimit(1000));
+    assert!(!solver.is_running());
+    assert!(matches!(solver.solve(), SatResult::Unknown));
+
+    let log = std::fs::read_to_string(&log).unwrap();
+    assert_eq!(log.matches("(set-option :timeout 1500)").count(), 1);
+    assert_eq!(log.matches("(set-option :rlimit 1000)").count(), 1);
+    assert_eq!(log.matches("(get-info :reason-unknown)").count(), 2);
+}
+
+#[test]
+fn test_generic_solvers_are_killed_on_timeout() {
+    let log = log_path("generic-limits");
+    let mut solver = fake_solver(&log, "sleep 5")
+        .limits(SolverLimits::default().timeout(Duration::from_millis(200)))
+        .build();
+
+    solver.generic_assert(&Constraint::gt(sym("x"), num(3)));
+
+    assert!(matches!(solver.solve(), SatResult::Unknown));
+    assert_eq!(solver.reason_unknown(), Some(UnknownReason::Timeout));
+    assert!(!solver.is_running());
+    assert!(!std::fs::read_to_string(&log).unwrap().contains(":timeout"));
+}
+
+#[test]
+fn test_unsat_core() {
+    let log = log_path("core");
+    let mut solver = fake_solver(&log, "echo unsat")
+        .dialect(SolverDialect::Z3)
+        .build();
+
+    solver.generic_assert(&Constraint::gt(sym("x"), num(3)));
+    assert!(matches!(solver.solve(), SatResult::Unsat));
+    assert_eq!(solver.unsat_core(), None);
+
+    solver
+        .assert_labelled("upper bound", &Constraint::lt(sym("x"), num(2)))
+        .unwrap();
+    assert!(matches!(solver.solve(), SatResult::Unsat));
+    assert_eq!(solver.unsat_core(), Some(vec!["upper bound".to_string()]));
+
+    let log = std::fs::read_to_string(&log).unwrap();
+    assert!(log.contains("(set-option :smt.core.minimize true)"));
+    assert!(log.contains("(assert (! (bvult x (_ bv2 64)) :named |core!upper bound|))"));
+    assert_eq!(log.matches("(get-unsat-core)").count(), 1);
+}
+
+#[test]
+fn test_optimize_with_z3_objectives() {
+    let log = log_path("optimize");
+    let mut solver = fake_solver(&log, "echo sat")
+        .dialect(SolverDialect::Z3)
+        .build();
+
+    solver.generic_assert(&Constraint::gt(sym("x"), num(4)));
+    let res = Optimize::optimize(&mut solver, &Objective::Minimize(sym("x")));
+
+    assert!(matches!(
+        res,
+        SatResult::Sat(o) if o.model == x_model() && o.value == CNumber::U64(5)
+    ));
+    let log = std::fs::read_to_string(&log).unwrap();
+    assert!(log.ends_with(
+        "(declare-const x (_ BitVec 64))\n\
+         (assert (bvugt x (_ bv4 64)))\n\
+         (push 1)\n\
+         (minimize x)\n\
+         (check-sat)\n\
+         (get-model)\n\
+         (get-value (x))\n\
+         (pop 1)\n"
+    ));
+}
+
+#[test]
+fn test_optimize_pops_on_every_path() {
+    let log = log_path("optimize-pop");
+    let mut solver = fake_solver(&log, "echo sat; pop_fails=1")
+        .dialect(SolverDialect::Z3)
+        .build();
+
+    solver.generic_assert(&Constraint::gt(sym("x"), num(4)));
+    let (res, popped) = solver.optimize(&Objective::Minimize(sym("x")));
+
+    assert!(matches!(
+        res,
+        Ok(SatResult::Sat(o)) if o.value == CNumber::U64(5)
+    ));
+    assert!(matches!(popped, Err(ProcessSolverError::Solver(m)) if m == "cannot pop"));
+    assert!(matches!(
+        solver.pop(),
+        Err(ProcessSolverError::PopOutermostScope)
+    ));
+}
+
+#[test]
+fn test_unencodable_constraint() {
+    let log = log_path("unencodable");
+    let mut solver = fake_solver(&log, "echo sat").build();
+    let not_an_array = Sentence::BinOp {
+        a: Val::new(num(1)),
+        b: Val::new(sym("i")),
+        op: BinOp::Select,
+    };
+
+    solver.push().unwrap();
+    assert!(solver
+        .assert_constraint(&Constraint::eq(not_an_array.clone(), num(0)))
+        .is_err());
+    solver.generic_assert(&Constraint::eq(not_an_array, num(0)));
+    assert!(matches!(solver.solve(), SatResult::Unknown));
+    assert!(matches!(
+        solver.take_last_error(),
+        Some(ProcessSolverError::SmtLib2(_))
+    ));
+
+    solver.pop().unwrap();
+    assert!(matches!(solver.solve(), SatResult::Sat(_)));
+}
+
+#[test]
+fn test_parse_model() {
+    let smt = SmtLib2::default();
+    let response = SExpr::parse_all(
+        "(model (define-fun |x| () (_ BitVec 64) (_ bv5 64)) (define-fun b () Bool false) \
+         (define-fun m () (Array (_ BitVec 64) (_ BitVec 8)) ((as const (Array (_ BitVec 64) (_ BitVec 8))) #x01)))",
+    )
+    .unwrap();
+
+    let model = parse_model(&smt, &response[0]).unwrap();
+
+    assert_eq!(model.len(), 2);
+    assert_eq!(model.get("x"), x_model().get("x"));
+    assert_eq!(model.get("b"), Some(&CSimpleVal::Boolean(Bool::False)));
+}
+
+#[test]
+#[ignore = "requires a z3 binary on PATH"]
+fn test_z3_binary() {
+    let mut solver = ProcessSolverBuilder::z3()
+        .timeout(Duration::from_secs(10))
+        .build();
+
+    solver.generic_assert(&Constraint::gt(sym("x"), num(3)));
+    solver.generic_assert(&Constraint::lt(sym("x"), num(5)));
+    let res = solver.solve();
+
+    let mut expected = Model::new();
+    expected.insert("x", CSimpleVal::Number(CNumber::U64(4)));
+    assert!(matches!(res, SatResult::Sat(m) if m == expected));
+
+    solver.push().unwrap();
+    solver
+        .assert_labelled("seven", &Constraint::eq(sym("x"), num(7)))
+        .unwrap();
+    solver
+        .assert_labelled("even", &Constraint::eq(sym("x"), num(4)))
+        .unwrap();
+    assert!(matches!(solver.solve(), SatResult::Unsat));
+    assert_eq!(solver.unsat_core(), Some(vec!["seven".to_string()]));
+    solver.pop().unwrap();
+    assert!(matches!(solver.solve(), SatResult::Sat(_)));
+
+    let max = solver.optimize(&Objective::Maximize(sym("x"))).0.unwrap();
+    assert!(matches!(max, SatResult::Sat(o) if o.value == CNumber::U64(4)));
+
+    let unique = unique_value(&mut solver, &[], &sym("x")).unwrap();
+    assert!(matches!(
+        unique,
+        SatResult::Sat(Some(CSimpleVal::Number(CNumber::U64(4))))
+    ));
+    let below = [Constraint::lt(sym("y"), num(3))];
+    let solutions = enumerate_models(&mut solver, &below, &[sym("y")], 10).unwrap();
+    assert!(solutions.exhausted);
+    assert_eq!(solutions.models.len(), 3);
+}
+
+#[test]
+#[ignore = "requires a z3 binary on PATH"]
+fn test_z3_array_memory() {
+    let mut solver = ProcessSolverBuilder::z3()
+        .timeout(Duration::from_secs(10))
+        .build();
+    let mem = Memory::symbolic("mem", MemoryConfig::default())
+        .apply(MemRecord {
+            changed: vec![MemOpRecord::Write(StackVal(sym("x")), StackVal(sym("v")))],
+        })
+        .unwrap();
+
+    // A word reads back as written, whatever the address
+    let read = mem.load_word(&StackVal(sym("x"))).unwrap();
+    solver.generic_assert(&Constraint::neq(read.0, sym("v")));
+    assert!(matches!(solver.solve(), SatResult::Unsat));
+
+    // Reading elsewhere can overlap the write
+    let mut solver = ProcessSolverBuilder::z3()
+        .timeout(Duration::from_secs(10))
+        .build();
+    let read = mem.load_byte(&StackVal(sym("y"))).unwrap();
+    solver.generic_assert(&Constraint::eq(read.0, num(0xab)));
+    solver.generic_assert(&Constraint::eq(sym("v"), num(0xab)));
+    solver.generic_assert(&Constraint::neq(sym("y"), sym("x")));
+    assert!(matches!(solver.solve(), SatResult::Sat(m) if m.get("y").is_some()));
+}
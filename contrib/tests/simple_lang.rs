@@ -6,6 +6,7 @@ use symbolic_stack_machines_core::machine::{
     inner_interpreter::ConcreteInnerInterpreter, r#abstract::AbstractMachine,
 };
 use symbolic_stack_machines_core::memory::Memory;
+use symbolic_stack_machines_core::storage::Storage;
 use symbolic_stack_machines_core::{
     machine::outer_interpreter::{ConcreteOuterInterpreter, OuterInterpreter},
     stack::{Stack, StackVal},
@@ -24,8 +25,10 @@ fn test_abstract_machine() {
         stack,
         mem,
         env,
+        storage: Storage::default(),
         pc,
         pgm: &pgm,
+        fault: None,
     };
     let inner_interpreter = Box::new(ConcreteInnerInterpreter {});
     let outer_interpreter = ConcreteOuterInterpreter { inner_interpreter };
@@ -40,3 +43,30 @@ fn test_abstract_machine() {
 
     assert_eq!(res.clone(), StackVal::from(0_u64));
 }
+
+#[test]
+fn test_storage_persists_across_invocations() {
+    // Store 7 at key 1, then read it back in a second invocation
+    let setter = vec![push(7), push(1), sstore()];
+    let getter = vec![push(1), sload(), push(2), sload()];
+    let machine = AbstractMachine {
+        stack: Stack::default(),
+        mem: Memory::default(),
+        env: Env {},
+        storage: Storage::default(),
+        pc: Some(0),
+        pgm: &setter,
+        fault: None,
+    };
+    let inner_interpreter = Box::new(ConcreteInnerInterpreter {});
+    let outer_interpreter = ConcreteOuterInterpreter { inner_interpreter };
+
+    let first = outer_interpreter.run(machine).unwrap();
+    assert_eq!(first.stack.len(), 0);
+
+    let second = outer_interpreter
+        .run(first.next_invocation(&getter, Memory::default()))
+        .unwrap();
+    assert_eq!(second.stack.peek(0), Some(&StackVal::from(0_u64)));
+    assert_eq!(second.stack.peek(1), Some(&StackVal::from(7_u64)));
+}
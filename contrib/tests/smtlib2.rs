@@ -0,0 +1,223 @@
+use symbolic_stack_machines_contrib::constraints::smtlib2::*;
+use symbolic_stack_machines_core::constraint::{Constraint, Transpile};
+use symbolic_stack_machines_core::memory::{MemOpRecord, MemRecord, Memory, MemoryConfig};
+use symbolic_stack_machines_core::stack::StackVal;
+use symbolic_stack_machines_core::value::*;
+
+mod common;
+use common::values::*;
+
+#[test]
+fn test_export_script() {
+    let smt = SmtLib2::new(8);
+    let constraints = vec![
+        Constraint::gt(plus(sym("x"), num(1)), num(5)),
+        !Constraint::eq(sym("y"), num(300)),
+    ];
+
+    let script = smt.script(&constraints).unwrap();
+
+    assert_eq!(
+        script,
+        "(declare-const x (_ BitVec 8))\n\
+         (declare-const y (_ BitVec 8))\n\
+         (assert (bvugt (bvadd x (_ bv1 8)) (_ bv5 8)))\n\
+         (assert (not (= y (_ bv44 8))))\n\
+         (check-sat)\n"
+    );
+}
+
+#[test]
+fn test_export_quotes_uuid_symbols() {
+    let smt = SmtLib2::default();
+    let x = SymbolId::<CNumber>::new(None);
+    let c = Constraint::lt(
+        Sentence::Basic(Value::Symbolic(SSimpleVal::SymbolicNumber(SNumber(
+            x.clone(),
+        )))),
+        num(10),
+    );
+
+    let term = smt.transpile(&c);
+
+    assert_eq!(term, format!("(bvult {} (_ bv10 64))", quote_symbol(&x.0)));
+    assert_eq!(smt.parse_term(&quote_symbol(&x.0)).unwrap(), {
+        Sentence::Basic(Value::Symbolic(SSimpleVal::SymbolicNumber(SNumber(
+            SymbolId(x.0, None),
+        ))))
+    });
+}
+
+#[test]
+fn test_round_trip() {
+    let exporter = SmtLib2::default();
+    let is_zero = Sentence::TernaryOp {
+        a: Val::new(Sentence::BinOp {
+            a: Val::new(sym("x")),
+            b: Val::new(num(0)),
+            op: BinOp::Eq,
+        }),
+        b: Val::new(num(1)),
+        c: Val::new(num(0)),
+        op: TernaryOp::Ite,
+    };
+    let constraints = vec![
+        Constraint::neq(is_zero, num(0)).or(Constraint::lte(sym("y"), sym("x"))),
+        Constraint::ite(
+            Constraint::gte(sym("x"), num(3)),
+            Constraint::True,
+            Constraint::False,
+        ),
+    ];
+    let script = exporter.script(&constraints).unwrap();
+
+    let importer = SmtLib2::default();
+    let parsed = importer.parse_script(&script).unwrap();
+
+    assert_eq!(parsed.len(), 2);
+    assert_eq!(importer.script(&parsed).unwrap(), script);
+}
+
+#[test]
+fn test_parse_handwritten_script() {
+    let smt = SmtLib2::default();
+    let script = r#"
+        ; a comment
+        (set-logic QF_BV)
+        (set-info :source |hand written|)
+        (declare-fun a () (_ BitVec 64))
+        (declare-const flag Bool)
+        (assert (and flag (bvult a #x000000000000000a) (= (bvand a #x0000000000000001) (_ bv1 64))))
+        (check-sat)
+        (exit)
+    "#;
+
+    let constraints = smt.parse_script(script).unwrap();
+
+    assert_eq!(constraints.len(), 1);
+    assert_eq!(
+        smt.transpile(&constraints[0]),
+        "(and (and flag (bvult a (_ bv10 64))) (= (bvand a (_ bv1 64)) (_ bv1 64)))"
+    );
+}
+
+#[test]
+fn test_parse_errors() {
+    let smt = SmtLib2::default();
+
+    assert!(matches!(
+        smt.parse_script("(assert (bvult z (_ bv1 64)))"),
+        Err(SmtLib2Error::UndeclaredSymbol(s)) if s == "z"
+    ));
+    assert!(matches!(
+        smt.parse_script("(assert (_ bv1 64))"),
+        Err(SmtLib2Error::SortMismatch(_))
+    ));
+    assert!(matches!(
+        smt.parse_script("(assert true"),
+        Err(SmtLib2Error::UnexpectedEof)
+    ));
+    assert!(matches!(
+        smt.parse_script("(push 1)"),
+        Err(SmtLib2Error::Unsupported(_))
+    ));
+}
+
+#[test]
+fn test_array_memory() {
+    let config = MemoryConfig {
+        word_size: 2,
+        ..MemoryConfig::default()
+    };
+    let mem = Memory::symbolic("mem", config)
+        .apply(MemRecord {
+            changed: vec![
+                MemOpRecord::Write(StackVal(sym("x")), StackVal(sym("v"))),
+                MemOpRecord::WriteByte(StackVal(num(3)), StackVal(num(0x1ff))),
+            ],
+        })
+        .unwrap();
+    let read = mem.load_word(&StackVal(sym("x"))).unwrap();
+    let c = Constraint::neq(read.0, sym("v"));
+
+    let smt = SmtLib2::default();
+    let script = smt.script(&[c]).unwrap();
+    assert!(script.starts_with(
+        "(declare-const mem (Array (_ BitVec 64) (_ BitVec 8)))\n\
+         (declare-const v (_ BitVec 64))\n\
+         (declare-const x (_ BitVec 64))\n"
+    ));
+    assert!(script.contains(
+        "(store (store (store mem x ((_ extract 7 0) (bvlshr v (_ bv8 64)))) \
+         (bvadd x (_ bv1 64)) ((_ extract 7 0) v)) (_ bv3 64) #xff)"
+    ));
+    assert!(script.contains("((_ zero_extend 56) (select "));
+
+    let parsed = SmtLib2::default().parse_script(&script).unwrap();
+    assert_eq!(smt.script(&parsed).unwrap(), script);
+}
+
+#[test]
+fn test_concrete_vectors() {
+    let smt = SmtLib2::default();
+    let v = Sentence::Basic(Value::Concrete(CSimpleVal::Vector(Vecc(vec![1, 2]))));
+    let read = Sentence::BinOp {
+        a: Val::new(v),
+        b: Val::new(sym("i")),
+        op: BinOp::Select,
+    };
+    let term = smt.val_to_ground_type(read);
+    assert_eq!(
+        term,
+        "((_ zero_extend 56) (select (store (store ((as const (Array (_ BitVec 64) (_ BitVec 8))) \
+         #x00) (_ bv0 64) #x01) (_ bv1 64) #x02) i))"
+    );
+    assert_eq!(
+        smt.val_to_ground_type(smt.ground_type_to_val(term.clone())),
+        term
+    );
+}
+
+#[test]
+fn test_encoding_errors() {
+    let smt = SmtLib2::default();
+    let mem = Sentence::Basic(Value::Symbolic(SSimpleVal::SymbolicVector(SymbolicVecc(
+        SymbolId("mem".to_string(), None),
+    ))));
+    let not_an_array = Sentence::BinOp {
+        a: Val::new(num(1)),
+        b: Val::new(sym("i")),
+        op: BinOp::Select,
+    };
+
+    assert!(matches!(
+        smt.try_transpile(&Constraint::eq(not_an_array, num(0))),
+        Err(SmtLib2Error::SortMismatch(_))
+    ));
+    assert!(matches!(
+        smt.script(&[Constraint::eq(mem, num(0))]),
+        Err(SmtLib2Error::SortMismatch(_))
+    ));
+    assert!(matches!(
+        smt.try_transpile(&Constraint::eq(Sentence::Basic(Value::default()), num(0))),
+        Err(SmtLib2Error::Unsupported(_))
+    ));
+
+    // The trait can't fail, so the error waits to be taken
+    assert_eq!(smt.ground_type_to_val("(bvadd".to_string()), num(0));
+    assert!(matches!(
+        smt.take_error(),
+        Some(SmtLib2Error::UnexpectedEof)
+    ));
+    assert!(smt.take_error().is_none());
+}
+
+#[test]
+fn test_scripts_declare_their_own_symbols() {
+    let smt = SmtLib2::default();
+    smt.script(&[Constraint::eq(sym("x"), num(1))]).unwrap();
+    let script = smt.script(&[Constraint::eq(sym("y"), num(1))]).unwrap();
+
+    assert!(!script.contains("declare-const x"));
+    assert!(smt.parse_script("(assert (= y x))").is_err());
+}
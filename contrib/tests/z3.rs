@@ -0,0 +1,152 @@
+#![cfg(feature = "z3")]
+
+use symbolic_stack_machines_contrib::constraints::z3::*;
+use symbolic_stack_machines_core::constraint::{
+    enumerate_models, unique_value, Constraint, Model, Node, Objective, Optimize, SatResult,
+    Solver, UnknownReason,
+};
+use symbolic_stack_machines_core::memory::{MemOpRecord, MemRecord, Memory, MemoryConfig};
+use symbolic_stack_machines_core::stack::StackVal;
+use symbolic_stack_machines_core::value::*;
+use z3::{Config, Context};
+
+mod common;
+use common::values::*;
+
+#[test]
+fn test_solve_push_pop_and_unsat_core() {
+    let ctx = Context::new(&Config::new());
+    let mut solver = Z3SolverBuilder::new().build(&ctx);
+
+    solver.generic_assert(&Constraint::gt(sym("x"), num(3)));
+    solver.generic_assert(&Constraint::lt(sym("x"), num(5)));
+    let mut expected = Model::new();
+    expected.insert("x", CSimpleVal::Number(CNumber::U64(4)));
+    assert!(matches!(solver.solve(), SatResult::Sat(m) if m == expected));
+
+    solver.push();
+    Z3Solver::assert_labelled(&mut solver, "seven", &Constraint::eq(sym("x"), num(7))).unwrap();
+    Z3Solver::assert_labelled(&mut solver, "even", &Constraint::eq(sym("x"), num(4))).unwrap();
+    solver.generic_assert(&Constraint::eq(sym("y"), num(1)));
+    assert!(matches!(solver.solve(), SatResult::Unsat));
+    assert_eq!(solver.unsat_core(), Some(vec!["seven".to_string()]));
+
+    // Symbols first used in a popped scope are no longer part of the model
+    solver.pop();
+    assert!(matches!(solver.solve(), SatResult::Sat(m) if m == expected));
+    assert_eq!(solver.unsat_core(), None);
+}
+
+#[test]
+fn test_optimize_leaves_assertions_unchanged() {
+    let ctx = Context::new(&Config::new());
+    let mut solver = Z3SolverBuilder::new().build(&ctx);
+    solver.generic_assert(&Constraint::gt(plus(sym("x"), num(1)), num(4)));
+    solver.generic_assert(&Constraint::lt(sym("x"), num(10)));
+
+    let min = Optimize::optimize(&mut solver, &Objective::Minimize(sym("x")));
+    assert!(matches!(min, SatResult::Sat(o) if o.value == CNumber::U64(4)));
+    let max = Optimize::optimize(&mut solver, &Objective::Maximize(plus(sym("x"), sym("y"))));
+    assert!(matches!(
+        max,
+        SatResult::Sat(o) if o.value == CNumber::U64(u64::MAX) && o.model.get("y").is_some()
+    ));
+
+    // `y` was only used by the objective
+    let res = solver.solve();
+    assert!(matches!(res, SatResult::Sat(m) if m.get("x").is_some() && m.get("y").is_none()));
+
+    let unique = unique_value(&mut solver, &[Constraint::gt(sym("x"), num(8))], &sym("x"));
+    assert!(matches!(
+        unique.unwrap(),
+        SatResult::Sat(Some(CSimpleVal::Number(CNumber::U64(9))))
+    ));
+    let solutions = enumerate_models(&mut solver, &[], &[sym("x")], 10).unwrap();
+    assert!(solutions.exhausted);
+    assert_eq!(solutions.models.len(), 6);
+}
+
+#[test]
+fn test_encoding_error_is_reported_until_popped() {
+    let ctx = Context::new(&Config::new());
+    let mut solver = Z3SolverBuilder::new().width(8).build(&ctx);
+    let flag = Sentence::Basic(Value::Symbolic(SSimpleVal::SymbolicBool(SymbolId(
+        "x".to_string(),
+        None,
+    ))));
+
+    solver.generic_assert(&Constraint::lt(sym("x"), num(300)));
+    solver.push();
+    solver.generic_assert(&Constraint::Assert(Node::new_simple(flag)));
+    assert!(matches!(solver.solve(), SatResult::Unknown));
+    assert!(matches!(
+        solver.reason_unknown(),
+        Some(UnknownReason::Error(e)) if e.contains("Sort mismatch")
+    ));
+
+    // Numbers are masked to the width, so `x < 300` is `x < 44`
+    solver.pop();
+    let max = Optimize::optimize(&mut solver, &Objective::Maximize(sym("x")));
+    assert!(matches!(
+        max,
+        SatResult::Sat(o) if o.value == CNumber::from_u128_with_width(43, 8)
+    ));
+}
+
+#[test]
+fn test_array_memory() {
+    let ctx = Context::new(&Config::new());
+    let mem = Memory::symbolic("mem", MemoryConfig::default())
+        .apply(MemRecord {
+            changed: vec![MemOpRecord::Write(StackVal(sym("x")), StackVal(sym("v")))],
+        })
+        .unwrap();
+
+    // A word reads back as written, whatever the address
+    let mut solver = Z3SolverBuilder::new().build(&ctx);
+    let read = mem.load_word(&StackVal(sym("x"))).unwrap();
+    solver.generic_assert(&Constraint::neq(read.0, sym("v")));
+    assert!(matches!(solver.solve(), SatResult::Unsat));
+
+    // A later write at another symbolic address only clobbers it if the two may overlap
+    let clobbered = mem
+        .apply(MemRecord {
+            changed: vec![MemOpRecord::Write(StackVal(sym("y")), StackVal(sym("w")))],
+        })
+        .unwrap();
+    let read = clobbered.load_word(&StackVal(sym("x"))).unwrap();
+    let mut solver = Z3SolverBuilder::new().build(&ctx);
+    solver.generic_assert(&Constraint::neq(read.0, sym("v")));
+    solver.generic_assert(&Constraint::lt(sym("x"), num(1 << 32)));
+    solver.generic_assert(&Constraint::lt(sym("y"), num(1 << 32)));
+    solver.push();
+    solver.generic_assert(&Constraint::gte(sym("y"), plus(sym("x"), num(8))));
+    assert!(matches!(solver.solve(), SatResult::Unsat));
+    solver.pop();
+    assert!(matches!(solver.solve(), SatResult::Sat(_)));
+
+    // The untouched bytes come from the symbolic initial contents, which are not in the model
+    let mut solver = Z3SolverBuilder::new().build(&ctx);
+    let read = mem.load_byte(&StackVal(sym("y"))).unwrap();
+    solver.generic_assert(&Constraint::eq(read.0, num(0xab)));
+    solver.generic_assert(&Constraint::gt(sym("y"), plus(sym("x"), num(40))));
+    assert!(matches!(
+        solver.solve(),
+        SatResult::Sat(m) if m.get("y").is_some() && m.get("mem").is_none()
+    ));
+
+    // Selected bytes are zero-extended, and the bytes past a concrete vector are zero
+    let bytes = Sentence::Basic(Value::Concrete(CSimpleVal::Vector(Vecc(vec![1, 0xff]))));
+    let select = Sentence::BinOp {
+        a: Val::new(bytes),
+        b: Val::new(sym("i")),
+        op: BinOp::Select,
+    };
+    let mut solver = Z3SolverBuilder::new().build(&ctx);
+    let max = Optimize::optimize_under(
+        &mut solver,
+        &[Constraint::gt(sym("i"), num(0))],
+        &Objective::Maximize(select),
+    );
+    assert!(matches!(max, SatResult::Sat(o) if o.value == CNumber::U64(0xff)));
+}
@@ -0,0 +1,185 @@
+use thiserror::Error;
+
+use super::*;
+use crate::value::{CSimpleVal, Value};
+
+// A term passed where a bare symbol was expected
+#[derive(Debug, Error)]
+#[error("{0} is not a symbol")]
+pub struct NotASymbol(pub Val);
+
+pub struct Solutions {
+    // Each restricted to the enumerated symbols
+    pub models: Vec<Model>,
+    // Whether `models` holds every solution, i.e. the solver ruled out any further one
+    pub exhausted: bool,
+}
+
+// Enumerates up to `limit` assignments to `symbols` that satisfy the asserted constraints
+// and `path`, by blocking each assignment found before asking for the next one. Symbols a
+// model leaves unassigned are not constrained by the blocking clauses. Each of `symbols`
+// must be a bare symbol, since only those have a value in the model.
+pub fn enumerate_models<S, Ast, G>(
+    solver: &mut S,
+    path: &[Constraint],
+    symbols: &[Val],
+    limit: usize,
+) -> Result<Solutions, NotASymbol>
+where
+    S: Solver<Ast, G, Model = Model>,
+{
+    let symbols = symbols
+        .iter()
+        .map(|s| match symbol_name(s) {
+            Some(name) => Ok((s, name.to_string())),
+            None => Err(NotASymbol(s.clone())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let names: BTreeSet<String> = symbols.iter().map(|(_, name)| name.clone()).collect();
+    let mut solutions = Solutions {
+        models: vec![],
+        exhausted: false,
+    };
+
+    solver.push();
+    for c in path {
+        solver.generic_assert(c);
+    }
+
+    while solutions.models.len() < limit {
+        let model = match solver.solve() {
+            SatResult::Sat(m) => m.restrict(&names),
+            SatResult::Unsat => {
+                solutions.exhausted = true;
+                break;
+            }
+            SatResult::Unknown => break,
+        };
+
+        let block = symbols
+            .iter()
+            .filter_map(|(s, name)| {
+                let v = model.get(name)?;
+                Some(Constraint::neq(
+                    (*s).clone(),
+                    Val::Basic(Value::Concrete(v.clone())),
+                ))
+            })
+            .reduce(Constraint::or);
+        solutions.models.push(model);
+        match block {
+            Some(block) => solver.generic_assert(&block),
+            // Nothing to tell this model apart from the next
+            None => break,
+        }
+    }
+
+    solver.pop();
+    Ok(solutions)
+}
+
+fn symbol_name(s: &Val) -> Option<&str> {
+    match s {
+        Val::Basic(v) => v.symbol(),
+        _ => None,
+    }
+}
+
+// `Sat(Some(v))` if `symbol` can only take the value `v` under the asserted constraints
+// and `path`, and `Sat(None)` if it can take several. A symbol the model doesn't assign
+// is unconstrained, so not unique.
+pub fn unique_value<S, Ast, G>(
+    solver: &mut S,
+    path: &[Constraint],
+    symbol: &Val,
+) -> Result<SatResult<Option<CSimpleVal>>, NotASymbol>
+where
+    S: Solver<Ast, G, Model = Model>,
+{
+    let solutions = enumerate_models(solver, path, std::slice::from_ref(symbol), 2)?;
+    let value = |m: &Model| symbol_name(symbol).and_then(|n| m.get(n).cloned());
+    Ok(match (solutions.models.as_slice(), solutions.exhausted) {
+        ([], true) => SatResult::Unsat,
+        ([m], _) if value(m).is_none() => SatResult::Sat(None),
+        ([m], true) => SatResult::Sat(value(m)),
+        ([_, _], _) => SatResult::Sat(None),
+        _ => SatResult::Unknown,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constraint::mock::MockSolver;
+    use crate::test_util::{num, sym};
+    use crate::value::CNumber;
+
+    fn x_values(solutions: &Solutions) -> Vec<CSimpleVal> {
+        solutions
+            .models
+            .iter()
+            .map(|m| m.get("x").unwrap().clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_enumerate_models() {
+        let mut solver = MockSolver {
+            domain: Some((3, 5)),
+            ..Default::default()
+        };
+        let path = vec![
+            Constraint::gt(sym("y"), num(4)),
+            Constraint::lte(sym("x"), num(7)),
+        ];
+
+        let all = enumerate_models(&mut solver, &path, &[sym("x")], 10).unwrap();
+        assert!(all.exhausted);
+        assert_eq!(
+            x_values(&all),
+            (3..=5)
+                .map(|v| CSimpleVal::Number(CNumber::U64(v)))
+                .collect::<Vec<_>>()
+        );
+        assert!(all.models.iter().all(|m| m.get("y").is_none()));
+
+        let some = enumerate_models(&mut solver, &path, &[sym("x")], 2).unwrap();
+        assert!(!some.exhausted);
+        assert_eq!(some.models.len(), 2);
+        assert!(solver.balanced());
+
+        // A compound term has no value of its own in the model to block
+        let x_plus_y = "(+ x y)".parse::<Val>().unwrap();
+        let err = enumerate_models(&mut solver, &path, &[sym("x"), x_plus_y], 10)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "x + y is not a symbol");
+        assert!(unique_value(&mut solver, &path, &num(1)).is_err());
+        assert!(solver.balanced());
+    }
+
+    #[test]
+    fn test_unique_value() {
+        let mut solver = MockSolver {
+            domain: Some((3, 5)),
+            ..Default::default()
+        };
+
+        let unique =
+            unique_value(&mut solver, &[Constraint::gte(sym("x"), num(5))], &sym("x")).unwrap();
+        assert!(matches!(
+            unique,
+            SatResult::Sat(Some(CSimpleVal::Number(CNumber::U64(5))))
+        ));
+
+        let several =
+            unique_value(&mut solver, &[Constraint::lt(sym("x"), num(5))], &sym("x")).unwrap();
+        assert!(matches!(several, SatResult::Sat(None)));
+
+        let unconstrained = unique_value(&mut solver, &[], &sym("x")).unwrap();
+        assert!(matches!(unconstrained, SatResult::Sat(None)));
+
+        let none = unique_value(&mut solver, &[Constraint::False], &sym("x")).unwrap();
+        assert!(matches!(none, SatResult::Unsat));
+    }
+}
@@ -0,0 +1,39 @@
+use std::time::Duration;
+
+// Resource limits for each query sent to a solver. `None` leaves the solver's own default.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SolverLimits {
+    pub timeout: Option<Duration>,
+    // Solver specific deterministic resource limit (z3 `rlimit`, cvc5 `rlimit-per`)
+    pub rlimit: Option<u64>,
+    pub memory_mb: Option<u64>,
+}
+
+impl SolverLimits {
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn rlimit(mut self, rlimit: u64) -> Self {
+        self.rlimit = Some(rlimit);
+        self
+    }
+
+    pub fn memory_mb(mut self, memory_mb: u64) -> Self {
+        self.memory_mb = Some(memory_mb);
+        self
+    }
+}
+
+// Why a solver answered `SatResult::Unknown`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UnknownReason {
+    Timeout,
+    // Resource or memory limit reached
+    ResourceLimit,
+    // The solver gave up, e.g. because the theory is incomplete
+    Incomplete(String),
+    // The solver failed to answer, e.g. it crashed or returned an error
+    Error(String),
+}
@@ -0,0 +1,206 @@
+use std::cell::{Cell, RefCell};
+
+use super::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::value::{CNumber, CSimpleVal, Value};
+
+// Test double for the solver traits. Records every push, pop and assert, and treats a
+// query as unsat iff `Constraint::False` is asserted in an open scope. Models assign 1
+// to every symbol of the asserted constraints. Queries with more than `max_constraints`
+// asserted constraints time out. Unsat cores are the labels of the asserted `False`s.
+// With a `domain`, every symbol ranges over it, comparisons between a symbol and a constant
+// narrow that range (or exclude a value), and models assign the lowest value left.
+pub struct MockSolver {
+    pub scopes: Vec<Vec<Constraint>>,
+    pub labels: Vec<Vec<Option<String>>>,
+    pub log: Vec<String>,
+    pub solves: Cell<usize>,
+    pub max_constraints: Option<usize>,
+    pub domain: Option<(u128, u128)>,
+    pub limits: SolverLimits,
+    pub unknown: RefCell<Option<UnknownReason>>,
+    pub core: RefCell<Option<Vec<String>>>,
+}
+
+impl Default for MockSolver {
+    fn default() -> Self {
+        Self {
+            scopes: vec![vec![]],
+            labels: vec![vec![]],
+            log: vec![],
+            solves: Cell::new(0),
+            max_constraints: None,
+            domain: None,
+            limits: SolverLimits::default(),
+            unknown: RefCell::new(None),
+            core: RefCell::new(None),
+        }
+    }
+}
+
+impl Constrained for MockSolver {
+    type Model = Model;
+
+    fn check(&self) -> SatResult<Model> {
+        self.solves.set(self.solves.get() + 1);
+        *self.unknown.borrow_mut() = None;
+        *self.core.borrow_mut() = None;
+        let asserted = self.scopes.iter().flatten();
+        if matches!(self.max_constraints, Some(max) if asserted.clone().count() > max) {
+            *self.unknown.borrow_mut() = Some(UnknownReason::Timeout);
+            return SatResult::Unknown;
+        }
+        if asserted.clone().any(|c| *c == Constraint::False) {
+            let core = asserted
+                .zip(self.labels.iter().flatten())
+                .filter(|(c, _)| **c == Constraint::False)
+                .filter_map(|(_, l)| l.clone())
+                .collect();
+            *self.core.borrow_mut() = Some(core);
+            return SatResult::Unsat;
+        }
+        let mut model = Model::new();
+        let domain = match self.domain {
+            Some(domain) => domain,
+            None => {
+                for symbol in asserted.flat_map(|c| c.symbols()) {
+                    model.insert(symbol, CSimpleVal::Number(CNumber::U64(1)));
+                }
+                return SatResult::Sat(model);
+            }
+        };
+
+        let mut ranges: BTreeMap<String, (u128, u128)> = BTreeMap::new();
+        let mut excluded: BTreeSet<(String, u128)> = BTreeSet::new();
+        for c in asserted.clone() {
+            for symbol in c.symbols() {
+                ranges.entry(symbol).or_insert(domain);
+            }
+            match Self::bound(c) {
+                Some((symbol, None, v)) => {
+                    excluded.insert((symbol, v));
+                }
+                Some((symbol, Some(lo), hi)) => {
+                    let range = ranges.get_mut(&symbol).unwrap();
+                    *range = (range.0.max(lo), range.1.min(hi));
+                }
+                None => {}
+            }
+        }
+        for (symbol, (lo, hi)) in ranges {
+            let v = (lo..=hi).find(|v| !excluded.contains(&(symbol.clone(), *v)));
+            match v {
+                Some(v) => model.insert(symbol, CSimpleVal::Number(CNumber::U64(v as u64))),
+                None => return SatResult::Unsat,
+            }
+        }
+        SatResult::Sat(model)
+    }
+}
+
+impl MockSolver {
+    // Whether every push was popped again, and no pop came before its push
+    pub fn balanced(&self) -> bool {
+        let mut open = 0;
+        for op in &self.log {
+            match op.as_str() {
+                "push" => open += 1,
+                "pop" if open == 0 => return false,
+                "pop" => open -= 1,
+                _ => {}
+            }
+        }
+        open == 0
+    }
+
+    // `(symbol, Some(lo), hi)` for a range, `(symbol, None, v)` for an excluded value
+    fn bound(c: &Constraint) -> Option<(String, Option<u128>, u128)> {
+        let cmp = match c {
+            Constraint::BinCmp(cmp) => cmp,
+            _ => return None,
+        };
+        let (l, r) = cmp.operands();
+        let symbol = l.unwrap().symbols().into_iter().next()?;
+        let v = match r.unwrap() {
+            Val::Basic(Value::Concrete(CSimpleVal::Number(n))) => u128::from(n),
+            _ => return None,
+        };
+        match cmp {
+            CmpType::GT(..) => Some((symbol, Some(v + 1), u128::MAX)),
+            CmpType::GTE(..) => Some((symbol, Some(v), u128::MAX)),
+            CmpType::LT(..) => Some((symbol, Some(0), v.checked_sub(1)?)),
+            CmpType::LTE(..) => Some((symbol, Some(0), v)),
+            CmpType::EQ(..) => Some((symbol, Some(v), v)),
+            CmpType::NEQ(..) => Some((symbol, None, v)),
+        }
+    }
+}
+
+impl Solver<(), ()> for MockSolver {
+    fn generic_assert(&mut self, constraint: &Constraint) {
+        self.log.push("assert".to_string());
+        self.scopes.last_mut().unwrap().push(constraint.clone());
+        self.labels.last_mut().unwrap().push(None);
+    }
+
+    fn solve(&self) -> SatResult<Model> {
+        self.check()
+    }
+
+    fn push(&mut self) {
+        self.log.push("push".to_string());
+        self.scopes.push(vec![]);
+        self.labels.push(vec![]);
+    }
+
+    fn pop(&mut self) {
+        self.log.push("pop".to_string());
+        self.scopes.pop();
+        self.labels.pop();
+    }
+
+    fn set_limits(&mut self, limits: &SolverLimits) {
+        self.limits = limits.clone();
+    }
+
+    fn reason_unknown(&self) -> Option<UnknownReason> {
+        self.unknown.borrow().clone()
+    }
+
+    fn assert_labelled(&mut self, label: &str, constraint: &Constraint) {
+        self.generic_assert(constraint);
+        *self.labels.last_mut().unwrap().last_mut().unwrap() = Some(label.to_string());
+    }
+
+    fn unsat_core(&self) -> Option<Vec<String>> {
+        self.core.borrow().clone()
+    }
+}
+
+impl Optimize<(), ()> for MockSolver {
+    fn optimize(&mut self, objective: &Objective) -> SatResult<Optimum<Model>> {
+        bisect(self, objective, 64)
+    }
+}
+
+impl Transpile<(), ()> for MockSolver {
+    fn val_to_ground_type(&self, _v: Val) {}
+    fn ground_type_to_val(&self, _g: ()) -> Val {
+        Val::default()
+    }
+    fn val_to_bool(&self, _v: Val) {}
+    fn assert(&self, _c: ()) {}
+    fn and(&self, _l: (), _r: ()) {}
+    fn not(&self, _c: ()) {}
+    fn or(&self, _l: (), _r: ()) {}
+    fn gt(&self, _l: (), _r: ()) {}
+    fn lt(&self, _l: (), _r: ()) {}
+    fn lte(&self, _l: (), _r: ()) {}
+    fn gte(&self, _l: (), _r: ()) {}
+    fn eq(&self, _l: (), _r: ()) {}
+    fn neq(&self, _l: (), _r: ()) {}
+    fn ite(&self, _c: (), _t: (), _e: ()) {}
+    fn true_(&self) {}
+    fn false_(&self) {}
+}
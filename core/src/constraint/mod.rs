@@ -1,8 +1,27 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 pub use std::rc::Rc;
 
-use crate::value::Sentence as Val;
-
-#[derive(Clone)]
+use serde::{Deserialize, Serialize};
+
+mod enumerate;
+mod limits;
+#[cfg(test)]
+pub(crate) mod mock;
+mod model;
+mod normalize;
+mod objective;
+pub mod optimizer;
+mod substitute;
+pub use enumerate::{enumerate_models, unique_value, NotASymbol, Solutions};
+pub use limits::{SolverLimits, UnknownReason};
+pub use model::Model;
+pub use normalize::normalize;
+pub(crate) use normalize::normalize_onto;
+pub use objective::{bisect, Objective, Optimize, Optimum};
+
+use crate::value::{SSimpleVal, Sentence as Val};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Constraint {
     True,
     False,
@@ -13,7 +32,7 @@ pub enum Constraint {
     BinCmp(CmpType),
     Ite(Rc<Constraint>, Node, Node),
 }
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Node {
     Simple(Val),
     Compound(Rc<Constraint>),
@@ -26,6 +45,17 @@ impl AsRef<Constraint> for Constraint {
 }
 
 impl Node {
+    fn collect_symbols(
+        &self,
+        symbols: &mut BTreeMap<String, SSimpleVal>,
+        visited: &mut HashSet<*const Val>,
+    ) {
+        match self {
+            Node::Simple(v) => v.collect_symbols(symbols, visited),
+            Node::Compound(c) => c.collect_symbols(symbols, visited),
+        }
+    }
+
     pub fn new_simple(v: Val) -> Self {
         Self::Simple(v)
     }
@@ -46,6 +76,8 @@ impl Node {
 pub trait Transpile<Ast, G> {
     fn val_to_ground_type(&self, v: Val) -> G;
     fn ground_type_to_val(&self, g: G) -> Val;
+    // Used when a boolean-valued sentence is asserted directly, e.g. `Assert(Node::Simple(a == b))`
+    fn val_to_bool(&self, v: Val) -> Ast;
     fn assert(&self, c: Ast) -> Ast;
 
     fn and(&self, l: Ast, r: Ast) -> Ast;
@@ -66,63 +98,24 @@ pub trait Transpile<Ast, G> {
 
     fn neq(&self, l: G, r: G) -> Ast;
 
+    fn ite(&self, c: Ast, t: Ast, e: Ast) -> Ast;
+
     fn true_(&self) -> Ast;
     fn false_(&self) -> Ast;
 
+    fn transpile_node(&self, n: &Node) -> Ast {
+        match n {
+            Node::Compound(c) => self.transpile(c),
+            Node::Simple(v) => self.val_to_bool(v.clone()),
+        }
+    }
+
     fn transpile(&self, constraint: impl AsRef<Constraint>) -> Ast {
         match constraint.as_ref() {
-            Constraint::Assert(c) => {
-                if let Node::Compound(constraint) = c {
-                    self.transpile(constraint)
-                } else {
-                    panic!("Cannot assert a ground value")
-                }
-            }
-            Constraint::Not(c) => {
-                if let Node::Compound(constraint) = c {
-                    self.transpile(constraint)
-                } else {
-                    panic!("Cannot logically operate a ground value")
-                }
-            }
-            Constraint::And(l, r) => {
-                let l = {
-                    if let Node::Compound(c) = l {
-                        self.transpile(c)
-                    } else {
-                        panic!("Cannot logically operate a ground value")
-                    }
-                };
-
-                let r = {
-                    if let Node::Compound(c) = r {
-                        self.transpile(c)
-                    } else {
-                        panic!("Cannot logically operate a ground value")
-                    }
-                };
-
-                self.and(l, r)
-            }
-            Constraint::Or(l, r) => {
-                let l = {
-                    if let Node::Compound(c) = l {
-                        self.transpile(c)
-                    } else {
-                        panic!("Cannot logically operate a ground value")
-                    }
-                };
-
-                let r = {
-                    if let Node::Compound(c) = r {
-                        self.transpile(c)
-                    } else {
-                        panic!("Cannot logically operate a ground value")
-                    }
-                };
-
-                self.or(l, r)
-            }
+            Constraint::Assert(c) => self.assert(self.transpile_node(c)),
+            Constraint::Not(c) => self.not(self.transpile_node(c)),
+            Constraint::And(l, r) => self.and(self.transpile_node(l), self.transpile_node(r)),
+            Constraint::Or(l, r) => self.or(self.transpile_node(l), self.transpile_node(r)),
             Constraint::BinCmp(cmp) => match cmp {
                 CmpType::GT(l, r) => self.gt(
                     self.val_to_ground_type(l.unwrap()),
@@ -149,51 +142,102 @@ pub trait Transpile<Ast, G> {
                     self.val_to_ground_type(r.unwrap()),
                 ),
             },
-            Constraint::Ite(_, _, _) => todo!(),
+            Constraint::Ite(c, t, e) => self.ite(
+                self.transpile(c),
+                self.transpile_node(t),
+                self.transpile_node(e),
+            ),
             Constraint::True => self.assert(self.true_()),
             Constraint::False => self.assert(self.false_()),
         }
     }
 }
 
-#[allow(dead_code)]
 impl Constraint {
-    fn assert(c: Constraint) -> Self {
+    // Names of all symbols the constraint depends on
+    pub fn symbols(&self) -> BTreeSet<String> {
+        self.free_symbols().into_keys().collect()
+    }
+
+    // The symbols the constraint depends on, keyed by name
+    pub fn free_symbols(&self) -> BTreeMap<String, SSimpleVal> {
+        let mut symbols = BTreeMap::new();
+        self.collect_symbols(&mut symbols, &mut HashSet::new());
+        symbols
+    }
+
+    fn collect_symbols(
+        &self,
+        symbols: &mut BTreeMap<String, SSimpleVal>,
+        visited: &mut HashSet<*const Val>,
+    ) {
+        match self {
+            Constraint::True | Constraint::False => {}
+            Constraint::Assert(n) | Constraint::Not(n) => n.collect_symbols(symbols, visited),
+            Constraint::And(l, r) | Constraint::Or(l, r) => {
+                l.collect_symbols(symbols, visited);
+                r.collect_symbols(symbols, visited);
+            }
+            Constraint::BinCmp(cmp) => {
+                let (l, r) = cmp.operands();
+                l.collect_symbols(symbols, visited);
+                r.collect_symbols(symbols, visited);
+            }
+            Constraint::Ite(c, t, e) => {
+                c.collect_symbols(symbols, visited);
+                t.collect_symbols(symbols, visited);
+                e.collect_symbols(symbols, visited);
+            }
+        }
+    }
+
+    pub fn assert(c: Constraint) -> Self {
         Self::Assert(Node::new_compound(c))
     }
 
-    fn and(self, c: Constraint) -> Self {
+    pub fn and(self, c: Constraint) -> Self {
         Self::And(Node::new_compound(self), Node::new_compound(c))
     }
 
-    fn not(self, c: Constraint) -> Self {
-        Self::Not(Node::new_compound(c))
+    pub fn or(self, c: Constraint) -> Self {
+        Self::Or(Node::new_compound(self), Node::new_compound(c))
     }
 
-    fn or(self, c: Constraint) -> Self {
-        Self::Or(Node::new_compound(self), Node::new_compound(c))
+    pub fn ite(c: Constraint, t: Constraint, e: Constraint) -> Self {
+        Self::Ite(Rc::new(c), Node::new_compound(t), Node::new_compound(e))
     }
 
-    fn gt(l: Val, r: Val) -> Self {
+    pub fn gt(l: Val, r: Val) -> Self {
         Self::BinCmp(CmpType::GT(Node::new_simple(l), Node::new_simple(r)))
     }
 
-    fn lt(l: Val, r: Val) -> Self {
+    pub fn lt(l: Val, r: Val) -> Self {
         Self::BinCmp(CmpType::LT(Node::new_simple(l), Node::new_simple(r)))
     }
 
-    fn eq(l: Val, r: Val) -> Self {
+    pub fn eq(l: Val, r: Val) -> Self {
         Self::BinCmp(CmpType::EQ(Node::new_simple(l), Node::new_simple(r)))
     }
 
-    fn lte(l: Val, r: Val) -> Self {
+    pub fn neq(l: Val, r: Val) -> Self {
+        Self::BinCmp(CmpType::NEQ(Node::new_simple(l), Node::new_simple(r)))
+    }
+
+    pub fn lte(l: Val, r: Val) -> Self {
         Self::BinCmp(CmpType::LTE(Node::new_simple(l), Node::new_simple(r)))
     }
 
-    fn gte(l: Val, r: Val) -> Self {
+    pub fn gte(l: Val, r: Val) -> Self {
         Self::BinCmp(CmpType::GTE(Node::new_simple(l), Node::new_simple(r)))
     }
-    // to do rest
+}
+
+impl std::ops::Not for Constraint {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        Self::Not(Node::new_compound(self))
+    }
 }
 
 // pub struct ConstraintSolver<S: Solver, V> {
@@ -204,9 +248,35 @@ impl Constraint {
 pub trait Solver<Ast, G>: Constrained + Transpile<Ast, G> {
     fn generic_assert(&mut self, constraint: &Constraint);
     fn solve(&self) -> SatResult<Self::Model>;
+
+    // Opens a new assertion scope. Everything asserted after a `push` is
+    // retracted by the matching `pop`.
+    fn push(&mut self);
+    fn pop(&mut self);
+
+    // Applies to every query from now on. Limits the solver has no notion of are ignored.
+    fn set_limits(&mut self, _limits: &SolverLimits) {}
+
+    // Why the most recent `solve` returned `SatResult::Unknown`, if the solver can tell
+    fn reason_unknown(&self) -> Option<UnknownReason> {
+        None
+    }
+
+    // Asserts `constraint` under `label`, so that it can be reported by `unsat_core`.
+    // Solvers without unsat core support treat it as a plain assertion.
+    fn assert_labelled(&mut self, _label: &str, constraint: &Constraint) {
+        self.generic_assert(constraint);
+    }
+
+    // After `solve` returned `SatResult::Unsat`, the labels of a subset of the labelled
+    // assertions that is unsatisfiable on its own (together with the unlabelled ones).
+    // `None` if the solver doesn't track labels.
+    fn unsat_core(&self) -> Option<Vec<String>> {
+        None
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum CmpType {
     GT(Node, Node),
     LT(Node, Node),
@@ -216,6 +286,20 @@ pub enum CmpType {
     NEQ(Node, Node),
 }
 
+impl CmpType {
+    pub fn operands(&self) -> (&Node, &Node) {
+        match self {
+            CmpType::GT(l, r)
+            | CmpType::LT(l, r)
+            | CmpType::GTE(l, r)
+            | CmpType::LTE(l, r)
+            | CmpType::EQ(l, r)
+            | CmpType::NEQ(l, r) => (l, r),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub enum SatResult<M> {
     Sat(M),
     Unsat,
@@ -0,0 +1,59 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::value::{CSimpleVal, Sentence, Value};
+
+// A satisfying assignment returned by a solver, keyed by symbol name (`SymbolId.0`)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Model {
+    assignments: BTreeMap<String, CSimpleVal>,
+}
+
+impl Model {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, symbol: impl Into<String>, v: CSimpleVal) {
+        self.assignments.insert(symbol.into(), v);
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&CSimpleVal> {
+        self.assignments.get(symbol)
+    }
+
+    pub fn get_sentence(&self, symbol: &str) -> Option<Sentence> {
+        self.get(symbol)
+            .map(|v| Sentence::Basic(Value::Concrete(v.clone())))
+    }
+
+    // Values from `other` take precedence
+    pub fn merge(&mut self, other: Model) {
+        self.assignments.extend(other.assignments);
+    }
+
+    // Only the assignments to `symbols`
+    pub fn restrict(&self, symbols: &BTreeSet<String>) -> Model {
+        let assignments = self
+            .assignments
+            .iter()
+            .filter(|(k, _)| symbols.contains(*k))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        Model { assignments }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &CSimpleVal)> {
+        self.assignments.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.assignments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assignments.is_empty()
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::HashSet;
+
+use super::*;
+use crate::value::{CSimpleVal, Value};
+
+// Literals are the leaves of a constraint in negation normal form: `True`, `False`,
+// comparisons, and asserted or negated boolean sentences.
+fn is_literal(c: &Constraint) -> bool {
+    matches!(
+        c,
+        Constraint::True
+            | Constraint::False
+            | Constraint::BinCmp(_)
+            | Constraint::Assert(Node::Simple(_))
+            | Constraint::Not(Node::Simple(_))
+    )
+}
+
+fn negate_literal(c: &Constraint) -> Constraint {
+    match c {
+        Constraint::True => Constraint::False,
+        Constraint::False => Constraint::True,
+        Constraint::BinCmp(cmp) => Constraint::BinCmp(cmp.negate()),
+        Constraint::Assert(n) => Constraint::Not(n.clone()),
+        Constraint::Not(n) => Constraint::Assert(n.clone()),
+        _ => !c.clone(),
+    }
+}
+
+fn node_to_constraint(n: &Node) -> Constraint {
+    match n {
+        Node::Simple(_) => Constraint::Assert(n.clone()),
+        Node::Compound(c) => (**c).clone(),
+    }
+}
+
+fn concrete(n: &Node) -> Option<&CSimpleVal> {
+    match n {
+        Node::Simple(Val::Basic(Value::Concrete(v))) => Some(v),
+        _ => None,
+    }
+}
+
+impl CmpType {
+    // The comparison that holds exactly when this one doesn't. Numbers are unsigned, so
+    // the order is total.
+    pub fn negate(&self) -> CmpType {
+        match self.clone() {
+            CmpType::GT(l, r) => CmpType::LTE(l, r),
+            CmpType::LT(l, r) => CmpType::GTE(l, r),
+            CmpType::GTE(l, r) => CmpType::LT(l, r),
+            CmpType::LTE(l, r) => CmpType::GT(l, r),
+            CmpType::EQ(l, r) => CmpType::NEQ(l, r),
+            CmpType::NEQ(l, r) => CmpType::EQ(l, r),
+        }
+    }
+}
+
+impl Constraint {
+    // Negation normal form: only `And` and `Or` above literals, with `Ite` expanded
+    pub fn nnf(&self) -> Constraint {
+        self.nnf_with_polarity(true)
+    }
+
+    fn nnf_with_polarity(&self, positive: bool) -> Constraint {
+        let node = |n: &Node, positive: bool| node_to_constraint(n).nnf_with_polarity(positive);
+        match self {
+            Constraint::Assert(n @ Node::Simple(_)) | Constraint::Not(n @ Node::Simple(_)) => {
+                let literal = Constraint::Assert(n.clone());
+                let positive = positive == matches!(self, Constraint::Assert(_));
+                if positive {
+                    literal
+                } else {
+                    negate_literal(&literal)
+                }
+            }
+            Constraint::Assert(n) => node(n, positive),
+            Constraint::Not(n) => node(n, !positive),
+            Constraint::And(l, r) if positive => node(l, true).and(node(r, true)),
+            Constraint::And(l, r) => node(l, false).or(node(r, false)),
+            Constraint::Or(l, r) if positive => node(l, true).or(node(r, true)),
+            Constraint::Or(l, r) => node(l, false).and(node(r, false)),
+            Constraint::Ite(c, t, e) => {
+                let c = (**c).clone();
+                let expanded = c
+                    .clone()
+                    .and(node_to_constraint(t))
+                    .or((!c).and(node_to_constraint(e)));
+                expanded.nnf_with_polarity(positive)
+            }
+            literal if positive => literal.clone(),
+            literal => negate_literal(literal),
+        }
+    }
+
+    // The operands of nested `And`s, left to right
+    pub fn conjuncts(&self) -> Vec<Constraint> {
+        let mut out = vec![];
+        self.flatten(&mut out, true);
+        out
+    }
+
+    // The operands of nested `Or`s, left to right
+    pub fn disjuncts(&self) -> Vec<Constraint> {
+        let mut out = vec![];
+        self.flatten(&mut out, false);
+        out
+    }
+
+    fn flatten(&self, out: &mut Vec<Constraint>, conjunction: bool) {
+        match self {
+            Constraint::And(l, r) if conjunction => {
+                node_to_constraint(l).flatten(out, conjunction);
+                node_to_constraint(r).flatten(out, conjunction);
+            }
+            Constraint::Or(l, r) if !conjunction => {
+                node_to_constraint(l).flatten(out, conjunction);
+                node_to_constraint(r).flatten(out, conjunction);
+            }
+            Constraint::Assert(Node::Compound(c)) => c.flatten(out, conjunction),
+            c => out.push(c.clone()),
+        }
+    }
+
+    // Conjunctive normal form as a list of clauses, each a list of literals. Clauses are
+    // simplified as in `normalize`, so an empty clause means the constraint is trivially
+    // unsatisfiable. Distributing `Or` over `And` can blow up exponentially.
+    pub fn to_cnf(&self) -> Vec<Vec<Constraint>> {
+        let mut clauses = vec![];
+        for c in self.nnf().conjuncts() {
+            let mut product: Vec<Vec<Constraint>> = vec![vec![]];
+            for d in c.disjuncts() {
+                let factor = if is_literal(&d) {
+                    vec![vec![d]]
+                } else {
+                    d.to_cnf()
+                };
+                product = product
+                    .iter()
+                    .flat_map(|p| {
+                        factor.iter().map(move |f| {
+                            let mut clause = p.clone();
+                            clause.extend(f.iter().cloned());
+                            clause
+                        })
+                    })
+                    .collect();
+            }
+            clauses.extend(product.into_iter().filter_map(simplify_clause));
+        }
+        dedup(clauses)
+    }
+
+    // `Some(b)` if the constraint is a literal that is always `b`: constants, comparisons of
+    // a sentence with itself, and comparisons between concrete numbers
+    pub fn trivial_value(&self) -> Option<bool> {
+        match self {
+            Constraint::True => Some(true),
+            Constraint::False => Some(false),
+            Constraint::Assert(n) | Constraint::Not(n) => match concrete(n)? {
+                CSimpleVal::Boolean(b) => {
+                    let b = *b == crate::value::Bool::True;
+                    Some(b == matches!(self, Constraint::Assert(_)))
+                }
+                _ => None,
+            },
+            Constraint::BinCmp(cmp) => {
+                let (l, r) = cmp.operands();
+                let ordering = if l == r {
+                    std::cmp::Ordering::Equal
+                } else {
+                    match (concrete(l)?, concrete(r)?) {
+                        (CSimpleVal::Number(a), CSimpleVal::Number(b)) => {
+                            u128::from(a.clone()).cmp(&u128::from(b.clone()))
+                        }
+                        (a, b) if a == b => std::cmp::Ordering::Equal,
+                        _ => return None,
+                    }
+                };
+                Some(match cmp {
+                    CmpType::GT(..) => ordering.is_gt(),
+                    CmpType::LT(..) => ordering.is_lt(),
+                    CmpType::GTE(..) => ordering.is_ge(),
+                    CmpType::LTE(..) => ordering.is_le(),
+                    CmpType::EQ(..) => ordering.is_eq(),
+                    CmpType::NEQ(..) => ordering.is_ne(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+// Drops duplicate and trivially false literals; `None` if the clause is a tautology
+fn simplify_clause(clause: Vec<Constraint>) -> Option<Vec<Constraint>> {
+    let mut seen = HashSet::new();
+    let mut out = vec![];
+    for l in clause {
+        match l.trivial_value() {
+            Some(true) => return None,
+            Some(false) => continue,
+            None => {}
+        }
+        if seen.contains(&negate_literal(&l)) {
+            return None;
+        }
+        if seen.insert(l.clone()) {
+            out.push(l);
+        }
+    }
+    Some(out)
+}
+
+fn dedup<T: Clone + Eq + std::hash::Hash>(items: Vec<T>) -> Vec<T> {
+    let mut seen = HashSet::new();
+    items
+        .into_iter()
+        .filter(|i| seen.insert(i.clone()))
+        .collect()
+}
+
+// Rewrites a conjunction of constraints into a flat list of conjuncts in negation normal
+// form, without duplicates or trivially true conjuncts. A trivial contradiction (a false
+// literal or clause, or a literal alongside its negation) gives `[Constraint::False]`.
+// Unlike `to_cnf` this never distributes, so the result is at most linear in the input.
+pub fn normalize(constraints: &[Constraint]) -> Vec<Constraint> {
+    let mut out: Vec<Constraint> = vec![];
+    let mut seen = HashSet::new();
+    for c in constraints.iter().flat_map(|c| c.nnf().conjuncts()) {
+        let c = match simplify_clause(c.disjuncts()) {
+            None => continue,
+            Some(literals) => match literals.into_iter().reduce(Constraint::or) {
+                None => return vec![Constraint::False],
+                Some(c) => c,
+            },
+        };
+        if is_literal(&c) && seen.contains(&negate_literal(&c)) {
+            return vec![Constraint::False];
+        }
+        if seen.insert(c.clone()) {
+            out.push(c);
+        }
+    }
+    out
+}
+
+// The normalized conjuncts `added` contributes to the normalized constraints `known`,
+// leaving out those already known, or `None` if they trivially contradict `known`. Only
+// `added` is normalized, so extending a path costs the size of the extension.
+pub(crate) fn normalize_onto(
+    known: &HashSet<&Constraint>,
+    added: &[Constraint],
+) -> Option<Vec<Constraint>> {
+    let added = normalize(added);
+    if added == [Constraint::False] {
+        return None;
+    }
+    let mut out = vec![];
+    for c in added {
+        if is_literal(&c) && known.contains(&negate_literal(&c)) {
+            return None;
+        }
+        if !known.contains(&c) {
+            out.push(c);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{num, sym};
+
+    #[test]
+    fn test_nnf() {
+        let x = Constraint::gt(sym("x"), num(1));
+        let y = Constraint::eq(sym("y"), num(2));
+
+        let c = !(x.clone().and(!y.clone()));
+        assert!(c.nnf() == Constraint::lte(sym("x"), num(1)).or(y.clone()));
+
+        let ite = !Constraint::ite(x.clone(), y.clone(), Constraint::False);
+        assert!(
+            ite.nnf()
+                == (Constraint::lte(sym("x"), num(1)).or(Constraint::neq(sym("y"), num(2))))
+                    .and(x.clone().or(Constraint::True))
+        );
+    }
+
+    #[test]
+    fn test_cnf() {
+        let [a, b, c, d] = ["a", "b", "c", "d"].map(|s| Constraint::eq(sym(s), num(0)));
+        let c = (a.clone().and(b.clone()))
+            .or(c.clone().and(d.clone()))
+            .or(!a.clone());
+
+        let cnf = c.to_cnf();
+
+        // `a ∨ ¬a` clauses are tautologies
+        assert!(
+            cnf == vec![
+                vec![b.clone(), Constraint::eq(sym("c"), num(0)), !a.clone()]
+                    .into_iter()
+                    .map(|l| l.nnf())
+                    .collect::<Vec<_>>(),
+                vec![b, d, !a.clone()]
+                    .into_iter()
+                    .map(|l| l.nnf())
+                    .collect::<Vec<_>>(),
+            ]
+        );
+        assert!(
+            Constraint::False
+                .or(Constraint::lt(num(2), num(1)))
+                .to_cnf()
+                == vec![vec![]]
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        let x = Constraint::gt(sym("x"), num(1));
+        let y = Constraint::eq(sym("y"), num(2));
+        let path = vec![
+            Constraint::True,
+            x.clone().and(y.clone().and(x.clone())),
+            Constraint::eq(sym("z"), sym("z")),
+            y.clone().or(!y.clone()),
+            Constraint::lt(num(1), num(2)),
+            y.clone(),
+        ];
+
+        assert!(normalize(&path) == vec![x.clone(), y.clone()]);
+
+        let contradictions = [
+            vec![x.clone(), Constraint::lte(sym("x"), num(1))],
+            vec![y.clone(), !(y.clone().or(Constraint::False))],
+            vec![Constraint::neq(sym("x"), sym("x"))],
+            vec![x.clone().and(Constraint::gte(num(1), num(2)))],
+        ];
+        for path in contradictions {
+            assert!(normalize(&path) == vec![Constraint::False]);
+        }
+    }
+
+    #[test]
+    fn test_normalize_onto() {
+        let x = Constraint::gt(sym("x"), num(1));
+        let y = Constraint::eq(sym("y"), num(2));
+        let path = normalize(std::slice::from_ref(&x));
+        let known: HashSet<&Constraint> = path.iter().collect();
+
+        assert!(normalize_onto(&known, &[y.clone().and(x.clone())]) == Some(vec![y.clone()]));
+        assert!(normalize_onto(&known, &[Constraint::True]) == Some(vec![]));
+        assert!(normalize_onto(&known, &[!x.clone()]).is_none());
+        assert!(normalize_onto(&known, &[y.clone(), !y]).is_none());
+    }
+}
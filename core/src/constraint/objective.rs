@@ -0,0 +1,166 @@
+use super::*;
+use crate::value::{CNumber, CSimpleVal, Value};
+
+// A numeric sentence to optimize, compared as an unsigned value
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Objective {
+    Minimize(Val),
+    Maximize(Val),
+}
+
+impl Objective {
+    pub fn term(&self) -> &Val {
+        match self {
+            Objective::Minimize(t) | Objective::Maximize(t) => t,
+        }
+    }
+}
+
+pub struct Optimum<M> {
+    pub model: M,
+    pub value: CNumber,
+}
+
+pub trait Optimize<Ast, G>: Solver<Ast, G> {
+    // A model of the asserted constraints that is optimal for `objective`, along with the
+    // optimal value. The asserted constraints are left as they were.
+    fn optimize(&mut self, objective: &Objective) -> SatResult<Optimum<Self::Model>>;
+
+    // Optimizes under a path condition, e.g. one reported by `run_with_solver`
+    fn optimize_under(
+        &mut self,
+        path: &[Constraint],
+        objective: &Objective,
+    ) -> SatResult<Optimum<Self::Model>> {
+        self.push();
+        for c in path {
+            self.generic_assert(c);
+        }
+        let res = self.optimize(objective);
+        self.pop();
+        res
+    }
+}
+
+// Optimizes with a binary search over the value of the objective, which is `width` bits
+// wide, taking up to `width + 1` queries. For solvers without native optimization.
+pub fn bisect<S, Ast, G>(
+    solver: &mut S,
+    objective: &Objective,
+    width: u32,
+) -> SatResult<Optimum<S::Model>>
+where
+    S: Solver<Ast, G>,
+{
+    let num = |v: u128| {
+        Val::Basic(Value::Concrete(CSimpleVal::Number(
+            CNumber::from_u128_with_width(v, width),
+        )))
+    };
+    let term = objective.term();
+
+    let mut best = match solver.solve() {
+        SatResult::Sat(m) => m,
+        res => return res.map_sat(),
+    };
+    let (mut lo, mut hi) = (0u128, u128::MAX >> (128 - width.clamp(1, 128)));
+
+    // Some value in [lo, hi] is attainable, and `best` attains the bound being moved
+    while lo < hi {
+        let (mid, bound) = match objective {
+            Objective::Minimize(_) => {
+                let mid = lo + (hi - lo) / 2;
+                (mid, Constraint::lte(term.clone(), num(mid)))
+            }
+            Objective::Maximize(_) => {
+                let mid = lo + (hi - lo) / 2 + (hi - lo) % 2;
+                (mid, Constraint::gte(term.clone(), num(mid)))
+            }
+        };
+
+        solver.push();
+        solver.generic_assert(&bound);
+        let res = solver.solve();
+        solver.pop();
+
+        match (res, objective) {
+            (SatResult::Sat(m), Objective::Minimize(_)) => {
+                hi = mid;
+                best = m;
+            }
+            (SatResult::Sat(m), Objective::Maximize(_)) => {
+                lo = mid;
+                best = m;
+            }
+            (SatResult::Unsat, Objective::Minimize(_)) => lo = mid + 1,
+            (SatResult::Unsat, Objective::Maximize(_)) => hi = mid - 1,
+            (SatResult::Unknown, _) => return SatResult::Unknown,
+        }
+    }
+
+    SatResult::Sat(Optimum {
+        model: best,
+        value: CNumber::from_u128_with_width(lo, width),
+    })
+}
+
+impl<M> SatResult<M> {
+    // For results known not to be `Sat`
+    fn map_sat<N>(self) -> SatResult<N> {
+        match self {
+            SatResult::Sat(_) => unreachable!(),
+            SatResult::Unsat => SatResult::Unsat,
+            SatResult::Unknown => SatResult::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constraint::mock::MockSolver;
+    use crate::test_util::{num, sym};
+
+    #[test]
+    fn test_bisect() {
+        let mut solver = MockSolver {
+            domain: Some((3, 10)),
+            ..Default::default()
+        };
+
+        let min = bisect(&mut solver, &Objective::Minimize(sym("x")), 8);
+        assert!(matches!(min, SatResult::Sat(o) if o.value == CNumber::U8(3)));
+
+        let max = bisect(&mut solver, &Objective::Maximize(sym("x")), 8);
+        assert!(matches!(
+            max,
+            SatResult::Sat(o) if o.value == CNumber::U8(10)
+                && o.model.get("x") == Some(&CSimpleVal::Number(CNumber::U64(10)))
+        ));
+        assert!(solver.balanced());
+
+        solver.generic_assert(&Constraint::False);
+        let unsat = bisect(&mut solver, &Objective::Maximize(sym("x")), 8);
+        assert!(matches!(unsat, SatResult::Unsat));
+        // Each search takes at most one query per bit after the first, and an unsat path
+        // takes just that first one
+        assert!(solver.solves.get() <= 2 * (8 + 1) + 1);
+    }
+
+    #[test]
+    fn test_optimize_under_path() {
+        let mut solver = MockSolver {
+            domain: Some((3, 10)),
+            ..Default::default()
+        };
+        let path = vec![
+            Constraint::gte(sym("x"), num(5)),
+            Constraint::lt(sym("x"), num(8)),
+        ];
+
+        let max = solver.optimize_under(&path, &Objective::Maximize(sym("x")));
+
+        assert!(matches!(max, SatResult::Sat(o) if o.value == CNumber::U64(7)));
+        assert!(solver.balanced());
+    }
+}
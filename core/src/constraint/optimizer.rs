@@ -0,0 +1,618 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::marker::PhantomData;
+
+use super::*;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueryStats {
+    pub queries: usize,
+    // Independent constraint groups the queries were split into
+    pub groups: usize,
+    pub cache_hits: usize,
+    pub solver_calls: usize,
+}
+
+// Results are keyed by the set of interned ids of a group's normalized constraints, so the
+// key doesn't depend on assertion order, duplicates or how conjunctions are nested
+type GroupKey = BTreeSet<usize>;
+
+pub const DEFAULT_CACHE_CAPACITY: usize = 4096;
+
+struct QueryCache {
+    capacity: usize,
+    ids: HashMap<Constraint, usize>,
+    next_id: usize,
+    exact: HashMap<GroupKey, SatResult<Model>>,
+    // Sat groups and unsat cores, with indices from each id to the entries containing it
+    sat: Vec<(GroupKey, Model)>,
+    sat_index: HashMap<usize, Vec<usize>>,
+    unsat: Vec<GroupKey>,
+    unsat_index: HashMap<usize, Vec<usize>>,
+}
+
+impl QueryCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ids: HashMap::new(),
+            next_id: 0,
+            exact: HashMap::new(),
+            sat: vec![],
+            sat_index: HashMap::new(),
+            unsat: vec![],
+            unsat_index: HashMap::new(),
+        }
+    }
+
+    fn key(&mut self, group: &[Constraint]) -> GroupKey {
+        normalize(group)
+            .into_iter()
+            .map(|c| {
+                *self.ids.entry(c).or_insert_with(|| {
+                    self.next_id += 1;
+                    self.next_id - 1
+                })
+            })
+            .collect()
+    }
+
+    // An exact match, any known unsat subset, or any known sat superset (whose model
+    // then satisfies the group too)
+    fn lookup(&self, key: &GroupKey) -> Option<SatResult<Model>> {
+        match self.exact.get(key) {
+            Some(SatResult::Sat(m)) => return Some(SatResult::Sat(m.clone())),
+            Some(SatResult::Unsat) => return Some(SatResult::Unsat),
+            _ => {}
+        }
+        if self.unsat_subset(key).is_some() {
+            return Some(SatResult::Unsat);
+        }
+        self.sat_superset(key).map(|m| SatResult::Sat(m.clone()))
+    }
+
+    // Only cached sets sharing an id with `key` can be its subsets
+    fn unsat_subset(&self, key: &GroupKey) -> Option<&GroupKey> {
+        let mut hits: HashMap<usize, usize> = HashMap::new();
+        for id in key {
+            for &u in self.unsat_index.get(id).into_iter().flatten() {
+                let n = hits.entry(u).or_insert(0);
+                *n += 1;
+                if *n == self.unsat[u].len() {
+                    return Some(&self.unsat[u]);
+                }
+            }
+        }
+        None
+    }
+
+    // Supersets of `key` contain its rarest id
+    fn sat_superset(&self, key: &GroupKey) -> Option<&Model> {
+        let rarest = key
+            .iter()
+            .map(|id| self.sat_index.get(id).map_or(&[][..], |v| &v[..]))
+            .min_by_key(|v| v.len())?;
+        rarest
+            .iter()
+            .map(|&s| &self.sat[s])
+            .find(|(s, _)| key.is_subset(s))
+            .map(|(_, m)| m)
+    }
+
+    // `core` is the part of an unsat group that is already unsatisfiable. A full cache is
+    // flushed first, so that the new entry starts the fresh cache.
+    fn insert(&mut self, key: GroupKey, res: &SatResult<Model>, core: Option<GroupKey>) {
+        if matches!(res, SatResult::Unknown) {
+            // Unknown results may well be decided by a later attempt
+            return;
+        }
+        if self.exact.len() >= self.capacity {
+            // The new entry's constraints keep their ids
+            let mut ids = std::mem::take(&mut self.ids);
+            ids.retain(|_, id| key.contains(id));
+            *self = Self {
+                ids,
+                next_id: self.next_id,
+                ..Self::new(self.capacity)
+            };
+        }
+        match res {
+            SatResult::Sat(m) => {
+                for id in &key {
+                    self.sat_index.entry(*id).or_default().push(self.sat.len());
+                }
+                self.sat.push((key.clone(), m.clone()));
+                self.exact.insert(key, SatResult::Sat(m.clone()));
+            }
+            SatResult::Unsat => {
+                let core = core
+                    .filter(|c| !c.is_empty())
+                    .unwrap_or_else(|| key.clone());
+                for id in &core {
+                    self.unsat_index
+                        .entry(*id)
+                        .or_default()
+                        .push(self.unsat.len());
+                }
+                self.unsat.push(core);
+                self.exact.insert(key, SatResult::Unsat);
+            }
+            SatResult::Unknown => {}
+        }
+    }
+}
+
+// Splits constraints into groups that share no symbols. Constraints without symbols
+// all end up in a single group.
+pub fn independent_groups(constraints: &[Constraint]) -> Vec<Vec<Constraint>> {
+    // Union-find over constraint indices, joined through the symbols they mention
+    let mut parent: Vec<usize> = (0..constraints.len()).collect();
+    fn find(parent: &mut [usize], i: usize) -> usize {
+        let mut root = i;
+        while parent[root] != root {
+            root = parent[root];
+        }
+        parent[i] = root;
+        root
+    }
+
+    let mut owner: HashMap<String, usize> = HashMap::new();
+    let mut ground: Option<usize> = None;
+    for (i, c) in constraints.iter().enumerate() {
+        let symbols = c.symbols();
+        if symbols.is_empty() {
+            let g = *ground.get_or_insert(i);
+            let (a, b) = (find(&mut parent, g), find(&mut parent, i));
+            parent[b] = a;
+        }
+        for s in symbols {
+            let j = *owner.entry(s).or_insert(i);
+            let (a, b) = (find(&mut parent, j), find(&mut parent, i));
+            parent[b] = a;
+        }
+    }
+
+    let mut groups: BTreeMap<usize, Vec<Constraint>> = BTreeMap::new();
+    for (i, c) in constraints.iter().enumerate() {
+        let root = find(&mut parent, i);
+        groups.entry(root).or_default().push(c.clone());
+    }
+    groups.into_values().collect()
+}
+
+// A `Solver` that sits in front of another solver. Each query is split into
+// independent groups of constraints, and each group is answered from a cache when
+// possible, so after a fork only the group touched by the new branch constraints
+// reaches the underlying solver. Groups are solved from scratch in a fresh scope of
+// the underlying solver, whose own assertion stack is left untouched otherwise.
+pub struct QueryOptimizer<S, Ast = (), G = ()> {
+    inner: RefCell<S>,
+    scopes: Vec<Vec<(Option<String>, Constraint)>>,
+    cache: RefCell<QueryCache>,
+    stats: RefCell<QueryStats>,
+    unknown: RefCell<Option<UnknownReason>>,
+    core: RefCell<Option<Vec<String>>>,
+    _transpiled: PhantomData<fn() -> (Ast, G)>,
+}
+
+impl<S, Ast, G> QueryOptimizer<S, Ast, G> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner: RefCell::new(inner),
+            scopes: vec![vec![]],
+            cache: RefCell::new(QueryCache::new(DEFAULT_CACHE_CAPACITY)),
+            stats: RefCell::new(QueryStats::default()),
+            unknown: RefCell::new(None),
+            core: RefCell::new(None),
+            _transpiled: PhantomData,
+        }
+    }
+
+    // Cached results kept before the cache is flushed, `DEFAULT_CACHE_CAPACITY` by default
+    pub fn with_cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache = RefCell::new(QueryCache::new(capacity));
+        self
+    }
+
+    pub fn stats(&self) -> QueryStats {
+        self.stats.borrow().clone()
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner.into_inner()
+    }
+
+    fn asserted(&self) -> Vec<Constraint> {
+        let mut seen = HashMap::new();
+        self.scopes
+            .iter()
+            .flatten()
+            .map(|(_, c)| c)
+            .filter(|c| **c != Constraint::True && seen.insert(*c, ()).is_none())
+            .cloned()
+            .collect()
+    }
+
+    // The label `c` was first asserted under, if any
+    fn label(&self, c: &Constraint) -> Option<String> {
+        self.scopes
+            .iter()
+            .flatten()
+            .find(|(l, x)| l.is_some() && x == c)
+            .and_then(|(l, _)| l.clone())
+    }
+
+    // The labels of the constraints of `group` behind the cached unsat result for `key`
+    fn cached_core(&self, group: &[Constraint], key: &GroupKey) -> Vec<String> {
+        let mut cache = self.cache.borrow_mut();
+        let core = cache.unsat_subset(key).unwrap_or(key).clone();
+        group
+            .iter()
+            .filter(|c| {
+                let ids = cache.key(std::slice::from_ref(*c));
+                !ids.is_disjoint(&core)
+            })
+            .filter_map(|c| self.label(c))
+            .collect()
+    }
+}
+
+impl<S, Ast, G> QueryOptimizer<S, Ast, G>
+where
+    S: Solver<Ast, G, Model = Model>,
+{
+    fn solve_group(&self, group: &[Constraint]) -> SatResult<Model> {
+        let key = self.cache.borrow_mut().key(group);
+        let cached = self.cache.borrow().lookup(&key);
+        if let Some(res) = cached {
+            self.stats.borrow_mut().cache_hits += 1;
+            if let SatResult::Unsat = res {
+                *self.core.borrow_mut() = Some(self.cached_core(group, &key));
+            }
+            return res;
+        }
+
+        self.stats.borrow_mut().solver_calls += 1;
+        let mut inner = self.inner.borrow_mut();
+        inner.push();
+        for c in group {
+            match self.label(c) {
+                Some(label) => inner.assert_labelled(&label, c),
+                None => inner.generic_assert(c),
+            }
+        }
+        let res = inner.solve();
+        let mut core_key = None;
+        match res {
+            SatResult::Unknown => *self.unknown.borrow_mut() = inner.reason_unknown(),
+            SatResult::Unsat => {
+                let core = inner.unsat_core();
+                // Caching only the core (and the unlabelled constraints it may rely on) lets
+                // it rule out more queries later
+                if let Some(labels) = &core {
+                    let core_constraints: Vec<Constraint> = group
+                        .iter()
+                        .filter(|c| match self.label(c) {
+                            Some(l) => labels.contains(&l),
+                            None => true,
+                        })
+                        .cloned()
+                        .collect();
+                    core_key = Some(self.cache.borrow_mut().key(&core_constraints));
+                }
+                *self.core.borrow_mut() = core;
+            }
+            SatResult::Sat(_) => {}
+        }
+        inner.pop();
+
+        self.cache.borrow_mut().insert(key, &res, core_key);
+        res
+    }
+}
+
+impl<S, Ast, G> Constrained for QueryOptimizer<S, Ast, G>
+where
+    S: Solver<Ast, G, Model = Model>,
+{
+    type Model = Model;
+
+    fn check(&self) -> SatResult<Model> {
+        let groups = independent_groups(&self.asserted());
+        *self.unknown.borrow_mut() = None;
+        *self.core.borrow_mut() = None;
+        {
+            let mut stats = self.stats.borrow_mut();
+            stats.queries += 1;
+            stats.groups += groups.len();
+        }
+
+        let mut model = Model::new();
+        let mut unknown = false;
+        for group in groups {
+            let symbols: BTreeSet<String> = group.iter().flat_map(|c| c.symbols()).collect();
+            match self.solve_group(&group) {
+                // A cached model may come from a larger query, so only keep what this group decides
+                SatResult::Sat(m) => model.merge(m.restrict(&symbols)),
+                SatResult::Unsat => return SatResult::Unsat,
+                SatResult::Unknown => unknown = true,
+            }
+        }
+
+        if unknown {
+            SatResult::Unknown
+        } else {
+            SatResult::Sat(model)
+        }
+    }
+}
+
+impl<S, Ast, G> Solver<Ast, G> for QueryOptimizer<S, Ast, G>
+where
+    S: Solver<Ast, G, Model = Model>,
+{
+    fn generic_assert(&mut self, constraint: &Constraint) {
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .push((None, constraint.clone()));
+    }
+
+    fn solve(&self) -> SatResult<Model> {
+        self.check()
+    }
+
+    fn push(&mut self) {
+        self.scopes.push(vec![]);
+    }
+
+    fn pop(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    fn set_limits(&mut self, limits: &SolverLimits) {
+        self.inner.get_mut().set_limits(limits);
+    }
+
+    fn reason_unknown(&self) -> Option<UnknownReason> {
+        self.unknown.borrow().clone()
+    }
+
+    fn assert_labelled(&mut self, label: &str, constraint: &Constraint) {
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .push((Some(label.to_string()), constraint.clone()));
+    }
+
+    fn unsat_core(&self) -> Option<Vec<String>> {
+        self.core.borrow().clone()
+    }
+}
+
+impl<S, Ast, G> Transpile<Ast, G> for QueryOptimizer<S, Ast, G>
+where
+    S: Solver<Ast, G, Model = Model>,
+{
+    fn val_to_ground_type(&self, v: Val) -> G {
+        self.inner.borrow().val_to_ground_type(v)
+    }
+
+    fn ground_type_to_val(&self, g: G) -> Val {
+        self.inner.borrow().ground_type_to_val(g)
+    }
+
+    fn val_to_bool(&self, v: Val) -> Ast {
+        self.inner.borrow().val_to_bool(v)
+    }
+
+    fn assert(&self, c: Ast) -> Ast {
+        self.inner.borrow().assert(c)
+    }
+
+    fn and(&self, l: Ast, r: Ast) -> Ast {
+        self.inner.borrow().and(l, r)
+    }
+
+    fn not(&self, c: Ast) -> Ast {
+        self.inner.borrow().not(c)
+    }
+
+    fn or(&self, l: Ast, r: Ast) -> Ast {
+        self.inner.borrow().or(l, r)
+    }
+
+    fn gt(&self, l: G, r: G) -> Ast {
+        self.inner.borrow().gt(l, r)
+    }
+
+    fn lt(&self, l: G, r: G) -> Ast {
+        self.inner.borrow().lt(l, r)
+    }
+
+    fn lte(&self, l: G, r: G) -> Ast {
+        self.inner.borrow().lte(l, r)
+    }
+
+    fn gte(&self, l: G, r: G) -> Ast {
+        self.inner.borrow().gte(l, r)
+    }
+
+    fn eq(&self, l: G, r: G) -> Ast {
+        self.inner.borrow().eq(l, r)
+    }
+
+    fn neq(&self, l: G, r: G) -> Ast {
+        self.inner.borrow().neq(l, r)
+    }
+
+    fn ite(&self, c: Ast, t: Ast, e: Ast) -> Ast {
+        self.inner.borrow().ite(c, t, e)
+    }
+
+    fn true_(&self) -> Ast {
+        self.inner.borrow().true_()
+    }
+
+    fn false_(&self) -> Ast {
+        self.inner.borrow().false_()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constraint::mock::MockSolver;
+    use crate::test_util::{num, sym};
+
+    #[test]
+    fn test_independent_groups() {
+        let groups = independent_groups(&[
+            Constraint::gt(sym("x"), num(1)),
+            Constraint::gt(sym("y"), num(2)),
+            Constraint::True,
+            Constraint::lt(sym("z"), sym("x")),
+            Constraint::False,
+        ]);
+
+        let symbols: Vec<Vec<String>> = groups
+            .iter()
+            .map(|g| {
+                g.iter()
+                    .flat_map(|c| c.symbols())
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            symbols,
+            vec![
+                vec!["x".to_string(), "z".to_string()],
+                vec!["y".to_string()],
+                vec![]
+            ]
+        );
+        assert_eq!(groups[2].len(), 2);
+    }
+
+    #[test]
+    fn test_only_the_touched_group_is_solved() {
+        let mut solver = QueryOptimizer::new(MockSolver::default());
+
+        solver.generic_assert(&Constraint::gt(sym("x"), num(1)));
+        solver.generic_assert(&Constraint::gt(sym("y"), num(2)));
+        let res = solver.solve();
+        assert!(matches!(res, SatResult::Sat(ref m) if m.len() == 2));
+
+        solver.push();
+        solver.generic_assert(&Constraint::lt(sym("x"), num(5)));
+        assert!(matches!(solver.solve(), SatResult::Sat(_)));
+
+        assert_eq!(
+            solver.stats(),
+            QueryStats {
+                queries: 2,
+                groups: 4,
+                cache_hits: 1,
+                solver_calls: 3,
+            }
+        );
+        // Groups are solved in their own scope of the underlying solver
+        assert!(solver.into_inner().balanced());
+    }
+
+    #[test]
+    fn test_unsat_subsets_and_sat_supersets_are_reused() {
+        let mut solver = QueryOptimizer::new(MockSolver::default());
+
+        solver.push();
+        solver.generic_assert(&Constraint::False);
+        assert!(matches!(solver.solve(), SatResult::Unsat));
+        solver.generic_assert(&Constraint::eq(num(1), num(2)));
+        assert!(matches!(solver.solve(), SatResult::Unsat));
+        solver.pop();
+
+        solver.push();
+        solver.generic_assert(&Constraint::gt(sym("x"), num(1)));
+        solver.generic_assert(&Constraint::lt(sym("x"), num(5)));
+        assert!(matches!(solver.solve(), SatResult::Sat(_)));
+        solver.pop();
+
+        solver.generic_assert(&Constraint::lt(sym("x"), num(5)));
+        assert!(matches!(solver.solve(), SatResult::Sat(ref m) if m.len() == 1));
+
+        let stats = solver.stats();
+        assert_eq!(stats.solver_calls, 2);
+        assert_eq!(stats.cache_hits, 2);
+    }
+
+    #[test]
+    fn test_keys_are_normalized() {
+        let mut solver = QueryOptimizer::new(MockSolver::default());
+        let a = Constraint::gt(sym("x"), num(1));
+        let b = Constraint::lt(sym("x"), num(5));
+
+        solver.push();
+        solver.generic_assert(&a.clone().and(b.clone()));
+        assert!(matches!(solver.solve(), SatResult::Sat(_)));
+        solver.pop();
+
+        solver.push();
+        solver.generic_assert(&b.clone().and(a.clone()));
+        assert!(matches!(solver.solve(), SatResult::Sat(_)));
+        solver.pop();
+
+        // `!(x <= 1)` normalizes to `x > 1`
+        solver.generic_assert(&!Constraint::lte(sym("x"), num(1)));
+        solver.generic_assert(&b);
+        assert!(matches!(solver.solve(), SatResult::Sat(_)));
+
+        assert_eq!(solver.stats().solver_calls, 1);
+        assert_eq!(solver.stats().cache_hits, 2);
+    }
+
+    #[test]
+    fn test_full_cache_is_flushed() {
+        let mut solver = QueryOptimizer::new(MockSolver::default()).with_cache_capacity(2);
+        let queries = [
+            Constraint::gt(sym("x"), num(1)),
+            Constraint::gt(sym("y"), num(1)),
+            Constraint::gt(sym("z"), num(1)),
+            Constraint::gt(sym("z"), num(1)),
+            Constraint::gt(sym("x"), num(1)),
+        ];
+
+        for c in &queries {
+            solver.push();
+            solver.generic_assert(c);
+            assert!(matches!(solver.solve(), SatResult::Sat(_)));
+            solver.pop();
+        }
+
+        // The third result flushed the full cache and was kept, the first was not
+        assert_eq!(solver.stats().cache_hits, 1);
+        assert_eq!(solver.stats().solver_calls, 4);
+    }
+
+    #[test]
+    fn test_unsat_cores() {
+        let mut solver = QueryOptimizer::new(MockSolver::default());
+
+        solver.assert_labelled("x", &Constraint::gt(sym("x"), num(1)));
+        solver.assert_labelled("false", &Constraint::False);
+        solver.assert_labelled("ground", &Constraint::eq(num(1), num(1)));
+        assert!(matches!(solver.solve(), SatResult::Unsat));
+        assert_eq!(solver.unsat_core(), Some(vec!["false".to_string()]));
+
+        // Answered from the cached core, under the labels of the current assertions
+        solver.push();
+        solver.assert_labelled("again", &Constraint::False);
+        assert!(matches!(solver.solve(), SatResult::Unsat));
+        assert_eq!(solver.unsat_core(), Some(vec!["false".to_string()]));
+        solver.pop();
+        assert_eq!(solver.stats().solver_calls, 2);
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use super::*;
+use crate::value::{rename_value, same_node, Folder, Replace, Value};
+
+// Replaces leaves in every sentence of a constraint, sharing both rewritten sentences and
+// rewritten compound nodes
+struct ConstraintRewriter<F> {
+    leaves: Folder<Replace<F>>,
+    memo: HashMap<*const Constraint, Option<Rc<Constraint>>>,
+}
+
+fn pick<T: Clone>(new: Option<T>, old: &T) -> T {
+    new.unwrap_or_else(|| old.clone())
+}
+
+impl<F: FnMut(&Value) -> Option<Val>> ConstraintRewriter<F> {
+    fn new(replace: F) -> Self {
+        Self {
+            leaves: Folder::new(Replace(replace)),
+            memo: HashMap::new(),
+        }
+    }
+
+    // `None` if no sentence in `c` changes
+    fn rewrite(&mut self, c: &Constraint) -> Option<Constraint> {
+        match c {
+            Constraint::True | Constraint::False => None,
+            Constraint::Assert(n) => Some(Constraint::Assert(self.node(n)?)),
+            Constraint::Not(n) => Some(Constraint::Not(self.node(n)?)),
+            Constraint::And(l, r) | Constraint::Or(l, r) => {
+                let (new_l, new_r) = (self.node(l), self.node(r));
+                if new_l.is_none() && new_r.is_none() {
+                    return None;
+                }
+                let (l, r) = (pick(new_l, l), pick(new_r, r));
+                Some(match c {
+                    Constraint::And(..) => Constraint::And(l, r),
+                    _ => Constraint::Or(l, r),
+                })
+            }
+            Constraint::BinCmp(cmp) => {
+                let (l, r) = cmp.operands();
+                let (new_l, new_r) = (self.node(l), self.node(r));
+                if new_l.is_none() && new_r.is_none() {
+                    return None;
+                }
+                let (l, r) = (pick(new_l, l), pick(new_r, r));
+                Some(Constraint::BinCmp(match cmp {
+                    CmpType::GT(..) => CmpType::GT(l, r),
+                    CmpType::LT(..) => CmpType::LT(l, r),
+                    CmpType::GTE(..) => CmpType::GTE(l, r),
+                    CmpType::LTE(..) => CmpType::LTE(l, r),
+                    CmpType::EQ(..) => CmpType::EQ(l, r),
+                    CmpType::NEQ(..) => CmpType::NEQ(l, r),
+                }))
+            }
+            Constraint::Ite(i, t, e) => {
+                let (new_i, new_t, new_e) = (self.compound(i), self.node(t), self.node(e));
+                if new_i.is_none() && new_t.is_none() && new_e.is_none() {
+                    return None;
+                }
+                Some(Constraint::Ite(
+                    pick(new_i, i),
+                    pick(new_t, t),
+                    pick(new_e, e),
+                ))
+            }
+        }
+    }
+
+    fn node(&mut self, n: &Node) -> Option<Node> {
+        match n {
+            Node::Simple(s) => {
+                let folded = self.leaves.fold(s);
+                (!same_node(&folded, s)).then_some(Node::Simple(folded))
+            }
+            Node::Compound(c) => self.compound(c).map(Node::Compound),
+        }
+    }
+
+    fn compound(&mut self, c: &Rc<Constraint>) -> Option<Rc<Constraint>> {
+        let key = Rc::as_ptr(c);
+        if let Some(done) = self.memo.get(&key) {
+            return done.clone();
+        }
+        let done = self.rewrite(c).map(Rc::new);
+        self.memo.insert(key, done.clone());
+        done
+    }
+}
+
+impl Constraint {
+    // As `Sentence::substitute`, over every sentence in the constraint
+    pub fn substitute(&self, subst: &HashMap<String, Val>) -> Constraint {
+        ConstraintRewriter::new(|v: &Value| subst.get(v.symbol()?).cloned())
+            .rewrite(self)
+            .unwrap_or_else(|| self.clone())
+    }
+
+    pub fn rename(&self, names: &HashMap<String, String>) -> Constraint {
+        ConstraintRewriter::new(|v: &Value| rename_value(v, names))
+            .rewrite(self)
+            .unwrap_or_else(|| self.clone())
+    }
+}
+
+impl Model {
+    // Each assignment as a concrete sentence, to plug the model into a sentence or
+    // constraint with `substitute`
+    pub fn substitution(&self) -> HashMap<String, Val> {
+        self.iter()
+            .map(|(k, v)| (k.clone(), Val::Basic(Value::Concrete(v.clone()))))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{num, sym};
+    use crate::value::{CNumber, CSimpleVal};
+
+    #[test]
+    fn test_substitute_model() {
+        let x = Constraint::gt(sym("x"), num(1));
+        let y = Constraint::eq(sym("y"), "(+ x 1)".parse().unwrap());
+        let c = Constraint::ite(x.clone(), y.clone(), x.clone());
+
+        let mut model = Model::new();
+        model.insert("y", CSimpleVal::Number(CNumber::U64(4)));
+        let c2 = c.substitute(&model.substitution());
+
+        assert!(
+            c2 == Constraint::ite(
+                x.clone(),
+                Constraint::eq(num(4), "(+ x 1)".parse().unwrap()),
+                x.clone()
+            )
+        );
+        assert_eq!(c2.free_symbols().keys().collect::<Vec<_>>(), vec!["x"]);
+        match (&c, &c2) {
+            (Constraint::Ite(i, ..), Constraint::Ite(i2, ..)) => assert!(Rc::ptr_eq(i, i2)),
+            _ => unreachable!(),
+        }
+
+        model.insert("x", CSimpleVal::Number(CNumber::U64(3)));
+        assert!(c
+            .substitute(&model.substitution())
+            .free_symbols()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_rename() {
+        let c = Constraint::lt(sym("x"), sym("y")).and(Constraint::eq(sym("x"), num(0)));
+        let names = HashMap::from([
+            ("x".to_string(), "y".to_string()),
+            ("y".to_string(), "x".to_string()),
+        ]);
+
+        assert!(
+            c.rename(&names)
+                == Constraint::lt(sym("y"), sym("x")).and(Constraint::eq(sym("y"), num(0)))
+        );
+        assert!(c.rename(&names).rename(&names) == c);
+    }
+}
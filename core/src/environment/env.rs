@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 use super::record::EnvRecord;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Env {
     
 }
@@ -1,9 +1,16 @@
 use thiserror::{self, Error};
 
+use crate::memory::MemoryError;
+use crate::storage::StorageError;
+
 #[derive(Debug, Error)]
 pub enum InstructionError {
     #[error("Unrecognized instruction {0}")]
     UnknownInstruction(String),
     #[error("Failed to execute instruction {0}")]
     InstructionExecutionFailure(String),
+    #[error(transparent)]
+    MemoryError(#[from] MemoryError),
+    #[error(transparent)]
+    StorageError(#[from] StorageError),
 }
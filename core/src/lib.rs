@@ -3,5 +3,12 @@ pub mod environment;
 pub mod instructions;
 pub mod machine;
 pub mod memory;
+pub mod pretty;
+pub mod script;
+mod serialize;
+pub mod sexpr;
 pub mod stack;
+pub mod storage;
+#[cfg(test)]
+mod test_util;
 pub mod value;
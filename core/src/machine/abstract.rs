@@ -1,16 +1,24 @@
+use serde::{Deserialize, Serialize};
+
 use crate::{
     environment::{Env, EnvRecord},
-    memory::{MemRecord, Memory},
+    memory::{Fault, MemRecord, Memory},
     stack::{Stack, StackRecord},
+    storage::{Storage, StorageRecord},
 };
 
+use super::MachineResult;
+
 #[derive(Clone)]
 pub struct AbstractMachine<'a, I> {
     pub stack: Stack,
     pub mem: Memory,
     pub env: Env,
+    pub storage: Storage,
     pub pc: Option<usize>,
     pub pgm: &'a [I],
+    // Why the machine halted, if a memory access faulted
+    pub fault: Option<Fault>,
 }
 // NOTE(will): For some reason, calling `.clone` directly on
 // `AbstractMachine` requires that `I` implement `Clone`. `I` is behind
@@ -22,8 +30,33 @@ impl<'a, I> AbstractMachine<'a, I> {
             stack: self.stack.clone(),
             mem: self.mem.clone(),
             env: self.env.clone(),
+            storage: self.storage.clone(),
             pc: self.pc,
             pgm: self.pgm,
+            fault: self.fault.clone(),
+        }
+    }
+
+    // The next invocation in a session: `pgm` runs from the start with an empty stack and
+    // `mem`, on the storage and environment this machine left behind
+    pub fn next_invocation<'b, J>(&self, pgm: &'b [J], mem: Memory) -> AbstractMachine<'b, J> {
+        AbstractMachine {
+            stack: Stack::default(),
+            mem,
+            env: self.env.clone(),
+            storage: self.storage.clone(),
+            pc: Some(0),
+            pgm,
+            fault: None,
+        }
+    }
+
+    // The machine halted on `fault`
+    pub fn faulted(self, fault: Fault) -> Self {
+        AbstractMachine {
+            pc: None,
+            fault: Some(fault),
+            ..self
         }
     }
 
@@ -31,13 +64,15 @@ impl<'a, I> AbstractMachine<'a, I> {
         self,
         stack_diff: Option<StackRecord>,
         mem_diff: Option<MemRecord>,
+        storage_diff: Option<StorageRecord>,
         env_diff: Option<EnvRecord>,
         pc_change: Option<usize>,
         halt: bool,
-    ) -> Self {
+    ) -> MachineResult<Self> {
         let mut stack = self.stack;
         let mut mem = self.mem;
         let mut env = self.env;
+        let mut storage = self.storage;
 
         stack = {
             if let Some(stack_diff) = stack_diff {
@@ -49,12 +84,20 @@ impl<'a, I> AbstractMachine<'a, I> {
 
         mem = {
             if let Some(mem_diff) = mem_diff {
-                mem.apply(mem_diff)
+                mem.apply(mem_diff)?
             } else {
                 mem
             }
         };
 
+        storage = {
+            if let Some(storage_diff) = storage_diff {
+                storage.apply(storage_diff)?
+            } else {
+                storage
+            }
+        };
+
         env = {
             if let Some(env_diff) = env_diff {
                 env.apply(env_diff)
@@ -72,17 +115,55 @@ impl<'a, I> AbstractMachine<'a, I> {
             }
         };
 
-        AbstractMachine {
+        Ok(AbstractMachine {
             stack,
             mem,
             env,
+            storage,
             pc,
             pgm: self.pgm,
-        }
+            fault: self.fault,
+        })
     }
 }
 
+// A machine without its program, e.g. to save exploration results and replay them later
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MachineState {
+    pub stack: Stack,
+    pub mem: Memory,
+    pub env: Env,
+    #[serde(default)]
+    pub storage: Storage,
+    pub pc: Option<usize>,
+    #[serde(default)]
+    pub fault: Option<Fault>,
+}
+
 impl<'a, I> AbstractMachine<'a, I> {
+    pub fn state(&self) -> MachineState {
+        MachineState {
+            stack: self.stack.clone(),
+            mem: self.mem.clone(),
+            env: self.env.clone(),
+            storage: self.storage.clone(),
+            pc: self.pc,
+            fault: self.fault.clone(),
+        }
+    }
+
+    pub fn from_state(state: MachineState, pgm: &'a [I]) -> Self {
+        AbstractMachine {
+            stack: state.stack,
+            mem: state.mem,
+            env: state.env,
+            storage: state.storage,
+            pc: state.pc,
+            pgm,
+            fault: state.fault,
+        }
+    }
+
     pub fn can_continue(&self) -> bool {
         match self.pc {
             Some(pc) => pc < self.pgm.len(),
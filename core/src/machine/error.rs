@@ -1,9 +1,51 @@
 use thiserror::{self, Error};
 
 use crate::instructions::error::InstructionError;
+use crate::memory::{Fault, MemoryError, SymbolicRegionAccess};
+use crate::stack::StackVal;
+use crate::storage::StorageError;
 
 #[derive(Debug, Error)]
 pub enum MachineError {
     #[error(transparent)]
     InstructionError(#[from] InstructionError),
+    #[error(transparent)]
+    MemoryError(#[from] MemoryError),
+    #[error(transparent)]
+    StorageError(#[from] StorageError),
+}
+
+impl MachineError {
+    // The address of a failed access at a symbolic address, from memory or an instruction
+    pub fn symbolic_address(&self) -> Option<&StackVal> {
+        match self {
+            MachineError::MemoryError(MemoryError::SymbolicAddress(a))
+            | MachineError::InstructionError(InstructionError::MemoryError(
+                MemoryError::SymbolicAddress(a),
+            )) => Some(a),
+            _ => None,
+        }
+    }
+
+    // An access at a symbolic address that may reach a region not permitting it
+    pub fn symbolic_region_access(&self) -> Option<&SymbolicRegionAccess> {
+        match self {
+            MachineError::MemoryError(MemoryError::SymbolicRegionAccess(a))
+            | MachineError::InstructionError(InstructionError::MemoryError(
+                MemoryError::SymbolicRegionAccess(a),
+            )) => Some(a),
+            _ => None,
+        }
+    }
+
+    // The fault of a memory access a region doesn't permit
+    pub fn fault(&self) -> Option<&Fault> {
+        match self {
+            MachineError::MemoryError(MemoryError::Fault(f))
+            | MachineError::InstructionError(InstructionError::MemoryError(MemoryError::Fault(
+                f,
+            ))) => Some(f),
+            _ => None,
+        }
+    }
 }
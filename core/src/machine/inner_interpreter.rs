@@ -3,7 +3,7 @@ use crate::{
     instructions::{AbstractExecRecord, AbstractInstruction},
 };
 
-use super::{r#abstract::AbstractMachine, MachineResult};
+use super::{error::MachineError, r#abstract::AbstractMachine, MachineResult};
 
 pub trait InnerInterpreter<'a, I, InstructionStepResult, InterpreterStepResult>
 where
@@ -22,15 +22,16 @@ where
     fn step(&self, m: AbstractMachine<'a, I>) -> MachineResult<AbstractMachine<'a, I>> {
         let i = m.pgm.get(m.pc.unwrap()).unwrap();
 
-        let exec_record = i.exec(&m.stack, &m.mem, &m.env)?;
+        let exec_record = i.exec(&m.stack, &m.mem, &m.env, &m.storage)?;
 
-        Ok(m.apply(
+        m.apply(
             exec_record.stack_diff,
             exec_record.mem_diff,
+            exec_record.storage_diff,
             exec_record.env_diff,
             exec_record.pc_change,
             exec_record.halt,
-        ))
+        )
     }
 }
 
@@ -51,24 +52,37 @@ where
 
         let i = pgm.get(pc).unwrap();
 
-        let exec_records = i.exec(&m.stack, &m.mem, &m.env)?;
+        // A faulting memory access ends the path, rather than the whole exploration
+        let fault = |m: &AbstractMachine<'a, I>, e: MachineError| match e.fault() {
+            Some(fault) => Ok(m.xclone().faulted(fault.clone())),
+            None => Err(e),
+        };
+
+        let exec_records = match i.exec(&m.stack, &m.mem, &m.env, &m.storage) {
+            Ok(exec_records) => exec_records,
+            Err(e) => return Ok(vec![(fault(&m, e.into())?, vec![])]),
+        };
 
         let rv = exec_records
             .into_iter()
             .map(|exec_record| {
                 let constraints = exec_record.constraints.unwrap_or_default();
 
-                let new_machine = m.xclone().apply(
-                    exec_record.stack_diff,
-                    exec_record.mem_diff,
-                    exec_record.env_diff,
-                    exec_record.pc_change,
-                    exec_record.halt,
-                );
-
-                (new_machine, constraints)
+                let new_machine = m
+                    .xclone()
+                    .apply(
+                        exec_record.stack_diff,
+                        exec_record.mem_diff,
+                        exec_record.storage_diff,
+                        exec_record.env_diff,
+                        exec_record.pc_change,
+                        exec_record.halt,
+                    )
+                    .or_else(|e| fault(&m, e))?;
+
+                Ok((new_machine, constraints))
             })
-            .collect();
+            .collect::<MachineResult<_>>()?;
 
         Ok(rv)
     }
@@ -1,8 +1,20 @@
-use crate::{constraint::Constraint, instructions::AbstractInstruction};
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constraint::{
+        bisect, normalize_onto, optimizer::independent_groups, Constraint, Objective, SatResult,
+        Solver, UnknownReason,
+    },
+    instructions::AbstractInstruction,
+    memory::AddressPolicy,
+    stack::StackVal,
+};
 
 use super::{
     inner_interpreter::{AbstractExecBranch, InnerInterpreter},
-    r#abstract::AbstractMachine,
+    r#abstract::{AbstractMachine, MachineState},
     MachineResult,
 };
 
@@ -38,11 +50,128 @@ pub struct SymbolicOuterInterpreter<'a, I, InstructionStepResult, InterpreterSte
 where
     I: AbstractInstruction<InstructionStepResult>,
 {
-    inner_interpreter: dyn InnerInterpreter<'a, I, InstructionStepResult, InterpreterStepResult>,
+    pub inner_interpreter:
+        Box<dyn InnerInterpreter<'a, I, InstructionStepResult, InterpreterStepResult>>,
+    pub unknown_policy: UnknownPolicy,
+    // Normalize the constraints each branch adds before they reach the solver (see
+    // `constraint::normalize`)
+    pub normalize: bool,
+}
+
+// What `run_with_solver` does with a path the solver returns `SatResult::Unknown` for
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnknownPolicy {
+    // Keep exploring the path, and report it as possibly reachable
+    #[default]
+    Keep,
+    // Treat the path as unreachable
+    Drop,
+    // Check again with only the path constraints that share symbols with the branch
+    // condition, and keep the path if that is still undecided. A satisfying model found
+    // this way only covers the symbols of those constraints.
+    Retry,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathStats {
+    pub queries: usize,
+    pub unknown: usize,
+    pub timeouts: usize,
+    pub retries: usize,
+    // Paths dropped because of `UnknownPolicy::Drop`
+    pub dropped: usize,
+    // Branches pruned by normalization without querying the solver
+    pub trivially_unsat: usize,
 }
 
 pub type SingleBranch<'a, I> = (AbstractMachine<'a, I>, Vec<Constraint>);
 
+// The instruction at `pc` added the constraint when taking its `branch`-th branch
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ConstraintOrigin {
+    pub pc: usize,
+    pub branch: usize,
+}
+
+// Path constraints of an unreachable path that contradict each other, as reported by
+// `Solver::unsat_core`. `unreachable` indexes `PathSummary::unreachable`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UnsatCore {
+    pub unreachable: usize,
+    pub constraints: Vec<(ConstraintOrigin, Constraint)>,
+}
+
+pub struct PathSummary<'a, I, M> {
+    pub reachable: Vec<(SingleBranch<'a, I>, SatResult<M>)>,
+    // Paths the solver could not decide, kept because of the `UnknownPolicy`
+    pub possibly_reachable: Vec<(SingleBranch<'a, I>, SatResult<M>)>,
+    pub unreachable: Vec<(SingleBranch<'a, I>, SatResult<M>)>,
+    // Only filled in for solvers that support unsat cores
+    pub unsat_cores: Vec<UnsatCore>,
+    pub stats: PathStats,
+}
+
+impl<'a, I, M> Default for PathSummary<'a, I, M> {
+    fn default() -> Self {
+        Self {
+            reachable: vec![],
+            possibly_reachable: vec![],
+            unreachable: vec![],
+            unsat_cores: vec![],
+            stats: PathStats::default(),
+        }
+    }
+}
+
+// A leaf of a `PathSummary` without the program, for saving results and replaying them
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LeafSnapshot<M> {
+    pub state: MachineState,
+    #[serde(with = "crate::serialize::shared")]
+    pub path: Vec<Constraint>,
+    pub result: SatResult<M>,
+}
+
+impl<M> LeafSnapshot<M> {
+    pub fn restore<I>(self, pgm: &[I]) -> (SingleBranch<'_, I>, SatResult<M>) {
+        (
+            (AbstractMachine::from_state(self.state, pgm), self.path),
+            self.result,
+        )
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SummarySnapshot<M> {
+    pub reachable: Vec<LeafSnapshot<M>>,
+    pub possibly_reachable: Vec<LeafSnapshot<M>>,
+    pub unreachable: Vec<LeafSnapshot<M>>,
+    pub unsat_cores: Vec<UnsatCore>,
+    pub stats: PathStats,
+}
+
+impl<'a, I, M: Clone> PathSummary<'a, I, M> {
+    pub fn snapshot(&self) -> SummarySnapshot<M> {
+        let leaves = |leaves: &[(SingleBranch<'a, I>, SatResult<M>)]| {
+            leaves
+                .iter()
+                .map(|((mach, path), result)| LeafSnapshot {
+                    state: mach.state(),
+                    path: path.clone(),
+                    result: result.clone(),
+                })
+                .collect()
+        };
+        SummarySnapshot {
+            reachable: leaves(&self.reachable),
+            possibly_reachable: leaves(&self.possibly_reachable),
+            unreachable: leaves(&self.unreachable),
+            unsat_cores: self.unsat_cores.clone(),
+            stats: self.stats.clone(),
+        }
+    }
+}
+
 impl<'a, I, InstructionStepResult>
     OuterInterpreter<Vec<SingleBranch<'a, I>>, AbstractMachine<'a, I>>
     for SymbolicOuterInterpreter<'a, I, InstructionStepResult, AbstractExecBranch<'a, I>>
@@ -76,42 +205,882 @@ where
         }
 
         Ok(leaves)
+    }
+}
+
+// Work items of the depth-first traversal in `run_with_solver`. `PopScope` is scheduled
+// underneath a node's children, so the solver scope opened for a node is closed only
+// once its whole subtree has been explored.
+// Most items are visits, so boxing the machine would only add allocations
+#[allow(clippy::large_enum_variant)]
+enum DfsItem<'a, I> {
+    Visit {
+        mach: AbstractMachine<'a, I>,
+        path: Vec<Constraint>,
+        origins: Vec<ConstraintOrigin>,
+        new_constraints: usize,
+        // Whether the path without the new constraints was found satisfiable
+        prefix_sat: bool,
+    },
+    PopScope,
+}
+
+impl<'a, I, InstructionStepResult>
+    SymbolicOuterInterpreter<'a, I, InstructionStepResult, AbstractExecBranch<'a, I>>
+where
+    I: AbstractInstruction<InstructionStepResult>,
+{
+    // Explores the execution tree depth first, keeping the solver's assertion stack in sync
+    // with the current path: at each fork only the constraints added by that step are
+    // asserted (in a fresh scope), and branches found unsatisfiable are pruned immediately.
+    // Path constraints are asserted labelled with their position in the path, so unsat
+    // cores can be traced back to the branches that added them.
+    pub fn run_with_solver<S, Ast, G>(
+        &self,
+        m: AbstractMachine<'a, I>,
+        solver: &mut S,
+    ) -> MachineResult<PathSummary<'a, I, S::Model>>
+    where
+        S: Solver<Ast, G>,
+    {
+        let mut summary = PathSummary::default();
+        // Number of constraints asserted in each scope opened by this run
+        let mut scopes: Vec<usize> = vec![];
+        let mut work = vec![DfsItem::Visit {
+            mach: m,
+            path: vec![],
+            origins: vec![],
+            new_constraints: 0,
+            prefix_sat: true,
+        }];
+
+        while let Some(item) = work.pop() {
+            let (mach, path, origins, new_constraints, prefix_sat) = match item {
+                DfsItem::PopScope => {
+                    solver.pop();
+                    scopes.pop();
+                    continue;
+                }
+                DfsItem::Visit {
+                    mach,
+                    path,
+                    origins,
+                    new_constraints,
+                    prefix_sat,
+                } => (mach, path, origins, new_constraints, prefix_sat),
+            };
+
+            let mut checked = None;
+            if new_constraints > 0 {
+                solver.push();
+                scopes.push(new_constraints);
+                work.push(DfsItem::PopScope);
+                let first = path.len() - new_constraints;
+                for (i, c) in path.iter().enumerate().skip(first) {
+                    solver.assert_labelled(&Self::label(i), c);
+                }
+
+                match self.query(
+                    solver,
+                    &path,
+                    new_constraints,
+                    prefix_sat,
+                    &scopes,
+                    &mut summary.stats,
+                ) {
+                    SatResult::Unsat => {
+                        Self::record_unsat_core(solver, &mut summary, &path, &origins);
+                        summary.unreachable.push(((mach, path), SatResult::Unsat));
+                        continue;
+                    }
+                    SatResult::Unknown if self.unknown_policy == UnknownPolicy::Drop => {
+                        summary.stats.dropped += 1;
+                        summary.unreachable.push(((mach, path), SatResult::Unknown));
+                        continue;
+                    }
+                    res => checked = Some(res),
+                }
+            }
+
+            // Whether the children's prefix, this node's whole path, is known to be satisfiable
+            let path_sat = match &checked {
+                Some(res) => matches!(res, SatResult::Sat(_)),
+                None => prefix_sat,
+            };
+
+            if mach.can_continue() {
+                let pc = mach.pc.unwrap_or_default();
+                let limit = match mach.mem.config().address_policy {
+                    AddressPolicy::Fork { limit } => Some(limit),
+                    AddressPolicy::Concretize => Some(1),
+                    _ => None,
+                };
+                // Kept to step again once a symbolic address is resolved
+                let retry =
+                    (limit.is_some() || !mach.mem.regions().is_empty()).then(|| mach.xclone());
+                let branches = match self.inner_interpreter.step(mach) {
+                    Ok(branches) => branches,
+                    Err(e) => {
+                        let Some(mach) = retry else { return Err(e) };
+                        // Either the access stays clear of the region, or the path faults
+                        if let Some(access) = e.symbolic_region_access() {
+                            let hit = access.region.overlap(&access.addr, access.size);
+                            let mut clear = mach.xclone();
+                            clear.mem = clear.mem.exclude_region(
+                                &access.addr,
+                                access.size,
+                                &access.region.name,
+                            );
+                            let fault = access.fault();
+                            let sides = vec![(clear, !hit.clone()), (mach.faulted(fault), hit)];
+                            for (branch, (mach, c)) in sides.into_iter().enumerate().rev() {
+                                let mut new_path = path.clone();
+                                new_path.push(c);
+                                let mut new_origins = origins.clone();
+                                new_origins.push(ConstraintOrigin { pc, branch });
+                                work.push(DfsItem::Visit {
+                                    mach,
+                                    path: new_path,
+                                    origins: new_origins,
+                                    new_constraints: 1,
+                                    prefix_sat: path_sat,
+                                });
+                            }
+                            continue;
+                        }
+                        let (addr, limit) = match (e.symbolic_address(), limit) {
+                            (Some(addr), Some(limit)) => (addr.clone(), limit),
+                            _ => return Err(e),
+                        };
+                        let width = mach.mem.config().address_width;
+                        let values = Self::feasible_addresses(solver, &addr, limit, width);
+                        if values.is_empty() {
+                            return Err(e);
+                        }
+                        // Each branch steps the same instruction again with the address pinned
+                        for (branch, v) in values.into_iter().enumerate().rev() {
+                            let mut mach = mach.xclone();
+                            mach.mem = mach.mem.pin_address(addr.clone(), v);
+                            let mut new_path = path.clone();
+                            new_path.push(Constraint::eq(addr.0.clone(), StackVal::from(v).0));
+                            let mut new_origins = origins.clone();
+                            new_origins.push(ConstraintOrigin { pc, branch });
+                            work.push(DfsItem::Visit {
+                                mach,
+                                path: new_path,
+                                origins: new_origins,
+                                new_constraints: 1,
+                                prefix_sat: path_sat,
+                            });
+                        }
+                        continue;
+                    }
+                };
+
+                // The path is normalized already, so only the added constraints need to be
+                let known: HashSet<&Constraint> = if self.normalize {
+                    path.iter().collect()
+                } else {
+                    HashSet::new()
+                };
+                // Reversed so that branches are explored in the order the instruction returned them
+                for (branch, (new_mach, constraints_to_add)) in
+                    branches.into_iter().enumerate().rev()
+                {
+                    let mut new_path = path.clone();
+                    let constraints_to_add = if self.normalize {
+                        match normalize_onto(&known, &constraints_to_add) {
+                            Some(constraints) => constraints,
+                            None => {
+                                summary.stats.trivially_unsat += 1;
+                                new_path.extend(constraints_to_add);
+                                summary
+                                    .unreachable
+                                    .push(((new_mach, new_path), SatResult::Unsat));
+                                continue;
+                            }
+                        }
+                    } else {
+                        constraints_to_add
+                    };
+                    let new_constraints = constraints_to_add.len();
+                    new_path.extend(constraints_to_add);
+                    let mut new_origins = origins.clone();
+                    new_origins.extend(vec![ConstraintOrigin { pc, branch }; new_constraints]);
+                    work.push(DfsItem::Visit {
+                        mach: new_mach,
+                        path: new_path,
+                        origins: new_origins,
+                        new_constraints,
+                        prefix_sat: path_sat,
+                    });
+                }
+            } else {
+                let res = checked.unwrap_or_else(|| {
+                    self.query(solver, &path, 0, prefix_sat, &scopes, &mut summary.stats)
+                });
+                match res {
+                    SatResult::Sat(_) => summary.reachable.push(((mach, path), res)),
+                    SatResult::Unknown if self.unknown_policy == UnknownPolicy::Drop => {
+                        summary.stats.dropped += 1;
+                        summary.unreachable.push(((mach, path), res));
+                    }
+                    SatResult::Unknown => summary.possibly_reachable.push(((mach, path), res)),
+                    SatResult::Unsat => {
+                        Self::record_unsat_core(solver, &mut summary, &path, &origins);
+                        summary.unreachable.push(((mach, path), res));
+                    }
+                }
+            }
+        }
+
+        Ok(summary)
+    }
+
+    // Solves the current path, retrying a simplified query if the policy asks for it. The
+    // retry is only sound when the prefix of the path was proven satisfiable, and only
+    // simpler when the path has new constraints to slice around.
+    fn query<S, Ast, G>(
+        &self,
+        solver: &mut S,
+        path: &[Constraint],
+        new_constraints: usize,
+        prefix_sat: bool,
+        scopes: &[usize],
+        stats: &mut PathStats,
+    ) -> SatResult<S::Model>
+    where
+        S: Solver<Ast, G>,
+    {
+        let res = Self::solve_counted(solver, stats);
+        if !matches!(res, SatResult::Unknown)
+            || self.unknown_policy != UnknownPolicy::Retry
+            || new_constraints == 0
+            || !prefix_sat
+        {
+            return res;
+        }
+
+        // The prefix of the path was already checked, so only the constraints connected to
+        // the new ones through shared symbols can make the path unsatisfiable
+        let new = &path[path.len() - new_constraints..];
+        let slice: Vec<Constraint> = independent_groups(path)
+            .into_iter()
+            .filter(|g| g.iter().any(|c| new.contains(c)))
+            .flatten()
+            .collect();
+        let slice: Vec<usize> = (0..path.len())
+            .filter(|i| slice.contains(&path[*i]))
+            .collect();
+        if slice.len() == path.len() {
+            return res;
+        }
+        stats.retries += 1;
+
+        // Replace the scopes holding the path with a single one holding the slice, then
+        // restore them for the rest of the traversal
+        scopes.iter().for_each(|_| solver.pop());
+        solver.push();
+        for i in slice {
+            solver.assert_labelled(&Self::label(i), &path[i]);
+        }
+        let res = Self::solve_counted(solver, stats);
+        solver.pop();
+
+        let mut start = 0;
+        for n in scopes {
+            solver.push();
+            for (i, c) in path.iter().enumerate().skip(start).take(*n) {
+                solver.assert_labelled(&Self::label(i), c);
+            }
+            start += n;
+        }
+
+        res
+    }
+
+    // Up to `limit` values `addr`, a `width` bit number, can take on the asserted path,
+    // smallest first. Found by minimizing the address with `bisect`, so that any solver
+    // will do.
+    fn feasible_addresses<S, Ast, G>(
+        solver: &mut S,
+        addr: &StackVal,
+        limit: usize,
+        width: u32,
+    ) -> Vec<usize>
+    where
+        S: Solver<Ast, G>,
+    {
+        let mut values = vec![];
+        solver.push();
+        while values.len() < limit {
+            let objective = Objective::Minimize(addr.0.clone());
+            let v = match bisect(solver, &objective, width) {
+                SatResult::Sat(optimum) => usize::try_from(u128::from(optimum.value)),
+                _ => break,
+            };
+            let Ok(v) = v else { break };
+            values.push(v);
+            solver.generic_assert(&Constraint::gt(addr.0.clone(), StackVal::from(v).0));
+        }
+        solver.pop();
+        values
+    }
+
+    fn label(i: usize) -> String {
+        format!("path{}", i)
+    }
+
+    fn record_unsat_core<S, Ast, G>(
+        solver: &S,
+        summary: &mut PathSummary<'a, I, S::Model>,
+        path: &[Constraint],
+        origins: &[ConstraintOrigin],
+    ) where
+        S: Solver<Ast, G>,
+    {
+        if let Some(core) = solver.unsat_core() {
+            let mut indices: Vec<usize> = core
+                .iter()
+                .filter_map(|l| l.strip_prefix("path")?.parse().ok())
+                .filter(|i| *i < path.len())
+                .collect();
+            indices.sort_unstable();
+            summary.unsat_cores.push(UnsatCore {
+                unreachable: summary.unreachable.len(),
+                constraints: indices
+                    .into_iter()
+                    .map(|i| (origins[i], path[i].clone()))
+                    .collect(),
+            });
+        }
+    }
+
+    fn solve_counted<S, Ast, G>(solver: &S, stats: &mut PathStats) -> SatResult<S::Model>
+    where
+        S: Solver<Ast, G>,
+    {
+        let res = solver.solve();
+        stats.queries += 1;
+        if let SatResult::Unknown = res {
+            stats.unknown += 1;
+            if solver.reason_unknown() == Some(UnknownReason::Timeout) {
+                stats.timeouts += 1;
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        constraint::{mock::MockSolver, Model},
+        environment::Env,
+        instructions::error::InstructionError,
+        instructions::{AbstractExecRecord, InstructionResult},
+        machine::{error::MachineError, inner_interpreter::SymbolicInnerInterpreter},
+        memory::{
+            Access, Fault, MemOpRecord, MemRecord, MemVal, Memory, MemoryConfig, MemoryError,
+            Permissions, Region,
+        },
+        stack::{Stack, StackOpRecord, StackRecord},
+        storage::Storage,
+        test_util::{num, sym},
+    };
+
+    // Forks into one branch per constraint; each branch adds that single constraint
+    struct Fork(Vec<Constraint>);
+
+    impl AbstractInstruction<Vec<AbstractExecRecord>> for Fork {
+        fn exec(
+            &self,
+            _stack: &Stack,
+            _mem: &Memory,
+            _env: &Env,
+            _storage: &Storage,
+        ) -> InstructionResult<Vec<AbstractExecRecord>> {
+            Ok(self
+                .0
+                .iter()
+                .map(|c| AbstractExecRecord {
+                    constraints: Some(vec![c.clone()]),
+                    ..Default::default()
+                })
+                .collect())
+        }
+    }
+
+    type Interpreter<'a, I> =
+        SymbolicOuterInterpreter<'a, I, Vec<AbstractExecRecord>, AbstractExecBranch<'a, I>>;
+
+    // A machine about to run the first instruction of `pgm`
+    fn machine<I>(pgm: &[I], stack: Stack, mem: Memory) -> AbstractMachine<'_, I> {
+        AbstractMachine {
+            stack,
+            mem,
+            env: Env {},
+            storage: Storage::default(),
+            pc: Some(0),
+            pgm,
+            fault: None,
+        }
+    }
+
+    fn interpreter<'a, I>(unknown_policy: UnknownPolicy) -> Interpreter<'a, I>
+    where
+        I: AbstractInstruction<Vec<AbstractExecRecord>>,
+    {
+        SymbolicOuterInterpreter {
+            inner_interpreter: Box::new(SymbolicInnerInterpreter {}),
+            unknown_policy,
+            normalize: false,
+        }
+    }
+
+    #[test]
+    fn test_run_with_solver_prunes_and_reuses_prefixes() {
+        let pgm = vec![
+            Fork(vec![Constraint::True, Constraint::True]),
+            Fork(vec![Constraint::True, Constraint::False]),
+        ];
+        let machine = machine(&pgm, Stack::default(), Memory::default());
+        let interpreter = interpreter(UnknownPolicy::Keep);
+        let mut solver = MockSolver::default();
+
+        let summary = interpreter.run_with_solver(machine, &mut solver).unwrap();
+
+        assert_eq!(summary.reachable.len(), 2);
+        assert_eq!(summary.unreachable.len(), 2);
+        assert!(summary
+            .reachable
+            .iter()
+            .all(|((_, path), _)| path.len() == 2));
+        // Both pruned paths are explained by the second fork's `False` branch
+        assert_eq!(summary.unsat_cores.len(), 2);
+        for (i, core) in summary.unsat_cores.iter().enumerate() {
+            assert_eq!(core.unreachable, i);
+            assert!(
+                core.constraints
+                    == vec![(ConstraintOrigin { pc: 1, branch: 1 }, Constraint::False)]
+            );
+        }
+        // Each first-level constraint is asserted once and shared by both of its children
+        assert_eq!(
+            solver.log.join(" "),
+            "push assert \
+             push assert pop \
+             push assert pop \
+             pop \
+             push assert \
+             push assert pop \
+             push assert pop \
+             pop"
+        );
+    }
+
+    #[test]
+    fn test_unknown_policies() {
+        let pgm = vec![
+            Fork(vec![Constraint::gt(sym("x"), num(1))]),
+            Fork(vec![Constraint::gt(sym("x"), num(2))]),
+            Fork(vec![Constraint::gt(sym("y"), num(3)), Constraint::False]),
+        ];
+        let run = |unknown_policy| {
+            let machine = machine(&pgm, Stack::default(), Memory::default());
+            let interpreter = interpreter(unknown_policy);
+            // Only the full three constraint paths time out
+            let mut solver = MockSolver {
+                max_constraints: Some(2),
+                ..Default::default()
+            };
+            let summary = interpreter.run_with_solver(machine, &mut solver).unwrap();
+            assert!(solver.balanced());
+            (
+                summary.reachable.len(),
+                summary.possibly_reachable.len(),
+                summary.unreachable.len(),
+                summary.stats,
+            )
+        };
+
+        assert_eq!(
+            run(UnknownPolicy::Keep),
+            (
+                0,
+                2,
+                0,
+                PathStats {
+                    queries: 4,
+                    unknown: 2,
+                    timeouts: 2,
+                    ..Default::default()
+                }
+            )
+        );
+        assert_eq!(
+            run(UnknownPolicy::Drop),
+            (
+                0,
+                0,
+                2,
+                PathStats {
+                    queries: 4,
+                    unknown: 2,
+                    timeouts: 2,
+                    dropped: 2,
+                    ..Default::default()
+                }
+            )
+        );
+        // Each branch condition is independent of the `x` constraints before it
+        assert_eq!(
+            run(UnknownPolicy::Retry),
+            (
+                1,
+                0,
+                1,
+                PathStats {
+                    queries: 6,
+                    unknown: 2,
+                    timeouts: 2,
+                    retries: 2,
+                    ..Default::default()
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_retry_needs_a_proven_prefix() {
+        // One branch per entry, each adding all of its constraints
+        struct Branches(Vec<Vec<Constraint>>);
+
+        impl AbstractInstruction<Vec<AbstractExecRecord>> for Branches {
+            fn exec(
+                &self,
+                _stack: &Stack,
+                _mem: &Memory,
+                _env: &Env,
+                _storage: &Storage,
+            ) -> InstructionResult<Vec<AbstractExecRecord>> {
+                Ok(self
+                    .0
+                    .iter()
+                    .map(|cs| AbstractExecRecord {
+                        constraints: Some(cs.clone()),
+                        ..Default::default()
+                    })
+                    .collect())
+            }
+        }
+
+        // The `x` prefix times out on its own, and the last step doesn't fork
+        let pgm = vec![
+            Branches(vec![vec![
+                Constraint::gt(sym("x"), num(1)),
+                Constraint::gt(sym("x"), num(2)),
+            ]]),
+            Branches(vec![vec![Constraint::gt(sym("y"), num(3))]]),
+            Branches(vec![vec![]]),
+        ];
+        let machine = machine(&pgm, Stack::default(), Memory::default());
+        let interpreter = interpreter(UnknownPolicy::Retry);
+        let mut solver = MockSolver {
+            max_constraints: Some(1),
+            ..Default::default()
+        };
+
+        let summary = interpreter.run_with_solver(machine, &mut solver).unwrap();
+
+        // Slicing around `y > 3` would drop the undecided `x` constraints, and the leaf
+        // adds no constraints to slice around, so the path stays undecided
+        assert!(summary.reachable.is_empty());
+        assert_eq!(summary.possibly_reachable.len(), 1);
+        assert_eq!(summary.possibly_reachable[0].0 .0.pc, Some(3));
+        assert_eq!(
+            summary.stats,
+            PathStats {
+                queries: 3,
+                unknown: 3,
+                timeouts: 3,
+                ..Default::default()
+            }
+        );
+        assert!(solver.balanced());
+    }
+
+    #[test]
+    fn test_normalized_branches() {
+        let x = Constraint::gt(sym("x"), num(1));
+        let not_x = Constraint::lte(sym("x"), num(1));
+        let pgm = vec![
+            Fork(vec![Constraint::True, x.clone()]),
+            Fork(vec![
+                x.clone().and(x.clone()),
+                not_x.clone(),
+                Constraint::False,
+            ]),
+        ];
+        let machine = machine(&pgm, Stack::default(), Memory::default());
+        let interpreter = Interpreter {
+            normalize: true,
+            ..interpreter(UnknownPolicy::Keep)
+        };
+        let mut solver = MockSolver::default();
+
+        let summary = interpreter.run_with_solver(machine, &mut solver).unwrap();
+
+        let paths: Vec<&Vec<Constraint>> = summary
+            .reachable
+            .iter()
+            .map(|((_, path), _)| path)
+            .collect();
+        assert!(paths == vec![&vec![x.clone()], &vec![not_x], &vec![x]]);
+        // `False` twice, and `x <= 1` under `x > 1`
+        assert_eq!(summary.unreachable.len(), 3);
+        assert_eq!(summary.stats.trivially_unsat, 3);
+        assert!(summary.unsat_cores.is_empty());
+        // The `True` branch opens no scope, and neither does repeating `x > 1`
+        assert_eq!(
+            solver.log.join(" "),
+            "push assert pop \
+             push assert pop \
+             push assert \
+             pop"
+        );
+    }
+
+    // Replaces the address on top of the stack with the word there
+    struct Load;
+
+    impl AbstractInstruction<Vec<AbstractExecRecord>> for Load {
+        fn exec(
+            &self,
+            stack: &Stack,
+            mem: &Memory,
+            _env: &Env,
+            _storage: &Storage,
+        ) -> InstructionResult<Vec<AbstractExecRecord>> {
+            let v = mem.load_word(stack.peek(0).unwrap())?;
+            Ok(vec![AbstractExecRecord {
+                stack_diff: Some(StackRecord {
+                    changed: vec![StackOpRecord::Pop, StackOpRecord::Push(v)],
+                }),
+                ..Default::default()
+            }])
+        }
+    }
+
+    #[test]
+    fn test_symbolic_addresses() {
+        let pgm = vec![Load, Load];
+        let run = |address_policy| {
+            let config = MemoryConfig {
+                word_size: 1,
+                address_policy,
+                ..MemoryConfig::default()
+            };
+            let machine = machine(
+                &pgm,
+                Stack::new(vec![StackVal(sym("x"))], Default::default()),
+                Memory::new((1..=16).map(MemVal::from).collect(), config),
+            );
+            let interpreter = interpreter(UnknownPolicy::Keep);
+            let mut solver = MockSolver {
+                domain: Some((3, 5)),
+                ..Default::default()
+            };
+            let summary = interpreter.run_with_solver(machine, &mut solver)?;
+            assert!(solver.balanced());
+            Ok::<_, MachineError>(
+                summary
+                    .reachable
+                    .into_iter()
+                    .map(|((mach, path), _)| (mach.stack.peek(0).unwrap().to_string(), path))
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let x_is = |v| vec![Constraint::eq(sym("x"), num(v))];
+
+        // The second load is at a concrete address
+        let forked = run(AddressPolicy::Fork { limit: 5 }).unwrap();
+        assert!(
+            forked
+                == vec![
+                    ("5".to_string(), x_is(3)),
+                    ("6".to_string(), x_is(4)),
+                    ("7".to_string(), x_is(5)),
+                ]
+        );
+        let bounded = run(AddressPolicy::Fork { limit: 2 }).unwrap();
+        assert!(bounded == forked[..2]);
+        let concretized = run(AddressPolicy::Concretize).unwrap();
+        assert!(concretized == forked[..1]);
+
+        assert!(matches!(
+            run(AddressPolicy::Reject),
+            Err(MachineError::InstructionError(
+                InstructionError::MemoryError(MemoryError::SymbolicAddress(_))
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_address_width() {
+        let x = StackVal(sym("x"));
+        let search = |domain, width: u32| {
+            let mut solver = MockSolver {
+                domain: Some(domain),
+                ..Default::default()
+            };
+            let values = Interpreter::<Load>::feasible_addresses(&mut solver, &x, 2, width);
+            assert!(solver.balanced());
+            // Each value takes at most a bisection over the address width
+            assert!(solver.solves.get() <= 2 * (width as usize + 1));
+            values
+        };
+
+        assert_eq!(search((3, 5), 8), vec![3, 4]);
+        assert_eq!(search((3, 5), 64), vec![3, 4]);
+        assert_eq!(search((300, 302), 16), vec![300, 301]);
+    }
+
+    #[test]
+    fn test_fault_leaves() {
+        let pgm = vec![Load, Load];
+        let config = MemoryConfig {
+            word_size: 1,
+            ..MemoryConfig::default()
+        };
+        let mem = Memory::new((1..=8).map(MemVal::from).collect(), config)
+            .with_region(Region::new("guard", 2, 1, Permissions::NONE))
+            .unwrap();
+        // Loads 2 from address 1, then faults loading from address 2
+        let machine = machine(
+            &pgm,
+            Stack::new(vec![StackVal(num(1))], Default::default()),
+            mem,
+        );
+        let interpreter = interpreter(UnknownPolicy::Keep);
+        let summary = interpreter
+            .run_with_solver(machine, &mut MockSolver::default())
+            .unwrap();
+
+        assert_eq!(summary.reachable.len(), 1);
+        let ((mach, _), _) = &summary.reachable[0];
+        assert_eq!(mach.pc, None);
+        assert_eq!(mach.stack.peek(0), Some(&StackVal(num(2))));
+        assert_eq!(
+            mach.fault,
+            Some(Fault {
+                region: "guard".to_string(),
+                addr: StackVal::from(2_usize),
+                access: Access::Read,
+            })
+        );
+        let snapshot = summary.snapshot();
+        assert_eq!(snapshot.reachable[0].state.fault, mach.fault);
+    }
+
+    // Writes 7 to the address on top of the stack
+    struct Store;
+
+    impl AbstractInstruction<Vec<AbstractExecRecord>> for Store {
+        fn exec(
+            &self,
+            stack: &Stack,
+            _mem: &Memory,
+            _env: &Env,
+            _storage: &Storage,
+        ) -> InstructionResult<Vec<AbstractExecRecord>> {
+            Ok(vec![AbstractExecRecord {
+                mem_diff: Some(MemRecord {
+                    changed: vec![MemOpRecord::WriteByte(
+                        stack.peek(0).unwrap().clone(),
+                        StackVal(num(7)),
+                    )],
+                }),
+                ..Default::default()
+            }])
+        }
+    }
+
+    #[test]
+    fn test_symbolic_region_access() {
+        let pgm = vec![Store];
+        let config = MemoryConfig {
+            word_size: 1,
+            address_policy: AddressPolicy::IteChain,
+            ..MemoryConfig::default()
+        };
+        let mem = Memory::new((1..=4).map(MemVal::from).collect(), config)
+            .with_region(Region::new("rom", 0, 2, Permissions::READ_ONLY))
+            .unwrap();
+        let machine = machine(
+            &pgm,
+            Stack::new(vec![StackVal(sym("x"))], Default::default()),
+            mem,
+        );
+        let interpreter = interpreter(UnknownPolicy::Keep);
+        let summary = interpreter
+            .run_with_solver(machine, &mut MockSolver::default())
+            .unwrap();
+
+        // The write either misses the ROM and leaves it alone, or faults in it
+        let leaves: Vec<_> = summary
+            .reachable
+            .iter()
+            .map(|((mach, path), _)| {
+                let path: Vec<_> = path.iter().map(|c| c.to_string()).collect();
+                let rom = mach.mem.load_byte(&StackVal(num(0))).unwrap().to_string();
+                let fault = mach.fault.as_ref().map(|f| (f.addr.to_string(), f.access));
+                (path, rom, fault)
+            })
+            .collect();
+        assert_eq!(
+            leaves,
+            vec![
+                (
+                    vec!["!(x >= 0 && x < 2)".to_string()],
+                    "1".to_string(),
+                    None
+                ),
+                (
+                    vec!["x >= 0 && x < 2".to_string()],
+                    "1".to_string(),
+                    // The first byte written in the ROM
+                    Some(("x < 0 ? 0 : x".to_string(), Access::Write))
+                ),
+            ]
+        );
+        let ((clear, _), _) = &summary.reachable[0];
+        assert_eq!(
+            clear.mem.load_byte(&StackVal(num(2))).unwrap().to_string(),
+            "x == 2 ? 7 : 3"
+        );
+    }
+
+    #[test]
+    fn test_summary_snapshot() {
+        let x = Constraint::gt(sym("x"), num(1));
+        let pgm = vec![Fork(vec![x.clone(), Constraint::False])];
+        let machine = machine(&pgm, Stack::default(), Memory::default());
+        let interpreter = interpreter(UnknownPolicy::Keep);
+        let mut solver = MockSolver::default();
+        let summary = interpreter.run_with_solver(machine, &mut solver).unwrap();
+
+        let json = serde_json::to_string(&summary.snapshot()).unwrap();
+        let snapshot: SummarySnapshot<Model> = serde_json::from_str(&json).unwrap();
 
-        // TODO(will) - This doesn't include the `PathSummary` portion yet where we use the solver
-        // to find the reachable paths and return models. The type signature of `ConcreteOuterInterpreter` is already incredibly
-        // complex and I'd prefer to not add the additional generics that the solver is going to entail
-        // in a separate PR.
-        //
-        // NOTE(will) - This type signature will have to change
-        //          #[derive(Default)]
-        //          pub struct PathSummary<IType, T, M>
-        //          where
-        //              IType: AbstractInstruction,
-        //          {
-        //              pub reachable: Vec<(SingleBranch<IType, T>, SatResult<M>)>,
-        //              pub unreachable: Vec<(SingleBranch<IType, T>, SatResult<M>)>,
-        //          }
-        //
-        //         let mut summary = PathSummary {
-        //             reachable: vec![],
-        //             unreachable: vec![],
-        //         };
-
-        //         if let Some(mut solver) = solver {
-        //             for leaf in leaves {
-        //                 let constraints = &leaf.1;
-        //                 for constraint in constraints {
-        //                     solver.generic_assert(constraint);
-        //                 }
-        //                 let sat = solver.solve();
-        //                 if let SatResult::Sat(m) = sat {
-        //                     summary.reachable.push((leaf, SatResult::Sat(m)));
-        //                 } else {
-        //                     summary.unreachable.push((leaf, SatResult::Unsat));
-        //                 }
-        //             }
-        //         }
-
-        //         Ok(summary)
+        assert_eq!(snapshot.stats, summary.stats);
+        assert_eq!(snapshot.unsat_cores.len(), 1);
+        let ((mach, path), res) = snapshot.reachable[0].clone().restore(&pgm);
+        assert!(path == vec![x]);
+        assert_eq!(mach.pc, Some(1));
+        assert!(!mach.can_continue());
+        assert!(matches!(res, SatResult::Sat(m) if m.get("x").is_some()));
+        assert!(snapshot.unreachable[0].path == vec![Constraint::False]);
     }
 }
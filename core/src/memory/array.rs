@@ -0,0 +1,62 @@
+// Memory in the theory of arrays. The contents are an array term: a base vector under a
+// chain of `TernaryOp::Store`s, one per byte written. The base is either a vector symbol,
+// for memory whose initial bytes are unknown, or a concrete vector, whose bytes past its
+// end are zero. Reads are `BinOp::Select`s, simplified while the addresses involved are
+// concrete: a read at a concrete address skips stores to other concrete addresses and
+// gives the byte stored or the base's byte directly.
+use crate::value::{
+    BinOp, CSimpleVal, SSimpleVal, Sentence, SymbolId, SymbolicVecc, TernaryOp, Val, Value, Vecc,
+};
+
+use super::bytes::{byte, concrete, number};
+
+pub(crate) fn symbolic_base(name: &str) -> Val<Sentence> {
+    Val::new(Sentence::Basic(Value::Symbolic(
+        SSimpleVal::SymbolicVector(SymbolicVecc(SymbolId(name.to_string(), None))),
+    )))
+}
+
+pub(crate) fn concrete_base(bytes: Vec<u8>) -> Val<Sentence> {
+    Val::new(Sentence::Basic(Value::Concrete(CSimpleVal::Vector(Vecc(
+        bytes,
+    )))))
+}
+
+pub(crate) fn store(arr: &Val<Sentence>, idx: &Sentence, byte: Sentence) -> Val<Sentence> {
+    Val::new(Sentence::TernaryOp {
+        a: arr.clone(),
+        b: Val::new(idx.clone()),
+        c: Val::new(byte),
+        op: TernaryOp::Store,
+    })
+}
+
+pub(crate) fn select(arr: &Val<Sentence>, idx: &Sentence) -> Sentence {
+    let mut arr = arr;
+    if let Some(i) = concrete(idx) {
+        loop {
+            match arr.0.as_ref() {
+                Sentence::TernaryOp {
+                    a,
+                    b,
+                    c,
+                    op: TernaryOp::Store,
+                } => match concrete(&b.0) {
+                    Some(j) if j == i => return byte(c, 0),
+                    Some(_) => arr = a,
+                    None => break,
+                },
+                Sentence::Basic(Value::Concrete(CSimpleVal::Vector(v))) => {
+                    let b = usize::try_from(i).ok().and_then(|i| v.0.get(i));
+                    return number(b.copied().unwrap_or(0) as u128);
+                }
+                _ => break,
+            }
+        }
+    }
+    Sentence::BinOp {
+        a: arr.clone(),
+        b: Val::new(idx.clone()),
+        op: BinOp::Select,
+    }
+}
@@ -0,0 +1,131 @@
+// Words as bytes and back, for values that may be symbolic. Sentences have no widths, so
+// extracting byte `k` of `v` is `(v >> 8k) & 255` and concatenating bytes is
+// `(w << 8) | b`; both fold to constants when concrete. Joining the bytes split from a
+// word gives the word back, so reading exactly what was written yields the value written.
+use crate::value::{BinOp, CNumber, CSimpleVal, Sentence, Val, Value};
+
+use super::config::Endianness;
+
+// The bytes of a word of `size` bytes, in address order
+pub(crate) fn split(v: &Sentence, size: usize, endianness: Endianness) -> Vec<Sentence> {
+    let v = Val::new(v.clone());
+    (0..size)
+        .map(|i| match endianness {
+            Endianness::Big => byte(&v, size - 1 - i),
+            Endianness::Little => byte(&v, i),
+        })
+        .collect()
+}
+
+// The word of the bytes, given in address order: a u64 up to 8 bytes and a u128 above.
+// Symbolic words are treated as 64 bits wide, so a narrower word split from `v` joins back
+// to `v` masked to its width.
+pub(crate) fn join(mut bytes: Vec<Sentence>, endianness: Endianness) -> Sentence {
+    if endianness == Endianness::Little {
+        bytes.reverse();
+    }
+    let size = bytes.len();
+
+    let concrete_word = bytes
+        .iter()
+        .try_fold(0_u128, |w, b| Some(w << 8 | concrete(b)?));
+    if let Some(w) = concrete_word {
+        return match size {
+            0..=8 => number(w),
+            _ => Sentence::Basic(Value::Concrete(CSimpleVal::Number(CNumber::U128(w)))),
+        };
+    }
+
+    if let Some(v) = split_from(&bytes) {
+        return match size {
+            0..=7 => op(
+                v.0.as_ref().clone(),
+                number((1 << (8 * size)) - 1),
+                BinOp::BitAnd,
+            ),
+            _ => v.0.as_ref().clone(),
+        };
+    }
+
+    let mut bytes = bytes.into_iter();
+    let first = bytes.next().unwrap_or_else(|| number(0));
+    bytes.fold(first, |w, b| {
+        op(op(w, number(8), BinOp::LShift), b, BinOp::BitOr)
+    })
+}
+
+// Byte `k` of `v`, counting from the least significant
+pub(crate) fn byte(v: &Val<Sentence>, k: usize) -> Sentence {
+    if let Some(v) = concrete(&v.0) {
+        return number(v.checked_shr(8 * k as u32).unwrap_or(0) & 0xff);
+    }
+    // Already a byte, e.g. read back from memory
+    if k == 0 && as_byte(&v.0).is_some() {
+        return v.0.as_ref().clone();
+    }
+    let shifted = match k {
+        0 => v.clone(),
+        k => Val::new(Sentence::BinOp {
+            a: v.clone(),
+            b: Val::new(number(8 * k as u128)),
+            op: BinOp::RShift,
+        }),
+    };
+    Sentence::BinOp {
+        a: shifted,
+        b: Val::new(number(0xff)),
+        op: BinOp::BitAnd,
+    }
+}
+
+// `v` if the bytes, most significant first, are those of `v`
+fn split_from(bytes: &[Sentence]) -> Option<&Val<Sentence>> {
+    let (v, _) = as_byte(bytes.first()?)?;
+    let matches =
+        bytes.iter().rev().enumerate().all(|(k, b)| {
+            as_byte(b).is_some_and(|(w, j)| j == k && (std::ptr::eq(w, v) || w == v))
+        });
+    matches.then_some(v)
+}
+
+// `(v, k)` for byte `k` of `v`, as built by `byte`
+fn as_byte(s: &Sentence) -> Option<(&Val<Sentence>, usize)> {
+    let a = match s {
+        Sentence::BinOp {
+            a,
+            b,
+            op: BinOp::BitAnd,
+        } if concrete(&b.0) == Some(0xff) => a,
+        _ => return None,
+    };
+    if let Sentence::BinOp {
+        a: v,
+        b,
+        op: BinOp::RShift,
+    } = a.0.as_ref()
+    {
+        if let Some(shift) = concrete(&b.0).filter(|s| s % 8 == 0 && *s > 0) {
+            return Some((v, shift as usize / 8));
+        }
+    }
+    Some((a, 0))
+}
+
+pub(crate) fn concrete(s: &Sentence) -> Option<u128> {
+    match s {
+        Sentence::Basic(Value::Concrete(CSimpleVal::Number(n))) => Some(n.clone().into()),
+        _ => None,
+    }
+}
+
+pub(crate) fn number(v: u128) -> Sentence {
+    Sentence::Basic(Value::Concrete(CSimpleVal::Number(CNumber::U64(v as u64))))
+}
+
+fn op(a: Sentence, b: Sentence, op: BinOp) -> Sentence {
+    Sentence::BinOp {
+        a: Val::new(a),
+        b: Val::new(b),
+        op,
+    }
+}
@@ -1,22 +1,56 @@
-use crate::value::Sentence;
-use crate::value::visitors::base_interpreter::{Interpreter, AdHocInterpreter, Hook};
-use crate::stack::{StackVal};
-use rhai::{Engine, EvalAltResult};
+use serde::{Deserialize, Serialize};
 
+// Byte order of words in memory
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Endianness {
+    #[default]
+    Big,
+    Little,
+}
+
+// What memory at concrete addresses does with an access at a symbolic address. Memory in
+// the theory of arrays takes symbolic addresses as they are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AddressPolicy {
+    // Fail with `MemoryError::SymbolicAddress`
+    #[default]
+    Reject,
+    // Fork one branch per feasible concrete address, for up to `limit` addresses, each
+    // constraining the address to its value
+    Fork {
+        limit: usize,
+    },
+    // Continue with a single feasible address, constrained to that value
+    Concretize,
+    // Read an ite chain over every cell, and write every cell conditionally, without
+    // forking. Precise, but the terms grow with the size of memory.
+    IteChain,
+}
 
-#[derive(Default, Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MemoryConfig {
+    // Bytes per word, at most 16
     pub word_size: u16,
+    pub endianness: Endianness,
+    // Whether writes past the end grow memory (zero filled) rather than fail
+    pub auto_grow: bool,
+    // `Fork` and `Concretize` need a solver, so only `run_with_solver` resolves them; other
+    // interpreters treat them as `Reject`
+    pub address_policy: AddressPolicy,
+    // Bits in an address, the range `run_with_solver` searches for the values of a symbolic
+    // address under `Fork` and `Concretize`
+    pub address_width: u32,
 }
 
-impl MemoryConfig {
-
-    pub fn stack_val_to_mem_addr<MemAddr, H>(&self, s: StackVal, type_converter: String) -> MemAddr
-    where
-        H: Fn(Sentence) -> MemAddr
-    {
-        // let post = self.stack_mem_addr;
-        // let val = AdHocInterpreter::interpret(post, type_converter, s.0.clone());
-        todo!()
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            word_size: 8,
+            endianness: Endianness::Big,
+            auto_grow: true,
+            address_policy: AddressPolicy::Reject,
+            address_width: 64,
+        }
     }
 }
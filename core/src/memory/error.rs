@@ -0,0 +1,25 @@
+use thiserror::{self, Error};
+
+use super::region::{Fault, SymbolicRegionAccess};
+use crate::stack::StackVal;
+
+#[derive(Debug, Error)]
+pub enum MemoryError {
+    #[error("Access of {size} bytes at {addr} is out of bounds of memory of {len} bytes")]
+    OutOfBounds {
+        addr: usize,
+        size: usize,
+        len: usize,
+    },
+    #[error("Memory address {0} is not a concrete number")]
+    SymbolicAddress(StackVal),
+    #[error("Unsupported word size {0}, expected 1 to 16 bytes")]
+    WordSize(u16),
+    // Boxed to keep `MemoryError` small
+    #[error(transparent)]
+    Fault(Box<Fault>),
+    #[error(transparent)]
+    SymbolicRegionAccess(Box<SymbolicRegionAccess>),
+    #[error("Region {0} overlaps region {1}")]
+    RegionOverlap(String, String),
+}
@@ -1,69 +1,808 @@
+use serde::{Deserialize, Serialize};
+
 use crate::stack::StackVal;
+use crate::value::{Sentence, Val};
 
 use super::{
-    config::MemoryConfig,
+    array, bytes,
+    config::{AddressPolicy, MemoryConfig},
+    error::MemoryError,
     record::{MemOpRecord, MemRecord},
+    region::{Access, Fault, Region, SymbolicRegionAccess},
     val::MemVal,
 };
 
-#[derive(Clone, Default)]
+#[derive(Clone, Serialize, Deserialize)]
+enum Cells {
+    // Concrete addresses, holding possibly symbolic bytes
+    Concrete(Vec<MemVal>),
+    // An array term, see `array.rs`
+    Array(Val<Sentence>),
+}
+
+impl Default for Cells {
+    fn default() -> Self {
+        Cells::Concrete(vec![])
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct Memory {
-    inner: Vec<MemVal>,
+    inner: Cells,
     // TODO should be a reference
     config: MemoryConfig,
+    // Symbolic addresses fixed to a concrete one, see `pin_address`
+    #[serde(default)]
+    pinned: Vec<(Sentence, usize)>,
+    // Sorted by base address, without overlaps
+    #[serde(default)]
+    regions: Vec<Region>,
+    // Symbolic byte addresses known to lie outside the named region, see `exclude_region`
+    #[serde(default)]
+    excluded: Vec<(Sentence, String)>,
 }
 
 impl Memory {
     pub fn new(init: Vec<MemVal>, config: MemoryConfig) -> Self {
         Self {
-            inner: init,
+            inner: Cells::Concrete(init),
             config,
+            pinned: vec![],
+            regions: vec![],
+            excluded: vec![],
         }
     }
 
-    pub fn read_word(&self, idx: StackVal) -> Option<StackVal> {
-        let idx_unwrapped = Into::<usize>::into(idx);
+    // Memory in the theory of arrays whose initial bytes are the symbolic vector `name`.
+    // Addresses and values may be symbolic, and memory has no end.
+    pub fn symbolic(name: &str, config: MemoryConfig) -> Self {
+        Self {
+            inner: Cells::Array(array::symbolic_base(name)),
+            config,
+            pinned: vec![],
+            regions: vec![],
+            excluded: vec![],
+        }
+    }
+
+    pub fn config(&self) -> &MemoryConfig {
+        &self.config
+    }
+
+    // The same contents in the theory of arrays, zero past the current end
+    pub fn to_array(&self) -> Self {
+        let inner = match &self.inner {
+            Cells::Concrete(cells) => {
+                let base =
+                    array::concrete_base(cells.iter().map(|b| b.as_byte().unwrap_or(0)).collect());
+                let symbolic = cells
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, b)| b.as_byte().is_none());
+                Cells::Array(symbolic.fold(base, |a, (i, b)| {
+                    array::store(&a, &StackVal::from(i).0, b.0.clone())
+                }))
+            }
+            Cells::Array(a) => Cells::Array(a.clone()),
+        };
+        Self {
+            inner,
+            config: self.config.clone(),
+            pinned: self.pinned.clone(),
+            regions: self.regions.clone(),
+            excluded: self.excluded.clone(),
+        }
+    }
 
-        // TODO(will): Check endianness/byte ordering
-        let mut bytes: [u8; 8] = [0; 8];
+    // The array term of memory in the theory of arrays
+    pub fn as_array(&self) -> Option<&Sentence> {
+        match &self.inner {
+            Cells::Array(a) => Some(&a.0),
+            Cells::Concrete(_) => None,
+        }
+    }
 
-        for i in 0..=7 {
-            let byte: u8 = (*self.read_byte_inner(idx_unwrapped + i)?).into();
-            bytes[i] = byte
+    // Memory with the accesses to `region` limited to its permissions. Contents are
+    // unchanged, so read-only data is given with the initial contents.
+    pub fn with_region(&self, region: Region) -> Result<Self, MemoryError> {
+        if let Some(r) = self
+            .regions
+            .iter()
+            .find(|r| r.overlaps(region.base, region.end()))
+        {
+            return Err(MemoryError::RegionOverlap(region.name, r.name.clone()));
         }
+        let mut mem = self.clone();
+        let i = mem.regions.partition_point(|r| r.base < region.base);
+        mem.regions.insert(i, region);
+        Ok(mem)
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    pub fn region(&self, addr: usize) -> Option<&Region> {
+        self.regions.iter().find(|r| r.contains(addr))
+    }
+
+    // Memory that accesses `addr` at `value` from now on, for a path on which the two are
+    // equal. Used to resolve symbolic addresses under `AddressPolicy::Fork` and
+    // `AddressPolicy::Concretize`.
+    pub fn pin_address(&self, addr: StackVal, value: usize) -> Self {
+        let mut mem = self.clone();
+        mem.pinned.push((addr.0, value));
+        mem
+    }
+
+    // Memory on which the `size` bytes at `addr` don't overlap `region`, for a path that
+    // excludes it (see `Region::overlap`). Accesses at `addr` then pass the region's
+    // permission check, and `ite` chains at `addr` leave out the region's cells.
+    pub fn exclude_region(&self, addr: &StackVal, size: usize, region: &str) -> Self {
+        let mut mem = self.clone();
+        mem.excluded
+            .extend((0..size).map(|i| (offset(addr, i).0, region.to_string())));
+        mem
+    }
 
-        Some(u64::from_be_bytes(bytes).into())
+    // Whether every byte of the access is known to lie outside `region`
+    fn excludes(&self, idx: &StackVal, size: usize, region: &Region) -> bool {
+        (0..size).all(|i| {
+            let addr = offset(idx, i).0;
+            self.excluded
+                .iter()
+                .any(|(a, r)| *a == addr && *r == region.name)
+        })
     }
 
+    // The address ranges of the regions the byte at `idx` is known to lie outside of
+    fn excluded_ranges(&self, idx: &StackVal) -> Vec<(usize, usize)> {
+        self.regions
+            .iter()
+            .filter(|r| self.excludes(idx, 1, r))
+            .map(|r| (r.base, r.end()))
+            .collect()
+    }
+
+    // A word of `word_size` bytes, as a u64 up to 8 bytes and a u128 above. `None` past the
+    // end of concrete memory; see `load_word` for the reason.
+    pub fn read_word(&self, idx: StackVal) -> Option<StackVal> {
+        self.load_word(&idx).ok()
+    }
+
+    // Concrete memory only; see `load_byte`
     pub fn read_byte(&self, idx: StackVal) -> Option<&MemVal> {
-        self.read_byte_inner(Into::<usize>::into(idx))
+        self.check(&idx, 1, Access::Read).ok()?;
+        match &self.inner {
+            Cells::Concrete(bytes) => bytes.get(self.address(&idx).ok()?),
+            Cells::Array(_) => None,
+        }
+    }
+
+    // The byte at `idx` as a value, for either kind of memory. Concrete memory needs an
+    // address within bounds, which is concrete unless the address policy says otherwise.
+    pub fn load_byte(&self, idx: &StackVal) -> Result<StackVal, MemoryError> {
+        self.check(idx, 1, Access::Read)?;
+        match &self.inner {
+            Cells::Array(a) => Ok(StackVal(array::select(a, &idx.0))),
+            Cells::Concrete(bytes) => match self.address(idx) {
+                Ok(addr) => bytes.get(addr).map(|b| StackVal::from(b.clone())).ok_or(
+                    MemoryError::OutOfBounds {
+                        addr,
+                        size: 1,
+                        len: bytes.len(),
+                    },
+                ),
+                Err(_) if self.config.address_policy == AddressPolicy::IteChain => {
+                    Ok(StackVal(ite_chain(bytes, idx, &self.excluded_ranges(idx))))
+                }
+                Err(e) => Err(e),
+            },
+        }
+    }
+
+    // Like `read_word`, for either kind of memory
+    pub fn load_word(&self, idx: &StackVal) -> Result<StackVal, MemoryError> {
+        let size = self.word_size()?;
+        self.check(idx, size, Access::Read)?;
+        let bytes = match &self.inner {
+            Cells::Array(a) => (0..size)
+                .map(|i| array::select(a, &offset(idx, i).0))
+                .collect(),
+            Cells::Concrete(bytes) => match self.address(idx) {
+                Ok(addr) => addr
+                    .checked_add(size)
+                    .and_then(|end| bytes.get(addr..end))
+                    .ok_or(MemoryError::OutOfBounds {
+                        addr,
+                        size,
+                        len: bytes.len(),
+                    })?
+                    .iter()
+                    .map(|b| b.0.clone())
+                    .collect(),
+                Err(_) if self.config.address_policy == AddressPolicy::IteChain => (0..size)
+                    .map(|i| {
+                        let addr = offset(idx, i);
+                        ite_chain(bytes, &addr, &self.excluded_ranges(&addr))
+                    })
+                    .collect(),
+                Err(e) => return Err(e),
+            },
+        };
+        Ok(StackVal(bytes::join(bytes, self.config.endianness)))
+    }
+
+    fn word_size(&self) -> Result<usize, MemoryError> {
+        match self.config.word_size {
+            1..=16 => Ok(self.config.word_size as usize),
+            n => Err(MemoryError::WordSize(n)),
+        }
+    }
+
+    // The concrete address of `idx`, which is either a concrete number or pinned
+    fn address(&self, idx: &StackVal) -> Result<usize, MemoryError> {
+        let pinned = || {
+            self.pinned
+                .iter()
+                .find(|(a, _)| *a == idx.0)
+                .map(|(_, v)| *v)
+        };
+        concrete(idx)
+            .and_then(|a| usize::try_from(a).ok())
+            .or_else(pinned)
+            .ok_or_else(|| MemoryError::SymbolicAddress(idx.clone()))
     }
 
-    fn read_byte_inner(&self, idx: usize) -> Option<&MemVal> {
-        self.inner.get(idx)
+    // Faults at the lowest address of the `size` bytes at `idx` that a region doesn't allow
+    // `access` to. A symbolic address fails unless it is known to lie outside every region
+    // that doesn't allow the access, for the caller to decide which side it is on.
+    fn check(&self, idx: &StackVal, size: usize, access: Access) -> Result<(), MemoryError> {
+        if self.regions.is_empty() {
+            return Ok(());
+        }
+        let addr = match self.address(idx) {
+            Ok(addr) => addr,
+            Err(_) => {
+                return match self
+                    .regions
+                    .iter()
+                    .find(|r| !r.permissions.allows(access) && !self.excludes(idx, size, r))
+                {
+                    Some(r) => Err(MemoryError::SymbolicRegionAccess(Box::new(
+                        SymbolicRegionAccess {
+                            addr: idx.clone(),
+                            size,
+                            access,
+                            region: r.clone(),
+                        },
+                    ))),
+                    None => Ok(()),
+                };
+            }
+        };
+        let end = addr.saturating_add(size);
+        match self
+            .regions
+            .iter()
+            .find(|r| r.overlaps(addr, end) && !r.permissions.allows(access))
+        {
+            Some(r) => Err(MemoryError::Fault(Box::new(Fault {
+                region: r.name.clone(),
+                addr: addr.max(r.base).into(),
+                access,
+            }))),
+            None => Ok(()),
+        }
     }
 
-    pub fn apply(&self, r: MemRecord) -> Self {
+    pub fn apply(&self, r: MemRecord) -> Result<Self, MemoryError> {
         // TODO(will) - we should use a copy on write data structure
-        let mut inner = self.inner.clone();
-
-        // for c in r.changed {
-        //     match c {
-        //         MemOpRecord::Write(idx, val) => {
-        //             // TODO(will): Check endianness/byte ordering
-        //             let idx_usize = Into::<usize>::into(idx);
-        //             let val_unwrapped = Into::<u64>::into(val).to_be_bytes();
-
-        //             for i in 0..=7 {
-        //                 inner[idx_usize + i] = val_unwrapped[i].into();
-        //             }
-        //         }
-        //     }
-        // }
+        let mut mem = self.clone();
 
-        Self {
-            inner,
-            config: self.config.clone(),
+        for c in r.changed {
+            let (idx, size) = match &c {
+                MemOpRecord::Write(idx, _) => (idx, self.word_size()?),
+                MemOpRecord::WriteByte(idx, _) => (idx, 1),
+            };
+            self.check(idx, size, Access::Write)?;
+            if let Cells::Array(a) = &mut mem.inner {
+                *a = self.store(a, c)?;
+            } else {
+                mem.write(c)?;
+            }
+        }
+
+        Ok(mem)
+    }
+
+    // The array term with the write applied
+    fn store(&self, a: &Val<Sentence>, c: MemOpRecord) -> Result<Val<Sentence>, MemoryError> {
+        match c {
+            MemOpRecord::Write(idx, val) => {
+                let bytes = bytes::split(&val.0, self.word_size()?, self.config.endianness);
+                Ok(bytes.into_iter().enumerate().fold(a.clone(), |a, (i, b)| {
+                    array::store(&a, &offset(&idx, i).0, b)
+                }))
+            }
+            MemOpRecord::WriteByte(idx, val) => {
+                Ok(array::store(a, &idx.0, bytes::byte(&Val::new(val.0), 0)))
+            }
+        }
+    }
+
+    fn write(&mut self, c: MemOpRecord) -> Result<(), MemoryError> {
+        match c {
+            MemOpRecord::Write(idx, val) => {
+                let bytes = bytes::split(&val.0, self.word_size()?, self.config.endianness);
+                self.write_bytes(&idx, bytes.into_iter().map(MemVal::from).collect())
+            }
+            MemOpRecord::WriteByte(idx, val) => {
+                let b = bytes::byte(&Val::new(val.0), 0);
+                self.write_bytes(&idx, vec![b.into()])
+            }
+        }
+    }
+
+    fn write_bytes(&mut self, idx: &StackVal, bytes: Vec<MemVal>) -> Result<(), MemoryError> {
+        let addr = match self.address(idx) {
+            Ok(addr) => addr,
+            Err(_) if self.config.address_policy == AddressPolicy::IteChain => {
+                self.write_ite_chain(idx, bytes);
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        let auto_grow = self.config.auto_grow;
+        let inner = match &mut self.inner {
+            Cells::Concrete(inner) => inner,
+            Cells::Array(_) => unreachable!(),
+        };
+        let out_of_bounds = |len| MemoryError::OutOfBounds {
+            addr,
+            size: bytes.len(),
+            len,
+        };
+        let end = addr
+            .checked_add(bytes.len())
+            .ok_or_else(|| out_of_bounds(inner.len()))?;
+
+        if end > inner.len() {
+            if !auto_grow {
+                return Err(out_of_bounds(inner.len()));
+            }
+            inner.resize(end, 0_u8.into());
+        }
+        for (i, b) in bytes.into_iter().enumerate() {
+            inner[addr + i] = b;
+        }
+        Ok(())
+    }
+
+    // Every cell the write may land on becomes `ite(idx + i == cell, byte i, old)`. The
+    // extent of the write is unknown, so memory doesn't grow. Cells of regions the address
+    // is known to lie outside of are left alone.
+    fn write_ite_chain(&mut self, idx: &StackVal, bytes: Vec<MemVal>) {
+        let excluded: Vec<_> = (0..bytes.len())
+            .map(|i| self.excluded_ranges(&offset(idx, i)))
+            .collect();
+        let inner = match &mut self.inner {
+            Cells::Concrete(inner) => inner,
+            Cells::Array(_) => unreachable!(),
+        };
+        for (i, b) in bytes.into_iter().enumerate() {
+            let addr = offset(idx, i);
+            for (j, cell) in inner.iter_mut().enumerate() {
+                if in_ranges(&excluded[i], j) {
+                    continue;
+                }
+                let hit = addr._eq(&StackVal::from(j));
+                *cell = MemVal(hit.ite(StackVal(b.0.clone()), StackVal(cell.0.clone())).0);
+            }
+        }
+    }
+}
+
+// The byte at symbolic address `idx` as `ite(idx == 0, cell 0, ite(idx == 1, ...))`, zero
+// past the end. Cells in the `excluded` ranges are left out.
+fn ite_chain(cells: &[MemVal], idx: &StackVal, excluded: &[(usize, usize)]) -> Sentence {
+    let zero = StackVal(MemVal::from(0_u8).0);
+    let chain = cells
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(i, _)| !in_ranges(excluded, *i))
+        .fold(zero, |rest, (i, b)| {
+            idx._eq(&StackVal::from(i)).ite(StackVal(b.0.clone()), rest)
+        });
+    chain.0
+}
+
+fn in_ranges(ranges: &[(usize, usize)], addr: usize) -> bool {
+    ranges
+        .iter()
+        .any(|(start, end)| *start <= addr && addr < *end)
+}
+
+fn concrete(v: &StackVal) -> Option<u128> {
+    v.as_cnumber().map(|n| u128::from(n.clone()))
+}
+
+pub(super) fn offset(idx: &StackVal, i: usize) -> StackVal {
+    match i {
+        0 => idx.clone(),
+        i => idx.clone() + StackVal::from(i),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::{Endianness, Permissions};
+
+    fn write(mem: &Memory, op: MemOpRecord) -> Result<Memory, MemoryError> {
+        mem.apply(MemRecord { changed: vec![op] })
+    }
+
+    fn bytes(mem: &Memory) -> Vec<u8> {
+        match &mem.inner {
+            Cells::Concrete(inner) => inner.iter().map(|b| b.as_byte().unwrap()).collect(),
+            Cells::Array(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_write_word() {
+        let val = StackVal::from(0x0102_u64);
+        let mem = write(
+            &Memory::default(),
+            MemOpRecord::Write(2_u64.into(), val.clone()),
+        )
+        .unwrap();
+        assert_eq!(bytes(&mem), [0, 0, 0, 0, 0, 0, 0, 0, 1, 2]);
+        assert_eq!(mem.read_word(2_u64.into()), Some(val.clone()));
+        assert_eq!(mem.read_word(3_u64.into()), None);
+
+        let config = MemoryConfig {
+            word_size: 4,
+            endianness: Endianness::Little,
+            ..MemoryConfig::default()
+        };
+        let mem = write(
+            &Memory::new(vec![], config),
+            MemOpRecord::Write(0_u64.into(), val.clone()),
+        )
+        .unwrap();
+        assert_eq!(bytes(&mem), [2, 1, 0, 0]);
+        assert_eq!(mem.read_word(0_u64.into()), Some(val));
+
+        // Bits beyond the word are dropped
+        let mem = write(
+            &mem,
+            MemOpRecord::Write(0_u64.into(), StackVal::from(u64::MAX)),
+        )
+        .unwrap();
+        assert_eq!(
+            mem.read_word(0_u64.into()),
+            Some(StackVal::from(0xffff_ffff_u64))
+        );
+    }
+
+    #[test]
+    fn test_write_byte() {
+        let mem = Memory::new(vec![7.into(); 4], MemoryConfig::default());
+        let mem = write(&mem, MemOpRecord::WriteByte(1_u64.into(), 0x1ff_u64.into())).unwrap();
+        assert_eq!(bytes(&mem), [7, 0xff, 7, 7]);
+
+        let mem = write(&mem, MemOpRecord::WriteByte(5_u64.into(), 1_u64.into())).unwrap();
+        assert_eq!(bytes(&mem), [7, 0xff, 7, 7, 0, 1]);
+    }
+
+    #[test]
+    fn test_write_errors() {
+        let config = MemoryConfig {
+            auto_grow: false,
+            ..MemoryConfig::default()
+        };
+        let mem = Memory::new(vec![0.into(); 8], config);
+        assert!(write(&mem, MemOpRecord::Write(0_u64.into(), 1_u64.into())).is_ok());
+        assert_eq!(
+            write(&mem, MemOpRecord::Write(1_u64.into(), 1_u64.into()))
+                .err()
+                .unwrap()
+                .to_string(),
+            "Access of 8 bytes at 1 is out of bounds of memory of 8 bytes"
+        );
+
+        let x = StackVal("x".parse().unwrap());
+        assert!(matches!(
+            write(&mem, MemOpRecord::WriteByte(x, 1_u64.into())),
+            Err(MemoryError::SymbolicAddress(_))
+        ));
+
+        let config = MemoryConfig {
+            word_size: 32,
+            ..MemoryConfig::default()
+        };
+        assert!(matches!(
+            write(
+                &Memory::new(vec![], config),
+                MemOpRecord::Write(0_u64.into(), 1_u64.into())
+            ),
+            Err(MemoryError::WordSize(32))
+        ));
+    }
+
+    #[test]
+    fn test_array_memory() {
+        let config = MemoryConfig {
+            word_size: 2,
+            ..MemoryConfig::default()
+        };
+        let x = StackVal("x".parse().unwrap());
+        let v = StackVal("v".parse().unwrap());
+        let mem = Memory::symbolic("m", config);
+        let mem = write(&mem, MemOpRecord::Write(0_u64.into(), 0x0102_u64.into())).unwrap();
+        let mem = write(&mem, MemOpRecord::WriteByte(x.clone(), v.clone())).unwrap();
+        assert_eq!(
+            mem.as_array().unwrap().to_string(),
+            "m[0 := 1][1 := 2][x := v & 255]"
+        );
+
+        // Reads at concrete addresses see through stores to other concrete addresses, but
+        // not past the store to `x`
+        assert_eq!(
+            mem.load_byte(&5_u64.into()).unwrap().to_string(),
+            "m[0 := 1][1 := 2][x := v & 255][5]"
+        );
+        let mem2 = write(&mem, MemOpRecord::WriteByte(7_u64.into(), 3_u64.into())).unwrap();
+        assert_eq!(
+            mem2.load_byte(&7_u64.into()).unwrap(),
+            StackVal::from(3_u64)
+        );
+        assert_eq!(
+            mem2.load_byte(&6_u64.into()).unwrap().to_string(),
+            "m[0 := 1][1 := 2][x := v & 255][6]"
+        );
+        let before = mem.to_array();
+        let mem = write(&mem, MemOpRecord::Write(4_u64.into(), v.clone())).unwrap();
+        assert_eq!(
+            mem.load_word(&4_u64.into()).unwrap().to_string(),
+            "v & 65535"
+        );
+        assert_eq!(
+            mem.load_byte(&x).unwrap().to_string().matches(":=").count(),
+            5
+        );
+        assert_eq!(
+            before.read_word(0_u64.into()).unwrap().to_string(),
+            "m[0 := 1][1 := 2][x := v & 255][0] << 8 | m[0 := 1][1 := 2][x := v & 255][1]"
+        );
+
+        // Concrete memory keeps its bytes and reads zero past its end
+        let mem = Memory::new(vec![1.into(), 2.into()], config_le()).to_array();
+        assert_eq!(
+            mem.load_word(&0_u64.into()).unwrap(),
+            StackVal::from(0x0201_u64)
+        );
+        assert_eq!(
+            mem.load_word(&1_u64.into()).unwrap(),
+            StackVal::from(0x02_u64)
+        );
+        let mem = write(&mem, MemOpRecord::Write(1_u64.into(), 0xaabb_u64.into())).unwrap();
+        assert_eq!(
+            mem.load_word(&0_u64.into()).unwrap(),
+            StackVal::from(0xbb01_u64)
+        );
+        assert_eq!(
+            mem.load_word(&1_u64.into()).unwrap(),
+            StackVal::from(0xaabb_u64)
+        );
+    }
+
+    #[test]
+    fn test_symbolic_bytes() {
+        let v = StackVal("v".parse().unwrap());
+        let w = StackVal("w".parse().unwrap());
+
+        // Reading exactly what was written gives the value back
+        let mem = write(
+            &Memory::default(),
+            MemOpRecord::Write(0_u64.into(), v.clone()),
+        )
+        .unwrap();
+        assert_eq!(mem.read_word(0_u64.into()), Some(v.clone()));
+        assert_eq!(mem.load_byte(&7_u64.into()).unwrap().to_string(), "v & 255");
+        assert_eq!(
+            mem.load_byte(&0_u64.into()).unwrap().to_string(),
+            "v >> 56 & 255"
+        );
+
+        let mem = write(
+            &Memory::new(vec![], config_le()),
+            MemOpRecord::Write(0_u64.into(), v.clone()),
+        )
+        .unwrap();
+        assert_eq!(
+            mem.read_word(0_u64.into()).unwrap().to_string(),
+            "v & 65535"
+        );
+        assert_eq!(mem.load_byte(&0_u64.into()).unwrap().to_string(), "v & 255");
+
+        // A read across two writes concatenates their bytes
+        let mem = write(&mem, MemOpRecord::Write(2_u64.into(), w)).unwrap();
+        assert_eq!(
+            mem.read_word(1_u64.into()).unwrap().to_string(),
+            "(w & 255) << 8 | v >> 8 & 255"
+        );
+
+        // Overwriting a byte breaks the match
+        let mem = write(&mem, MemOpRecord::WriteByte(0_u64.into(), 1_u64.into())).unwrap();
+        assert_eq!(
+            mem.read_word(0_u64.into()).unwrap().to_string(),
+            "(v >> 8 & 255) << 8 | 1"
+        );
+
+        // Symbolic bytes carry over to array memory
+        let mem = mem.to_array();
+        assert_eq!(
+            mem.as_array().unwrap().to_string(),
+            "0x01000000[1 := v >> 8 & 255][2 := w & 255][3 := w >> 8 & 255]"
+        );
+        assert_eq!(
+            mem.load_word(&2_u64.into()).unwrap().to_string(),
+            "w & 65535"
+        );
+    }
+
+    #[test]
+    fn test_ite_chain() {
+        let config = MemoryConfig {
+            word_size: 2,
+            address_policy: AddressPolicy::IteChain,
+            ..MemoryConfig::default()
+        };
+        let x = StackVal("x".parse().unwrap());
+        let mem = Memory::new(vec![1.into(), 2.into(), 3.into()], config);
+        assert_eq!(
+            mem.load_byte(&x).unwrap().to_string(),
+            "x == 0 ? 1 : x == 1 ? 2 : x == 2 ? 3 : 0"
+        );
+        assert_eq!(
+            mem.read_word(x.clone()).unwrap().to_string(),
+            "(x == 0 ? 1 : x == 1 ? 2 : x == 2 ? 3 : 0) << 8 \
+             | (x + 1 == 0 ? 1 : x + 1 == 1 ? 2 : x + 1 == 2 ? 3 : 0)"
+        );
+
+        // Writes update every cell and don't grow memory
+        let mem = write(&mem, MemOpRecord::WriteByte(x.clone(), 7_u64.into())).unwrap();
+        assert_eq!(
+            mem.load_byte(&1_u64.into()).unwrap().to_string(),
+            "x == 1 ? 7 : 2"
+        );
+        assert!(mem.load_byte(&3_u64.into()).is_err());
+
+        // Without the policy, symbolic addresses are rejected unless pinned
+        let mem = Memory::new(vec![1.into(), 2.into()], MemoryConfig::default());
+        assert!(matches!(
+            mem.load_byte(&x),
+            Err(MemoryError::SymbolicAddress(a)) if a == x
+        ));
+        let mem = mem.pin_address(x.clone(), 1);
+        assert_eq!(mem.load_byte(&x).unwrap(), StackVal::from(2_u64));
+        let mem = write(&mem, MemOpRecord::WriteByte(x, 5_u64.into())).unwrap();
+        assert_eq!(bytes(&mem), [1, 5]);
+    }
+
+    #[test]
+    fn test_regions() {
+        let config = MemoryConfig {
+            word_size: 2,
+            ..MemoryConfig::default()
+        };
+        let mem = Memory::new(vec![1.into(); 8], config)
+            .with_region(Region::new("guard", 6, 1, Permissions::NONE))
+            .unwrap()
+            .with_region(Region::new("rom", 0, 4, Permissions::READ_ONLY))
+            .unwrap();
+        assert!(matches!(
+            mem.with_region(Region::new("heap", 3, 2, Permissions::READ_WRITE)),
+            Err(MemoryError::RegionOverlap(a, b)) if a == "heap" && b == "rom"
+        ));
+        assert_eq!(mem.regions()[0].name, "rom");
+        assert_eq!(mem.region(6).unwrap().name, "guard");
+        assert!(mem.region(4).is_none());
+
+        assert_eq!(
+            mem.load_word(&2_u64.into()).unwrap(),
+            StackVal::from(0x0101_u64)
+        );
+        assert_eq!(
+            write(&mem, MemOpRecord::Write(3_u64.into(), 5_u64.into()))
+                .err()
+                .unwrap()
+                .to_string(),
+            "Cannot write address 3 in region rom"
+        );
+        assert!(write(&mem, MemOpRecord::Write(4_u64.into(), 5_u64.into())).is_ok());
+        assert!(matches!(
+            mem.load_word(&5_u64.into()),
+            Err(MemoryError::Fault(f)) if f.addr == 6_u64.into() && f.access == Access::Read
+        ));
+        assert_eq!(mem.read_byte(6_u64.into()), None);
+
+        // Array memory needs a symbolic address to be kept clear of regions it can't write
+        let mem = mem.to_array();
+        let x = StackVal("x".parse().unwrap());
+        assert!(write(&mem, MemOpRecord::WriteByte(0_u64.into(), 5_u64.into())).is_err());
+        assert!(matches!(
+            write(&mem, MemOpRecord::WriteByte(x.clone(), 5_u64.into())),
+            Err(MemoryError::SymbolicRegionAccess(a)) if a.region.name == "rom"
+        ));
+        let mem = mem.exclude_region(&x, 1, "rom");
+        assert!(matches!(
+            write(&mem, MemOpRecord::WriteByte(x.clone(), 5_u64.into())),
+            Err(MemoryError::SymbolicRegionAccess(a)) if a.region.name == "guard"
+        ));
+        let mem = mem.exclude_region(&x, 1, "guard");
+        assert!(write(&mem, MemOpRecord::WriteByte(x, 5_u64.into())).is_ok());
+    }
+
+    #[test]
+    fn test_symbolic_region_access() {
+        let config = MemoryConfig {
+            word_size: 1,
+            address_policy: AddressPolicy::IteChain,
+            ..MemoryConfig::default()
+        };
+        let mem = Memory::new(vec![1.into(), 2.into(), 3.into(), 4.into()], config)
+            .with_region(Region::new("rom", 0, 2, Permissions::READ_ONLY))
+            .unwrap()
+            .with_region(Region::new("guard", 3, 1, Permissions::NONE))
+            .unwrap();
+        let x = StackVal("x".parse().unwrap());
+
+        // The write may land in the ROM, so it waits for the path to rule that out
+        let err = write(&mem, MemOpRecord::WriteByte(x.clone(), 7_u64.into()))
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "Symbolic address x may write region rom");
+        let hit = match err {
+            MemoryError::SymbolicRegionAccess(a) => a.region.overlap(&a.addr, a.size),
+            _ => unreachable!(),
+        };
+        assert_eq!(hit.to_string(), "x >= 0 && x < 2");
+
+        // Off the ROM and the guard, the write leaves their cells alone
+        let clear = mem
+            .exclude_region(&x, 1, "rom")
+            .exclude_region(&x, 1, "guard");
+        let written = write(&clear, MemOpRecord::WriteByte(x.clone(), 7_u64.into())).unwrap();
+        assert_eq!(written.load_byte(&0_u64.into()).unwrap().to_string(), "1");
+        assert_eq!(written.load_byte(&1_u64.into()).unwrap().to_string(), "2");
+        assert_eq!(
+            written.load_byte(&2_u64.into()).unwrap().to_string(),
+            "x == 2 ? 7 : 3"
+        );
+        assert!(matches!(&written.inner, Cells::Concrete(c) if c[3].as_byte() == Some(4)));
+
+        // Reads may hit the readable ROM, but not the guard
+        assert!(matches!(
+            mem.load_byte(&x),
+            Err(MemoryError::SymbolicRegionAccess(a)) if a.region.name == "guard"
+        ));
+        let clear = mem.exclude_region(&x, 1, "guard");
+        assert_eq!(
+            clear.load_byte(&x).unwrap().to_string(),
+            "x == 0 ? 1 : x == 1 ? 2 : x == 2 ? 3 : 0"
+        );
+    }
+
+    fn config_le() -> MemoryConfig {
+        MemoryConfig {
+            word_size: 2,
+            endianness: Endianness::Little,
+            ..MemoryConfig::default()
         }
     }
 }
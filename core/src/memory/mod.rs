@@ -1,7 +1,16 @@
+mod array;
+mod bytes;
 mod config;
+mod error;
+#[allow(clippy::module_inception)]
 mod memory;
 mod record;
+mod region;
 mod val;
 
+pub use config::{AddressPolicy, Endianness, MemoryConfig};
+pub use error::MemoryError;
 pub use memory::Memory;
 pub use record::{MemOpRecord, MemRecord};
+pub use region::{Access, Fault, Permissions, Region, SymbolicRegionAccess};
+pub use val::MemVal;
@@ -5,5 +5,8 @@ pub struct MemRecord {
 }
 
 pub enum MemOpRecord {
+    // A word of `MemoryConfig::word_size` bytes at the address
     Write(StackVal, StackVal),
+    // The low byte of the value
+    WriteByte(StackVal, StackVal),
 }
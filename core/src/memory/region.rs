@@ -0,0 +1,141 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+use thiserror::{self, Error};
+
+use super::memory::offset;
+use crate::{
+    constraint::Constraint,
+    stack::StackVal,
+    value::{BinOp, Sentence, Val},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Permissions {
+    pub const NONE: Self = Self {
+        read: false,
+        write: false,
+    };
+    pub const READ_ONLY: Self = Self {
+        read: true,
+        write: false,
+    };
+    pub const READ_WRITE: Self = Self {
+        read: true,
+        write: true,
+    };
+
+    pub fn allows(&self, access: Access) -> bool {
+        match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+        }
+    }
+}
+
+// A named range of addresses, e.g. a read-only data segment or a writable heap. Accesses to
+// addresses outside every region are not restricted.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    pub name: String,
+    pub base: usize,
+    pub size: usize,
+    pub permissions: Permissions,
+}
+
+impl Region {
+    pub fn new(name: &str, base: usize, size: usize, permissions: Permissions) -> Self {
+        Self {
+            name: name.to_string(),
+            base,
+            size,
+            permissions,
+        }
+    }
+
+    // One past the last address, saturating
+    pub fn end(&self) -> usize {
+        self.base.saturating_add(self.size)
+    }
+
+    pub fn contains(&self, addr: usize) -> bool {
+        self.base <= addr && addr < self.end()
+    }
+
+    pub(crate) fn overlaps(&self, start: usize, end: usize) -> bool {
+        self.base < end && start < self.end()
+    }
+
+    // The condition for one of the `size` bytes at `addr` to lie in the region
+    pub fn overlap(&self, addr: &StackVal, size: usize) -> Constraint {
+        let base = StackVal::from(self.base).0;
+        let end = StackVal::from(self.end()).0;
+        (0..size)
+            .map(|i| {
+                let a = offset(addr, i).0;
+                Constraint::gte(a.clone(), base.clone()).and(Constraint::lt(a, end.clone()))
+            })
+            .reduce(Constraint::or)
+            .unwrap_or(Constraint::False)
+    }
+}
+
+// An access a region's permissions don't allow. Symbolic execution ends the path there,
+// see `AbstractMachine::fault`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Error)]
+#[error("Cannot {access} address {addr} in region {region}")]
+pub struct Fault {
+    pub region: String,
+    // The first byte of the access that lies in the region. It is symbolic for an access at
+    // a symbolic address, see `SymbolicRegionAccess::fault`.
+    pub addr: StackVal,
+    pub access: Access,
+}
+
+// An access at a symbolic address that may reach a region not permitting it. Symbolic
+// execution forks on whether the address lies in the region, see `Memory::exclude_region`.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+#[error("Symbolic address {addr} may {access} region {}", region.name)]
+pub struct SymbolicRegionAccess {
+    pub addr: StackVal,
+    pub size: usize,
+    pub access: Access,
+    pub region: Region,
+}
+
+impl SymbolicRegionAccess {
+    // The fault on the path where the access does reach the region
+    pub fn fault(&self) -> Fault {
+        let base = StackVal::from(self.region.base);
+        let below = StackVal(Sentence::BinOp {
+            a: Val::new(self.addr.0.clone()),
+            b: Val::new(base.0.clone()),
+            op: BinOp::Lt,
+        });
+        Fault {
+            region: self.region.name.clone(),
+            addr: below.ite(base, self.addr.clone()),
+            access: self.access,
+        }
+    }
+}
@@ -1,17 +1,34 @@
-// TODO(will) - ultimately this will be replaced with the more general value type
-// that implements an ast
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy)]
-pub struct MemVal(u8);
+use crate::stack::StackVal;
+use crate::value::Sentence;
 
-impl Into<u8> for MemVal {
-    fn into(self) -> u8 {
-        self.0
+use super::bytes::{concrete, number};
+
+// A byte of memory, possibly symbolic
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemVal(pub Sentence);
+
+impl MemVal {
+    pub fn as_byte(&self) -> Option<u8> {
+        concrete(&self.0).map(|b| b as u8)
     }
 }
 
 impl From<u8> for MemVal {
     fn from(x: u8) -> Self {
-        MemVal(x)
+        MemVal(number(x as u128))
+    }
+}
+
+impl From<Sentence> for MemVal {
+    fn from(s: Sentence) -> Self {
+        MemVal(s)
+    }
+}
+
+impl From<MemVal> for StackVal {
+    fn from(x: MemVal) -> Self {
+        StackVal(x.0)
     }
 }
@@ -0,0 +1,550 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::{
+    constraint::{CmpType, Constraint, Node},
+    stack::StackVal,
+    value::{BinOp, CNumber, CSimpleVal, SNumber, SSimpleVal, Sentence, TernaryOp, UnaryOp, Value},
+};
+
+// Options for printing sentences and constraints in infix form. `Display` uses the defaults.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PrettyConfig {
+    // Annotate numbers and symbols with their width or sort, e.g. `x:u64 + 1:u8`
+    pub sorts: bool,
+    // Nodes nested deeper than this are printed as `…`
+    pub max_depth: Option<usize>,
+    // Print a subtree that occurs several times once, labelled `#n=(..)`, and refer to it
+    // as `#n` afterwards. Off by default; without it, printing a DAG can take exponential
+    // space.
+    pub sharing: bool,
+}
+
+impl PrettyConfig {
+    pub fn sorts(mut self, sorts: bool) -> Self {
+        self.sorts = sorts;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    pub fn sharing(mut self, sharing: bool) -> Self {
+        self.sharing = sharing;
+        self
+    }
+}
+
+// Something printed with a `PrettyConfig`, e.g. `format!("{}", s.pretty(config))`
+pub struct Pretty<'a, T> {
+    item: &'a T,
+    config: PrettyConfig,
+}
+
+impl Sentence {
+    pub fn pretty(&self, config: PrettyConfig) -> Pretty<'_, Sentence> {
+        Pretty { item: self, config }
+    }
+}
+
+impl Constraint {
+    pub fn pretty(&self, config: PrettyConfig) -> Pretty<'_, Constraint> {
+        Pretty { item: self, config }
+    }
+}
+
+// Binding strength, loosest first. Binary operators at the same level associate to the
+// left, except comparisons, which don't associate.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Prec {
+    Ite,
+    Or,
+    And,
+    Cmp,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shift,
+    Add,
+    Mul,
+    Prefix,
+    Atom,
+}
+
+fn bin_op(op: &BinOp) -> (&'static str, Prec) {
+    match op {
+        BinOp::Plus => ("+", Prec::Add),
+        BinOp::Minus => ("-", Prec::Add),
+        BinOp::Mul => ("*", Prec::Mul),
+        BinOp::Div => ("/", Prec::Mul),
+        BinOp::Mod => ("%", Prec::Mul),
+        BinOp::Eq => ("==", Prec::Cmp),
+        BinOp::Neq => ("!=", Prec::Cmp),
+        BinOp::Lt => ("<", Prec::Cmp),
+        BinOp::Lte => ("<=", Prec::Cmp),
+        BinOp::Gt => (">", Prec::Cmp),
+        BinOp::Gte => (">=", Prec::Cmp),
+        BinOp::BitOr => ("|", Prec::BitOr),
+        BinOp::BitAnd => ("&", Prec::BitAnd),
+        BinOp::BitXor => ("^", Prec::BitXor),
+        BinOp::LShift => ("<<", Prec::Shift),
+        BinOp::RShift => (">>", Prec::Shift),
+        // Postfix, printed as `a[b]`
+        BinOp::Select => ("[]", Prec::Atom),
+    }
+}
+
+fn cmp_op(cmp: &CmpType) -> &'static str {
+    match cmp {
+        CmpType::GT(..) => ">",
+        CmpType::LT(..) => "<",
+        CmpType::GTE(..) => ">=",
+        CmpType::LTE(..) => "<=",
+        CmpType::EQ(..) => "==",
+        CmpType::NEQ(..) => "!=",
+    }
+}
+
+fn number_sort(n: &CNumber) -> String {
+    format!("u{}", n.bit_width())
+}
+
+// Either kind of node of a sentence or constraint DAG
+#[derive(Clone, Copy)]
+enum Term<'a> {
+    Sentence(&'a Sentence),
+    Constraint(&'a Constraint),
+}
+
+impl<'a> Term<'a> {
+    fn node(n: &'a Node) -> Self {
+        match n {
+            Node::Simple(s) => Term::Sentence(s),
+            Node::Compound(c) => Term::Constraint(c),
+        }
+    }
+
+    // A constraint and the sentence it asserts can share an address
+    fn key(&self) -> (*const (), bool) {
+        match self {
+            Term::Sentence(s) => (*s as *const Sentence as *const (), false),
+            Term::Constraint(c) => (*c as *const Constraint as *const (), true),
+        }
+    }
+
+    // Leaves are never labelled as shared
+    fn is_leaf(&self) -> bool {
+        match self {
+            Term::Sentence(s) => matches!(s, Sentence::Basic(_)),
+            Term::Constraint(c) => matches!(c, Constraint::True | Constraint::False),
+        }
+    }
+
+    fn children(&self) -> Vec<Term<'a>> {
+        match self {
+            Term::Sentence(Sentence::Basic(_)) => vec![],
+            Term::Sentence(Sentence::UnaryOp { a, .. }) => vec![Term::Sentence(&a.0)],
+            Term::Sentence(Sentence::BinOp { a, b, .. }) => {
+                vec![Term::Sentence(&a.0), Term::Sentence(&b.0)]
+            }
+            Term::Sentence(Sentence::TernaryOp { a, b, c, .. }) => vec![
+                Term::Sentence(&a.0),
+                Term::Sentence(&b.0),
+                Term::Sentence(&c.0),
+            ],
+            Term::Constraint(c) => match c {
+                Constraint::True | Constraint::False => vec![],
+                Constraint::Assert(n) | Constraint::Not(n) => vec![Term::node(n)],
+                Constraint::And(l, r) | Constraint::Or(l, r) => {
+                    vec![Term::node(l), Term::node(r)]
+                }
+                Constraint::BinCmp(cmp) => {
+                    let (l, r) = cmp.operands();
+                    vec![Term::node(l), Term::node(r)]
+                }
+                Constraint::Ite(c, t, e) => {
+                    vec![Term::Constraint(c), Term::node(t), Term::node(e)]
+                }
+            },
+        }
+    }
+
+    fn prec(&self) -> Prec {
+        match self {
+            Term::Sentence(s) => match s {
+                Sentence::Basic(_) => Prec::Atom,
+                Sentence::UnaryOp { .. } => Prec::Prefix,
+                Sentence::BinOp { op, .. } => bin_op(op).1,
+                Sentence::TernaryOp {
+                    op: TernaryOp::Ite, ..
+                } => Prec::Ite,
+                Sentence::TernaryOp {
+                    op: TernaryOp::Store,
+                    ..
+                } => Prec::Atom,
+            },
+            Term::Constraint(c) => match c {
+                Constraint::True | Constraint::False => Prec::Atom,
+                Constraint::Assert(n) => Term::node(n).prec(),
+                Constraint::Not(_) => Prec::Prefix,
+                Constraint::And(..) => Prec::And,
+                Constraint::Or(..) => Prec::Or,
+                Constraint::BinCmp(_) => Prec::Cmp,
+                Constraint::Ite(..) => Prec::Ite,
+            },
+        }
+    }
+}
+
+struct Printer<'a, 'f, 'b> {
+    f: &'f mut fmt::Formatter<'b>,
+    config: &'a PrettyConfig,
+    // Nodes that occur more than once, and the label they were printed with, if any yet
+    shared: HashMap<(*const (), bool), Option<usize>>,
+    labels: usize,
+}
+
+impl<'a, 'f, 'b> Printer<'a, 'f, 'b> {
+    fn print(f: &'f mut fmt::Formatter<'b>, config: &'a PrettyConfig, root: Term) -> fmt::Result {
+        let mut shared = HashMap::new();
+        if config.sharing {
+            let mut seen = HashSet::new();
+            let mut todo = vec![root];
+            while let Some(t) = todo.pop() {
+                if t.is_leaf() {
+                    continue;
+                }
+                if seen.insert(t.key()) {
+                    todo.extend(t.children());
+                } else {
+                    shared.insert(t.key(), None);
+                }
+            }
+        }
+        Printer {
+            f,
+            config,
+            shared,
+            labels: 0,
+        }
+        .term(root, Prec::Ite, 0)
+    }
+
+    // Prints `t` where an operand binding at least as tightly as `min` is expected
+    fn term(&mut self, t: Term, min: Prec, depth: usize) -> fmt::Result {
+        if self.config.max_depth.is_some_and(|max| depth > max) {
+            return write!(self.f, "…");
+        }
+        if let Some(label) = self.shared.get_mut(&t.key()) {
+            match label {
+                Some(n) => return write!(self.f, "#{}", n),
+                None => {
+                    self.labels += 1;
+                    *label = Some(self.labels);
+                    write!(self.f, "#{}=", self.labels)?;
+                    return self.parenthesized(t, Prec::Atom, depth);
+                }
+            }
+        }
+        self.parenthesized(t, min, depth)
+    }
+
+    fn parenthesized(&mut self, t: Term, min: Prec, depth: usize) -> fmt::Result {
+        if t.prec() < min {
+            write!(self.f, "(")?;
+            self.inner(t, depth)?;
+            write!(self.f, ")")
+        } else {
+            self.inner(t, depth)
+        }
+    }
+
+    fn infix(&mut self, l: Term, op: &str, r: Term, prec: Prec, depth: usize) -> fmt::Result {
+        let (left, right) = match prec {
+            Prec::Cmp => (Prec::BitOr, Prec::BitOr),
+            _ => (prec, Self::tighter(prec)),
+        };
+        self.term(l, left, depth + 1)?;
+        write!(self.f, " {} ", op)?;
+        self.term(r, right, depth + 1)
+    }
+
+    fn tighter(p: Prec) -> Prec {
+        match p {
+            Prec::Ite => Prec::Or,
+            Prec::Or => Prec::And,
+            Prec::And => Prec::Cmp,
+            Prec::Cmp => Prec::BitOr,
+            Prec::BitOr => Prec::BitXor,
+            Prec::BitXor => Prec::BitAnd,
+            Prec::BitAnd => Prec::Shift,
+            Prec::Shift => Prec::Add,
+            Prec::Add => Prec::Mul,
+            Prec::Mul => Prec::Prefix,
+            Prec::Prefix | Prec::Atom => Prec::Atom,
+        }
+    }
+
+    fn ite(&mut self, c: Term, t: Term, e: Term, depth: usize) -> fmt::Result {
+        self.term(c, Prec::Or, depth + 1)?;
+        write!(self.f, " ? ")?;
+        self.term(t, Prec::Ite, depth + 1)?;
+        write!(self.f, " : ")?;
+        self.term(e, Prec::Ite, depth + 1)
+    }
+
+    fn inner(&mut self, t: Term, depth: usize) -> fmt::Result {
+        match t {
+            Term::Sentence(s) => match s {
+                Sentence::Basic(v) => self.value(v),
+                Sentence::UnaryOp { a, op } => {
+                    let op = match op {
+                        UnaryOp::Not => "!",
+                        UnaryOp::BitNot => "~",
+                    };
+                    write!(self.f, "{}", op)?;
+                    self.term(Term::Sentence(&a.0), Prec::Prefix, depth + 1)
+                }
+                Sentence::BinOp {
+                    a,
+                    b,
+                    op: BinOp::Select,
+                } => {
+                    self.term(Term::Sentence(&a.0), Prec::Atom, depth + 1)?;
+                    write!(self.f, "[")?;
+                    self.term(Term::Sentence(&b.0), Prec::Ite, depth + 1)?;
+                    write!(self.f, "]")
+                }
+                Sentence::BinOp { a, b, op } => {
+                    let (op, prec) = bin_op(op);
+                    self.infix(Term::Sentence(&a.0), op, Term::Sentence(&b.0), prec, depth)
+                }
+                Sentence::TernaryOp {
+                    a,
+                    b,
+                    c,
+                    op: TernaryOp::Ite,
+                } => self.ite(
+                    Term::Sentence(&a.0),
+                    Term::Sentence(&b.0),
+                    Term::Sentence(&c.0),
+                    depth,
+                ),
+                Sentence::TernaryOp {
+                    a,
+                    b,
+                    c,
+                    op: TernaryOp::Store,
+                } => {
+                    self.term(Term::Sentence(&a.0), Prec::Atom, depth + 1)?;
+                    write!(self.f, "[")?;
+                    self.term(Term::Sentence(&b.0), Prec::Ite, depth + 1)?;
+                    write!(self.f, " := ")?;
+                    self.term(Term::Sentence(&c.0), Prec::Ite, depth + 1)?;
+                    write!(self.f, "]")
+                }
+            },
+            Term::Constraint(c) => match c {
+                Constraint::True => write!(self.f, "true"),
+                Constraint::False => write!(self.f, "false"),
+                // Already parenthesized as the asserted node
+                Constraint::Assert(n) => self.term(Term::node(n), Prec::Ite, depth),
+                Constraint::Not(n) => {
+                    write!(self.f, "!")?;
+                    self.term(Term::node(n), Prec::Prefix, depth + 1)
+                }
+                Constraint::And(l, r) => {
+                    self.infix(Term::node(l), "&&", Term::node(r), Prec::And, depth)
+                }
+                Constraint::Or(l, r) => {
+                    self.infix(Term::node(l), "||", Term::node(r), Prec::Or, depth)
+                }
+                Constraint::BinCmp(cmp) => {
+                    let (l, r) = cmp.operands();
+                    self.infix(Term::node(l), cmp_op(cmp), Term::node(r), Prec::Cmp, depth)
+                }
+                Constraint::Ite(c, t, e) => {
+                    self.ite(Term::Constraint(c), Term::node(t), Term::node(e), depth)
+                }
+            },
+        }
+    }
+
+    fn value(&mut self, v: &Value) -> fmt::Result {
+        let sorts = self.config.sorts;
+        match v {
+            Value::Concrete(CSimpleVal::Boolean(b)) => {
+                write!(self.f, "{}", bool::from(b.clone()))
+            }
+            Value::Concrete(CSimpleVal::Number(n)) => {
+                write!(self.f, "{}", u128::from(n.clone()))?;
+                if sorts {
+                    write!(self.f, ":{}", number_sort(n))?;
+                }
+                Ok(())
+            }
+            Value::Concrete(CSimpleVal::Vector(v)) => write!(self.f, "0x{}", hex::encode(&v.0)),
+            Value::Symbolic(s) => {
+                let (name, sort) = match s {
+                    SSimpleVal::SymbolicBool(id) => (&id.0, "bool".to_string()),
+                    SSimpleVal::SymbolicNumber(SNumber(id)) => {
+                        (&id.0, id.1.as_ref().map_or("num".to_string(), number_sort))
+                    }
+                    SSimpleVal::SymbolicVector(v) => (&v.0 .0, "vec".to_string()),
+                };
+                write!(self.f, "{}", name)?;
+                if sorts {
+                    write!(self.f, ":{}", sort)?;
+                }
+                Ok(())
+            }
+            Value::Either(_) => write!(self.f, "_"),
+        }
+    }
+}
+
+impl fmt::Display for Pretty<'_, Sentence> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Printer::print(f, &self.config, Term::Sentence(self.item))
+    }
+}
+
+impl fmt::Display for Pretty<'_, Constraint> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Printer::print(f, &self.config, Term::Constraint(self.item))
+    }
+}
+
+impl fmt::Display for Sentence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.pretty(PrettyConfig::default()).fmt(f)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Printer {
+            f,
+            config: &PrettyConfig::default(),
+            shared: HashMap::new(),
+            labels: 0,
+        }
+        .value(self)
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.pretty(PrettyConfig::default()).fmt(f)
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Printer::print(f, &PrettyConfig::default(), Term::node(self))
+    }
+}
+
+impl fmt::Display for CmpType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (l, r) = self.operands();
+        write!(f, "{} {} {}", l, cmp_op(self), r)
+    }
+}
+
+impl fmt::Display for StackVal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{num, sym};
+    use crate::value::{SymbolId, Val};
+
+    fn op(a: Sentence, op: BinOp, b: Sentence) -> Sentence {
+        Sentence::BinOp {
+            a: Val::new(a),
+            b: Val::new(b),
+            op,
+        }
+    }
+
+    #[test]
+    fn test_minimal_parentheses() {
+        let x = || sym("x");
+        let sum = op(x(), BinOp::Plus, num(1));
+
+        assert_eq!(
+            op(sum.clone(), BinOp::Mul, num(2)).to_string(),
+            "(x + 1) * 2"
+        );
+        assert_eq!(
+            op(num(2), BinOp::Mul, sum.clone()).to_string(),
+            "2 * (x + 1)"
+        );
+        assert_eq!(op(sum.clone(), BinOp::Minus, x()).to_string(), "x + 1 - x");
+        assert_eq!(
+            op(x(), BinOp::Minus, sum.clone()).to_string(),
+            "x - (x + 1)"
+        );
+        assert_eq!(
+            op(op(x(), BinOp::Lt, num(1)), BinOp::Eq, x()).to_string(),
+            "(x < 1) == x"
+        );
+        let ite = Sentence::TernaryOp {
+            a: Val::new(op(x(), BinOp::Gt, num(0))),
+            b: Val::new(sum),
+            c: Val::new(Sentence::UnaryOp {
+                a: Val::new(op(x(), BinOp::BitAnd, num(3))),
+                op: UnaryOp::BitNot,
+            }),
+            op: TernaryOp::Ite,
+        };
+        assert_eq!(ite.to_string(), "x > 0 ? x + 1 : ~(x & 3)");
+
+        let c = (Constraint::gt(x(), num(1)).or(Constraint::eq(x(), num(3))))
+            .and(!Constraint::lte(op(x(), BinOp::Mod, num(2)), num(0)));
+        assert_eq!(c.to_string(), "(x > 1 || x == 3) && !(x % 2 <= 0)");
+        assert_eq!(
+            StackVal::from(sym("y"))
+                .ite(StackVal::from(5u64), StackVal::from(6u64))
+                .to_string(),
+            "y ? 5 : 6"
+        );
+    }
+
+    #[test]
+    fn test_config() {
+        let x = Sentence::Basic(Value::Symbolic(SSimpleVal::SymbolicNumber(SNumber(
+            SymbolId("x".to_string(), Some(CNumber::U8(0))),
+        ))));
+        let sum = Val::new(op(x, BinOp::Plus, num(1)));
+        let shared = Sentence::BinOp {
+            a: sum.clone(),
+            b: sum,
+            op: BinOp::Mul,
+        };
+
+        assert_eq!(shared.to_string(), "(x + 1) * (x + 1)");
+        assert_eq!(
+            shared
+                .pretty(PrettyConfig::default().sharing(true))
+                .to_string(),
+            "#1=(x + 1) * #1"
+        );
+        let config = PrettyConfig::default().sorts(true);
+        assert_eq!(
+            shared.pretty(config.clone()).to_string(),
+            "(x:u8 + 1:u64) * (x:u8 + 1:u64)"
+        );
+        assert_eq!(
+            shared.pretty(config.max_depth(1)).to_string(),
+            "(… + …) * (… + …)"
+        );
+    }
+}
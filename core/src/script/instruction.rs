@@ -0,0 +1,479 @@
+// Instructions whose semantics are Rhai functions. An instruction set is a script with one
+// function per instruction, called as `fn name(stack, mem, env, args..)` where `args` are
+// the instruction's immediates. `stack.peek(i)` gives the i-th value from the top as a
+// sentence and `stack.len` the depth; `mem.read_byte(addr)` and `mem.read_word(addr)`
+// read memory at concrete addresses, a byte being an INT unless it is symbolic.
+//
+// A function returns its effect as a map with any of the keys
+//
+//     pop: INT                   values popped, before pushing
+//     push: [Sentence | INT]     values pushed, last on top
+//     writes: [[addr, value]]    memory writes
+//     pc: INT                    jump target; otherwise execution falls through
+//     halt: bool
+//     constraints: [Sentence]    boolean sentences the branch asserts
+//
+// or an array of such maps, one per branch, to fork symbolic execution. For example:
+//
+//     fn add(stack, mem, env) {
+//         #{ pop: 2, push: [stack.peek(0) + stack.peek(1)] }
+//     }
+//
+//     fn jumpi(stack, mem, env, dest) {
+//         let c = stack.peek(0);
+//         [
+//             #{ pop: 1, pc: dest, constraints: [bin_op(BinOp::Neq, c, num(0))] },
+//             #{ pop: 1, constraints: [bin_op(BinOp::Eq, c, num(0))] },
+//         ]
+//     }
+use std::path::Path;
+use std::rc::Rc;
+
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST, INT};
+
+use super::{sentence_engine, ScriptError};
+use crate::constraint::{Constraint, Node};
+use crate::environment::Env;
+use crate::instructions::error::InstructionError;
+use crate::instructions::{AbstractExecRecord, AbstractInstruction, InstructionResult};
+use crate::memory::{MemOpRecord, MemRecord, Memory};
+use crate::stack::{Stack, StackOpRecord, StackRecord, StackVal};
+use crate::storage::Storage;
+use crate::value::Sentence;
+
+fn address(addr: INT) -> Result<StackVal, Box<EvalAltResult>> {
+    u64::try_from(addr)
+        .map(StackVal::from)
+        .map_err(|_| format!("Invalid address {}", addr).into())
+}
+
+// Registers `Stack`, `Memory` and `Env` as read-only types on `engine`
+pub fn register_machine_api(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<Stack>("Stack")
+        .register_fn("peek", |stack: &mut Stack, idx: INT| {
+            usize::try_from(idx)
+                .ok()
+                .and_then(|idx| stack.peek(idx))
+                .map(|v| v.0.clone())
+                .ok_or_else(|| -> Box<EvalAltResult> {
+                    format!("Cannot peek at {} in a stack of {}", idx, stack.len()).into()
+                })
+        })
+        .register_get("len", |stack: &mut Stack| stack.len() as INT)
+        .register_type_with_name::<Memory>("Memory")
+        .register_fn("read_byte", |mem: &mut Memory, addr: INT| {
+            mem.read_byte(address(addr)?)
+                .map(|b| match b.as_byte() {
+                    Some(b) => Dynamic::from(b as INT),
+                    None => Dynamic::from(b.0.clone()),
+                })
+                .ok_or_else(|| -> Box<EvalAltResult> {
+                    format!("Cannot read byte at {}", addr).into()
+                })
+        })
+        .register_fn("read_word", |mem: &mut Memory, addr: INT| {
+            mem.read_word(address(addr)?)
+                .map(|v| v.0)
+                .ok_or_else(|| -> Box<EvalAltResult> {
+                    format!("Cannot read word at {}", addr).into()
+                })
+        })
+        .register_type_with_name::<Env>("Env");
+}
+
+// The instructions defined by a script
+pub struct ScriptInstructionSet {
+    engine: Rc<Engine>,
+    ast: Rc<AST>,
+}
+
+impl ScriptInstructionSet {
+    pub fn compile(script: &str) -> Result<Self, ScriptError> {
+        let engine = Self::engine();
+        let ast = engine.compile(script).map_err(Box::<EvalAltResult>::from)?;
+        Ok(Self::new(engine, ast))
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ScriptError> {
+        let engine = Self::engine();
+        let ast = engine.compile_file(path.as_ref().to_path_buf())?;
+        Ok(Self::new(engine, ast))
+    }
+
+    fn engine() -> Engine {
+        let mut engine = sentence_engine();
+        register_machine_api(&mut engine);
+        engine
+    }
+
+    fn new(engine: Engine, ast: AST) -> Self {
+        Self {
+            engine: Rc::new(engine),
+            ast: Rc::new(ast),
+        }
+    }
+
+    // The instruction `name` with the given immediates
+    pub fn instruction(
+        &self,
+        name: &str,
+        args: Vec<Dynamic>,
+    ) -> Result<ScriptInstruction, ScriptError> {
+        let arity = 3 + args.len();
+        if !self
+            .ast
+            .iter_functions()
+            .any(|f| f.name == name && f.params.len() == arity)
+        {
+            return Err(ScriptError::MissingFunction(format!("{}/{}", name, arity)));
+        }
+        Ok(ScriptInstruction {
+            engine: self.engine.clone(),
+            ast: self.ast.clone(),
+            name: name.to_string(),
+            args,
+        })
+    }
+}
+
+pub struct ScriptInstruction {
+    engine: Rc<Engine>,
+    ast: Rc<AST>,
+    name: String,
+    args: Vec<Dynamic>,
+}
+
+impl ScriptInstruction {
+    fn fail(&self, message: impl std::fmt::Display) -> InstructionError {
+        InstructionError::InstructionExecutionFailure(format!("{}: {}", self.name, message))
+    }
+
+    // The machine is copied into the script; stack values share their sentences
+    fn branches(&self, stack: &Stack, mem: &Memory, env: &Env) -> InstructionResult<Vec<Map>> {
+        let mut args = vec![
+            Dynamic::from(stack.clone()),
+            Dynamic::from(mem.clone()),
+            Dynamic::from(env.clone()),
+        ];
+        args.extend(self.args.iter().cloned());
+        let res: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), &self.ast, &self.name, args)
+            .map_err(|e| self.fail(e))?;
+
+        if res.is_map() {
+            return Ok(vec![res.cast::<Map>()]);
+        }
+        res.try_cast::<Array>()
+            .ok_or_else(|| self.fail("expected a map or an array of maps"))?
+            .into_iter()
+            .map(|b| {
+                b.try_cast::<Map>()
+                    .ok_or_else(|| self.fail("expected a map"))
+            })
+            .collect()
+    }
+
+    fn record(&self, mut branch: Map) -> InstructionResult<AbstractExecRecord> {
+        let mut take = |key: &str| branch.remove(key);
+        let (pop, push, writes, pc, halt, constraints) = (
+            take("pop"),
+            take("push"),
+            take("writes"),
+            take("pc"),
+            take("halt"),
+            take("constraints"),
+        );
+        if let Some(key) = branch.keys().next() {
+            return Err(self.fail(format!("unknown key {}", key)));
+        }
+
+        let int = |v: Dynamic, what: &str| {
+            v.as_int()
+                .ok()
+                .and_then(|i| usize::try_from(i).ok())
+                .ok_or_else(|| self.fail(format!("{} must be a non-negative integer", what)))
+        };
+        let array = |v: Dynamic, what: &str| {
+            v.try_cast::<Array>()
+                .ok_or_else(|| self.fail(format!("{} must be an array", what)))
+        };
+        let sentence = |v: Dynamic, what: &str| {
+            if let Ok(i) = v.as_int() {
+                return u64::try_from(i)
+                    .map(|i| StackVal::from(i).0)
+                    .map_err(|_| self.fail(format!("{} cannot be negative", what)));
+            }
+            v.try_cast::<Sentence>()
+                .ok_or_else(|| self.fail(format!("{} must be a sentence or an integer", what)))
+        };
+
+        let mut record = AbstractExecRecord::default();
+
+        let mut changed = vec![];
+        if let Some(pop) = pop {
+            changed.extend(std::iter::repeat_n(StackOpRecord::Pop, int(pop, "pop")?));
+        }
+        for v in push
+            .map(|p| array(p, "push"))
+            .transpose()?
+            .unwrap_or_default()
+        {
+            changed.push(StackOpRecord::Push(StackVal(sentence(v, "pushed value")?)));
+        }
+        if !changed.is_empty() {
+            record.stack_diff = Some(StackRecord { changed });
+        }
+
+        if let Some(writes) = writes {
+            let mut changed = vec![];
+            for w in array(writes, "writes")? {
+                let w = array(w, "write")?;
+                let [addr, val]: [Dynamic; 2] = w
+                    .try_into()
+                    .map_err(|_| self.fail("a write must be [addr, value]"))?;
+                changed.push(MemOpRecord::Write(
+                    StackVal(sentence(addr, "address")?),
+                    StackVal(sentence(val, "written value")?),
+                ));
+            }
+            record.mem_diff = Some(MemRecord { changed });
+        }
+
+        record.pc_change = pc.map(|pc| int(pc, "pc")).transpose()?;
+        if let Some(halt) = halt {
+            record.halt = halt
+                .as_bool()
+                .map_err(|_| self.fail("halt must be a boolean"))?;
+        }
+        if let Some(constraints) = constraints {
+            record.constraints = Some(
+                array(constraints, "constraints")?
+                    .into_iter()
+                    .map(|c| {
+                        let c = sentence(c, "constraint")?;
+                        Ok(Constraint::Assert(Node::Simple(c)))
+                    })
+                    .collect::<InstructionResult<_>>()?,
+            );
+        }
+        Ok(record)
+    }
+}
+
+// Concrete execution takes the only branch
+impl AbstractInstruction<AbstractExecRecord> for ScriptInstruction {
+    fn exec(
+        &self,
+        stack: &Stack,
+        mem: &Memory,
+        env: &Env,
+        _storage: &Storage,
+    ) -> InstructionResult<AbstractExecRecord> {
+        let mut branches = self.branches(stack, mem, env)?;
+        if branches.len() != 1 {
+            return Err(self.fail(format!("expected a single branch, got {}", branches.len())));
+        }
+        self.record(branches.remove(0))
+    }
+}
+
+impl AbstractInstruction<Vec<AbstractExecRecord>> for ScriptInstruction {
+    fn exec(
+        &self,
+        stack: &Stack,
+        mem: &Memory,
+        env: &Env,
+        _storage: &Storage,
+    ) -> InstructionResult<Vec<AbstractExecRecord>> {
+        self.branches(stack, mem, env)?
+            .into_iter()
+            .map(|b| self.record(b))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::machine::inner_interpreter::{ConcreteInnerInterpreter, SymbolicInnerInterpreter};
+    use crate::machine::outer_interpreter::{
+        ConcreteOuterInterpreter, OuterInterpreter, SymbolicOuterInterpreter, UnknownPolicy,
+    };
+    use crate::machine::r#abstract::AbstractMachine;
+
+    const ISA: &str = r#"
+        fn push(stack, mem, env, v) { #{ push: [v] } }
+
+        fn add(stack, mem, env) { #{ pop: 2, push: [stack.peek(0) + stack.peek(1)] } }
+
+        // Folds constants, so concrete programs compute numbers
+        fn sub(stack, mem, env) {
+            let a = stack.peek(0);
+            let b = stack.peek(1);
+            let v = if a.is_number && b.is_number { num(a.number - b.number) } else { a - b };
+            #{ pop: 2, push: [v] }
+        }
+
+        fn jumpi(stack, mem, env, dest) {
+            let c = stack.peek(0);
+            [
+                #{ pop: 1, pc: dest, constraints: [bin_op(BinOp::Neq, c, num(0))] },
+                #{ pop: 1, constraints: [bin_op(BinOp::Eq, c, num(0))] },
+            ]
+        }
+
+        fn load(stack, mem, env) { #{ push: [mem.read_byte(stack.peek(0).number.to_int())] } }
+
+        fn store(stack, mem, env) { #{ pop: 2, writes: [[stack.peek(0), stack.peek(1)]] } }
+
+        fn stop(stack, mem, env) { #{ halt: true } }
+
+        fn depth(stack, mem, env) { #{ push: [stack.len] } }
+
+        fn bad(stack, mem, env) { #{ popp: 1 } }
+    "#;
+
+    fn machine<I>(pgm: &[I]) -> AbstractMachine<'_, I> {
+        AbstractMachine {
+            stack: Stack::default(),
+            mem: Memory::default(),
+            env: Env {},
+            storage: Storage::default(),
+            pc: Some(0),
+            pgm,
+            fault: None,
+        }
+    }
+
+    fn instructions(
+        isa: &ScriptInstructionSet,
+        pgm: &[(&str, Option<Dynamic>)],
+    ) -> Vec<ScriptInstruction> {
+        pgm.iter()
+            .map(|(name, arg)| isa.instruction(name, arg.iter().cloned().collect()))
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_concrete() {
+        let isa = ScriptInstructionSet::compile(ISA).unwrap();
+        let pgm = instructions(
+            &isa,
+            &[
+                ("push", Some(Dynamic::from_int(3))),
+                ("push", Some(Dynamic::from_int(10))),
+                ("sub", None),
+                ("depth", None),
+                ("stop", None),
+                ("push", Some(Dynamic::from_int(1))),
+            ],
+        );
+        let interpreter = ConcreteOuterInterpreter {
+            inner_interpreter: Box::new(ConcreteInnerInterpreter {}),
+        };
+
+        let m = interpreter.run(machine(&pgm)).unwrap();
+        assert!(m.pc.is_none());
+        assert_eq!(m.stack.len(), 2);
+        assert_eq!(m.stack.peek(0), Some(&StackVal::from(1_u64)));
+        assert_eq!(m.stack.peek(1), Some(&StackVal::from(7_u64)));
+
+        let record: AbstractExecRecord = isa
+            .instruction("store", vec![])
+            .unwrap()
+            .exec(&m.stack, &m.mem, &m.env, &m.storage)
+            .unwrap();
+        assert!(matches!(
+            &record.mem_diff.unwrap().changed[..],
+            [MemOpRecord::Write(addr, val)] if *addr == StackVal::from(1_u64) && *val == StackVal::from(7_u64)
+        ));
+    }
+
+    #[test]
+    fn test_symbolic() {
+        let isa = ScriptInstructionSet::compile(ISA).unwrap();
+        let x: Sentence = "x".parse().unwrap();
+        let pgm = instructions(
+            &isa,
+            &[
+                ("push", Some(Dynamic::from(x.clone()))),
+                ("push", Some(Dynamic::from_int(1))),
+                ("add", None),
+                ("jumpi", Some(Dynamic::from_int(5))),
+                ("push", Some(Dynamic::from_int(0))),
+                ("stop", None),
+            ],
+        );
+        let interpreter = SymbolicOuterInterpreter {
+            inner_interpreter: Box::new(SymbolicInnerInterpreter {}),
+            unknown_policy: UnknownPolicy::Keep,
+            normalize: false,
+        };
+
+        let leaves = interpreter.run(machine(&pgm)).unwrap();
+        let mut leaves: Vec<_> = leaves
+            .into_iter()
+            .map(|(m, path)| (m.stack.len(), path[0].to_string()))
+            .collect();
+        leaves.sort();
+        assert_eq!(
+            leaves,
+            vec![(0, "1 + x != 0".to_string()), (1, "1 + x == 0".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let isa = ScriptInstructionSet::compile(ISA).unwrap();
+        assert!(matches!(
+            isa.instruction("mul", vec![]),
+            Err(ScriptError::MissingFunction(f)) if f == "mul/3"
+        ));
+        assert!(matches!(
+            isa.instruction("push", vec![]),
+            Err(ScriptError::MissingFunction(_))
+        ));
+
+        let (stack, mem, env, storage) = (
+            Stack::default(),
+            Memory::default(),
+            Env {},
+            Storage::default(),
+        );
+        let exec = |name: &str, args: Vec<Dynamic>| -> InstructionResult<AbstractExecRecord> {
+            isa.instruction(name, args)
+                .unwrap()
+                .exec(&stack, &mem, &env, &storage)
+        };
+        let message = |r: InstructionResult<AbstractExecRecord>| match r {
+            Err(InstructionError::InstructionExecutionFailure(m)) => m,
+            _ => panic!("expected an execution failure"),
+        };
+
+        assert_eq!(message(exec("bad", vec![])), "bad: unknown key popp");
+        assert!(message(exec("add", vec![])).contains("Cannot peek at 0 in a stack of 0"));
+        assert_eq!(
+            message(exec("push", vec![Dynamic::from_int(-1)])),
+            "push: pushed value cannot be negative"
+        );
+        assert_eq!(
+            message(exec("push", vec![Dynamic::from("x")])),
+            "push: pushed value must be a sentence or an integer"
+        );
+
+        let stack = Stack::default().apply(StackRecord {
+            changed: vec![StackOpRecord::Push(StackVal::from(0_u64))],
+        });
+        let jumpi: ScriptInstruction = isa
+            .instruction("jumpi", vec![Dynamic::from_int(0)])
+            .unwrap();
+        let r: InstructionResult<AbstractExecRecord> = jumpi.exec(&stack, &mem, &env, &storage);
+        assert_eq!(message(r), "jumpi: expected a single branch, got 2");
+        let load: ScriptInstruction = isa.instruction("load", vec![]).unwrap();
+        let r: InstructionResult<AbstractExecRecord> = load.exec(&stack, &mem, &env, &storage);
+        assert!(message(r).contains("Cannot read byte at 0"));
+    }
+}
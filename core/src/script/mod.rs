@@ -0,0 +1,174 @@
+mod instruction;
+mod sentence;
+pub use instruction::{register_machine_api, ScriptInstruction, ScriptInstructionSet};
+pub use sentence::{register_sentence_api, sentence_module};
+
+use std::path::Path;
+use std::rc::Rc;
+
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+use thiserror::Error;
+
+use crate::value::visitors::base_interpreter::{Hook, HookError, Interpreter};
+use crate::value::Sentence;
+
+#[derive(Error, Debug)]
+pub enum ScriptError {
+    #[error("Failed to load script: {0}")]
+    Load(#[from] Box<EvalAltResult>),
+    #[error("Script defines neither fn pre(s) nor fn post(s)")]
+    NoRules,
+    #[error("Script does not define fn {0}")]
+    MissingFunction(String),
+}
+
+// An engine with the sentence API registered
+pub fn sentence_engine() -> Engine {
+    let mut engine = Engine::new();
+    // Rhai's release defaults; debug builds halve them, which is too shallow for nested
+    // sentence constructors
+    engine.set_max_expr_depths(64, 32);
+    register_sentence_api(&mut engine);
+    engine
+}
+
+// Rewrite rules written in Rhai. The script defines `fn pre(s)`, `fn post(s)` or both,
+// each returning a replacement sentence or `()` to leave the node alone; they run as the
+// pre and post hooks of an `Interpreter`. For example, to fold additions of constants:
+//
+//     fn post(s) {
+//         if s.is_bin_op && s.op == BinOp::Plus && s.a.is_number && s.b.is_number {
+//             return num(s.a.number + s.b.number);
+//         }
+//     }
+pub struct RewriteRules {
+    engine: Rc<Engine>,
+    ast: Rc<AST>,
+}
+
+impl RewriteRules {
+    pub fn compile(script: &str) -> Result<Self, ScriptError> {
+        let engine = sentence_engine();
+        let ast = engine.compile(script).map_err(Box::<EvalAltResult>::from)?;
+        Self::new(engine, ast)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ScriptError> {
+        let engine = sentence_engine();
+        let ast = engine.compile_file(path.as_ref().to_path_buf())?;
+        Self::new(engine, ast)
+    }
+
+    fn new(engine: Engine, ast: AST) -> Result<Self, ScriptError> {
+        let rules = Self {
+            engine: Rc::new(engine),
+            ast: Rc::new(ast),
+        };
+        if !rules.defines("pre") && !rules.defines("post") {
+            return Err(ScriptError::NoRules);
+        }
+        Ok(rules)
+    }
+
+    fn defines(&self, name: &str) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|f| f.name == name && f.params.len() == 1)
+    }
+
+    fn hook(&self, name: &'static str) -> Hook {
+        if !self.defines(name) {
+            return Box::new(|_s: &Sentence| Ok(None));
+        }
+        let (engine, ast) = (self.engine.clone(), self.ast.clone());
+        Box::new(move |s: &Sentence| {
+            let res: Dynamic = engine
+                .call_fn(&mut Scope::new(), &ast, name, (s.clone(),))
+                .map_err(|e| e.to_string())?;
+            if res.is_unit() {
+                Ok(None)
+            } else {
+                res.try_cast::<Sentence>()
+                    .map(Some)
+                    .ok_or_else(|| format!("{} did not return a sentence or ()", name))
+            }
+        })
+    }
+
+    pub fn pre_hook(&self) -> Hook {
+        self.hook("pre")
+    }
+
+    pub fn post_hook(&self) -> Hook {
+        self.hook("post")
+    }
+
+    pub fn apply(&self, sentence: Sentence) -> Result<Sentence, HookError> {
+        Interpreter { pgm: sentence }.interpret(self.pre_hook(), self.post_hook(), |s| s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const RULES: &str = r#"
+        // x + 0 and 0 + x
+        fn post(s) {
+            if s.is_bin_op && s.op == BinOp::Plus {
+                if s.b.is_number && s.b.number == u64(0) { return s.a; }
+                if s.a.is_number && s.a.number == u64(0) { return s.b; }
+            }
+        }
+
+        // Takes the branch of an ite on a constant, leaving the other one alone. The
+        // branch taken isn't rewritten by `pre`, but is still passed to `post`.
+        fn pre(s) {
+            if s.is_ternary_op && s.a.is_bool {
+                return if s.a.bool { s.b } else { s.c };
+            }
+        }
+    "#;
+
+    #[test]
+    fn test_rewrite_rules() {
+        let rules = RewriteRules::compile(RULES).unwrap();
+        let s: Sentence = "(* (+ x 0) (ite false y (+ 0 (+ z 0))))".parse().unwrap();
+        assert_eq!(rules.apply(s).unwrap(), "(* x (+ z 0))".parse().unwrap());
+
+        let path = std::env::temp_dir().join(format!("rules-{}.rhai", std::process::id()));
+        std::fs::write(&path, RULES).unwrap();
+        let rules = RewriteRules::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let s: Sentence = "(+ (+ 0 x) 0)".parse().unwrap();
+        assert_eq!(rules.apply(s).unwrap(), "x".parse().unwrap());
+    }
+
+    #[test]
+    fn test_rewrite_rule_errors() {
+        assert!(matches!(
+            RewriteRules::compile("fn post(s) {"),
+            Err(ScriptError::Load(_))
+        ));
+        assert!(matches!(
+            RewriteRules::compile("fn other(s) { s }"),
+            Err(ScriptError::NoRules)
+        ));
+        assert!(matches!(
+            RewriteRules::from_file("/nonexistent/rules.rhai"),
+            Err(ScriptError::Load(_))
+        ));
+
+        let rules = RewriteRules::compile("fn post(s) { if s.is_symbol { 1 } }").unwrap();
+        let err = rules.apply("(+ x 1)".parse().unwrap()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Post hook failed on x: post did not return a sentence or ()"
+        );
+        let rules = RewriteRules::compile("fn post(s) { s.a }").unwrap();
+        assert!(matches!(
+            rules.apply("x".parse().unwrap()),
+            Err(HookError::Post(..))
+        ));
+    }
+}
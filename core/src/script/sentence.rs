@@ -0,0 +1,497 @@
+// Rhai bindings for sentences. Scripts see `Sentence`, `Value`, `CNumber`, `BinOp` and
+// `UnaryOp` as custom types, and the operators as constants such as `BinOp::Plus`.
+//
+// Constructors: `num(5)` or `num(u8(5))`, `boolean(true)`, `sym("x")`, `sym_bool("b")`,
+// `parse_sentence("(+ x 1)")`, `basic(value)`, `bin_op(op, a, b)`, `unary_op(op, a)` and
+// `ite(c, t, e)`, and `store(m, i, v)` for byte arrays. `+ - * / %` on two sentences build
+// the corresponding `BinOp`.
+//
+// Inspection: `is_basic`, `is_bin_op`, `is_unary_op`, `is_ternary_op`, `is_number`,
+// `is_bool` and `is_symbol`, and the getters `op`, `a`, `b`, `c`, `value`, `number`,
+// `bool` and `symbol`. A getter that doesn't apply to the node is an error, except `op`
+// and `symbol`, which give `()`.
+//
+// Numbers: `u8(n)` to `u128(n)` build a `CNumber`, `+ - * /` wrap around at the wider
+// operand's width, `width` is the bit width and `to_int` converts back to an integer.
+use rhai::plugin::*;
+use rhai::{Engine, Module, INT};
+
+use crate::value::{BinOp, CNumber, CSimpleVal, Sentence, UnaryOp, Val, Value};
+
+fn basic_value(s: &Sentence) -> Result<&Value, Box<EvalAltResult>> {
+    match s {
+        Sentence::Basic(v) => Ok(v),
+        _ => Err(format!("{} is not a basic value", s).into()),
+    }
+}
+
+fn concrete_number(v: &Value) -> Option<&CNumber> {
+    match v {
+        Value::Concrete(CSimpleVal::Number(n)) => Some(n),
+        _ => None,
+    }
+}
+
+fn bin(op: BinOp, a: Sentence, b: Sentence) -> Sentence {
+    Sentence::BinOp {
+        a: Val::new(a),
+        b: Val::new(b),
+        op,
+    }
+}
+
+fn convert<T: TryFrom<INT>>(n: INT, name: &str) -> Result<T, Box<EvalAltResult>> {
+    T::try_from(n).map_err(|_| format!("{} does not fit in {}", n, name).into())
+}
+
+#[export_module]
+mod sentence_api {
+    use super::{basic_value, bin, concrete_number, convert};
+    use crate::value::{Bool, CSimpleVal, SNumber, SSimpleVal, SymbolId, TernaryOp, Val};
+    use rhai::INT;
+
+    pub type Sentence = crate::value::Sentence;
+    pub type Value = crate::value::Value;
+    pub type CNumber = crate::value::CNumber;
+    pub type BinOp = crate::value::BinOp;
+    pub type UnaryOp = crate::value::UnaryOp;
+
+    // ---- Constructors
+
+    #[rhai_fn(name = "num", return_raw)]
+    pub fn num_int(n: INT) -> Result<Sentence, Box<EvalAltResult>> {
+        Ok(num(u64(n)?))
+    }
+
+    pub fn num(n: CNumber) -> Sentence {
+        Sentence::Basic(Value::Concrete(CSimpleVal::Number(n)))
+    }
+
+    pub fn boolean(b: bool) -> Sentence {
+        Sentence::Basic(Value::Concrete(CSimpleVal::Boolean(b.into())))
+    }
+
+    pub fn sym(name: &str) -> Sentence {
+        Sentence::Basic(Value::Symbolic(SSimpleVal::SymbolicNumber(SNumber(
+            SymbolId(name.to_string(), None),
+        ))))
+    }
+
+    pub fn sym_bool(name: &str) -> Sentence {
+        Sentence::Basic(Value::Symbolic(SSimpleVal::SymbolicBool(SymbolId(
+            name.to_string(),
+            None,
+        ))))
+    }
+
+    #[rhai_fn(return_raw)]
+    pub fn parse_sentence(s: &str) -> Result<Sentence, Box<EvalAltResult>> {
+        s.parse::<Sentence>().map_err(|e| e.to_string().into())
+    }
+
+    pub fn basic(v: Value) -> Sentence {
+        Sentence::Basic(v)
+    }
+
+    pub fn bin_op(op: BinOp, a: Sentence, b: Sentence) -> Sentence {
+        bin(op, a, b)
+    }
+
+    pub fn unary_op(op: UnaryOp, a: Sentence) -> Sentence {
+        Sentence::UnaryOp { a: Val::new(a), op }
+    }
+
+    pub fn ite(c: Sentence, t: Sentence, e: Sentence) -> Sentence {
+        Sentence::TernaryOp {
+            a: Val::new(c),
+            b: Val::new(t),
+            c: Val::new(e),
+            op: TernaryOp::Ite,
+        }
+    }
+
+    pub fn store(m: Sentence, i: Sentence, v: Sentence) -> Sentence {
+        Sentence::TernaryOp {
+            a: Val::new(m),
+            b: Val::new(i),
+            c: Val::new(v),
+            op: TernaryOp::Store,
+        }
+    }
+
+    #[rhai_fn(name = "+")]
+    pub fn plus(a: Sentence, b: Sentence) -> Sentence {
+        bin(BinOp::Plus, a, b)
+    }
+
+    #[rhai_fn(name = "-")]
+    pub fn minus(a: Sentence, b: Sentence) -> Sentence {
+        bin(BinOp::Minus, a, b)
+    }
+
+    #[rhai_fn(name = "*")]
+    pub fn mul(a: Sentence, b: Sentence) -> Sentence {
+        bin(BinOp::Mul, a, b)
+    }
+
+    #[rhai_fn(name = "/")]
+    pub fn div(a: Sentence, b: Sentence) -> Sentence {
+        bin(BinOp::Div, a, b)
+    }
+
+    #[rhai_fn(name = "%")]
+    pub fn modulo(a: Sentence, b: Sentence) -> Sentence {
+        bin(BinOp::Mod, a, b)
+    }
+
+    // ---- Inspection
+
+    #[rhai_fn(get = "is_basic", pure)]
+    pub fn is_basic(s: &mut Sentence) -> bool {
+        matches!(s, Sentence::Basic(_))
+    }
+
+    #[rhai_fn(get = "is_bin_op", pure)]
+    pub fn is_bin_op(s: &mut Sentence) -> bool {
+        s.is_bin_op()
+    }
+
+    #[rhai_fn(get = "is_unary_op", pure)]
+    pub fn is_unary_op(s: &mut Sentence) -> bool {
+        s.is_unary_op()
+    }
+
+    #[rhai_fn(get = "is_ternary_op", pure)]
+    pub fn is_ternary_op(s: &mut Sentence) -> bool {
+        s.is_ternary_op()
+    }
+
+    #[rhai_fn(get = "is_number", pure)]
+    pub fn is_number(s: &mut Sentence) -> bool {
+        basic_value(s).ok().and_then(concrete_number).is_some()
+    }
+
+    #[rhai_fn(get = "is_bool", pure)]
+    pub fn is_bool(s: &mut Sentence) -> bool {
+        matches!(s, Sentence::Basic(Value::Concrete(CSimpleVal::Boolean(_))))
+    }
+
+    #[rhai_fn(get = "is_symbol", pure)]
+    pub fn is_symbol(s: &mut Sentence) -> bool {
+        matches!(s, Sentence::Basic(v) if v.symbol().is_some())
+    }
+
+    #[rhai_fn(get = "op", pure)]
+    pub fn op(s: &mut Sentence) -> Dynamic {
+        match s {
+            Sentence::BinOp { op, .. } => Dynamic::from(op.clone()),
+            Sentence::UnaryOp { op, .. } => Dynamic::from(op.clone()),
+            Sentence::TernaryOp { op, .. } => Dynamic::from(format!("{:?}", op)),
+            Sentence::Basic(_) => Dynamic::UNIT,
+        }
+    }
+
+    #[rhai_fn(get = "a", pure, return_raw)]
+    pub fn a(s: &mut Sentence) -> Result<Sentence, Box<EvalAltResult>> {
+        match s {
+            Sentence::BinOp { a, .. }
+            | Sentence::UnaryOp { a, .. }
+            | Sentence::TernaryOp { a, .. } => Ok((*a.0).clone()),
+            Sentence::Basic(_) => Err(format!("{} has no operand a", s).into()),
+        }
+    }
+
+    #[rhai_fn(get = "b", pure, return_raw)]
+    pub fn b(s: &mut Sentence) -> Result<Sentence, Box<EvalAltResult>> {
+        match s {
+            Sentence::BinOp { b, .. } | Sentence::TernaryOp { b, .. } => Ok((*b.0).clone()),
+            _ => Err(format!("{} has no operand b", s).into()),
+        }
+    }
+
+    #[rhai_fn(get = "c", pure, return_raw)]
+    pub fn c(s: &mut Sentence) -> Result<Sentence, Box<EvalAltResult>> {
+        match s {
+            Sentence::TernaryOp { c, .. } => Ok((*c.0).clone()),
+            _ => Err(format!("{} has no operand c", s).into()),
+        }
+    }
+
+    #[rhai_fn(get = "value", pure, return_raw)]
+    pub fn value(s: &mut Sentence) -> Result<Value, Box<EvalAltResult>> {
+        basic_value(s).cloned()
+    }
+
+    #[rhai_fn(name = "number", get = "number", pure, return_raw)]
+    pub fn sentence_number(s: &mut Sentence) -> Result<CNumber, Box<EvalAltResult>> {
+        let v = basic_value(s)?;
+        concrete_number(v)
+            .cloned()
+            .ok_or_else(|| format!("{} is not a concrete number", v).into())
+    }
+
+    #[rhai_fn(get = "bool", pure, return_raw)]
+    pub fn bool(s: &mut Sentence) -> Result<bool, Box<EvalAltResult>> {
+        match s {
+            Sentence::Basic(Value::Concrete(CSimpleVal::Boolean(b))) => Ok(*b == Bool::True),
+            _ => Err(format!("{} is not a concrete boolean", s).into()),
+        }
+    }
+
+    #[rhai_fn(name = "symbol", get = "symbol", pure)]
+    pub fn sentence_symbol(s: &mut Sentence) -> Dynamic {
+        match s {
+            Sentence::Basic(v) => value_symbol(v),
+            _ => Dynamic::UNIT,
+        }
+    }
+
+    #[rhai_fn(name = "to_string", name = "to_debug", pure)]
+    pub fn sentence_to_string(s: &mut Sentence) -> String {
+        s.to_string()
+    }
+
+    #[rhai_fn(name = "==", pure)]
+    pub fn sentence_eq(a: &mut Sentence, b: Sentence) -> bool {
+        *a == b
+    }
+
+    #[rhai_fn(name = "!=", pure)]
+    pub fn sentence_neq(a: &mut Sentence, b: Sentence) -> bool {
+        *a != b
+    }
+
+    // ---- Values
+
+    #[rhai_fn(get = "is_symbolic", pure)]
+    pub fn is_symbolic(v: &mut Value) -> bool {
+        matches!(v, Value::Symbolic(_))
+    }
+
+    #[rhai_fn(get = "is_concrete", pure)]
+    pub fn is_concrete(v: &mut Value) -> bool {
+        matches!(v, Value::Concrete(_))
+    }
+
+    #[rhai_fn(name = "symbol", get = "symbol", pure)]
+    pub fn value_symbol(v: &mut Value) -> Dynamic {
+        v.symbol()
+            .map_or(Dynamic::UNIT, |name| name.to_string().into())
+    }
+
+    #[rhai_fn(name = "number", get = "number", pure, return_raw)]
+    pub fn value_number(v: &mut Value) -> Result<CNumber, Box<EvalAltResult>> {
+        concrete_number(v)
+            .cloned()
+            .ok_or_else(|| format!("{} is not a concrete number", v).into())
+    }
+
+    #[rhai_fn(name = "to_string", name = "to_debug", pure)]
+    pub fn value_to_string(v: &mut Value) -> String {
+        v.to_string()
+    }
+
+    #[rhai_fn(name = "==", pure)]
+    pub fn value_eq(a: &mut Value, b: Value) -> bool {
+        *a == b
+    }
+
+    // ---- Numbers
+
+    #[rhai_fn(return_raw)]
+    pub fn u8(n: INT) -> Result<CNumber, Box<EvalAltResult>> {
+        convert::<u8>(n, "u8").map(CNumber::from)
+    }
+
+    #[rhai_fn(return_raw)]
+    pub fn u16(n: INT) -> Result<CNumber, Box<EvalAltResult>> {
+        convert::<u16>(n, "u16").map(CNumber::from)
+    }
+
+    #[rhai_fn(return_raw)]
+    pub fn u32(n: INT) -> Result<CNumber, Box<EvalAltResult>> {
+        convert::<u32>(n, "u32").map(CNumber::from)
+    }
+
+    #[rhai_fn(return_raw)]
+    pub fn u64(n: INT) -> Result<CNumber, Box<EvalAltResult>> {
+        convert::<u64>(n, "u64").map(CNumber::from)
+    }
+
+    #[rhai_fn(return_raw)]
+    pub fn u128(n: INT) -> Result<CNumber, Box<EvalAltResult>> {
+        convert::<u128>(n, "u128").map(CNumber::from)
+    }
+
+    #[rhai_fn(get = "width", pure)]
+    pub fn width(n: &mut CNumber) -> INT {
+        n.bit_width() as INT
+    }
+
+    #[rhai_fn(pure, return_raw)]
+    pub fn to_int(n: &mut CNumber) -> Result<INT, Box<EvalAltResult>> {
+        INT::try_from(u128::from(n.clone()))
+            .map_err(|_| format!("{:?} does not fit in an integer", n).into())
+    }
+
+    #[rhai_fn(name = "+")]
+    pub fn number_plus(a: CNumber, b: CNumber) -> CNumber {
+        a + b
+    }
+
+    #[rhai_fn(name = "-")]
+    pub fn number_minus(a: CNumber, b: CNumber) -> CNumber {
+        a - b
+    }
+
+    #[rhai_fn(name = "*")]
+    pub fn number_mul(a: CNumber, b: CNumber) -> CNumber {
+        a * b
+    }
+
+    #[rhai_fn(name = "/")]
+    pub fn number_div(a: CNumber, b: CNumber) -> CNumber {
+        a / b
+    }
+
+    #[rhai_fn(name = "==", pure)]
+    pub fn number_eq(a: &mut CNumber, b: CNumber) -> bool {
+        u128::from(a.clone()) == u128::from(b)
+    }
+
+    #[rhai_fn(name = "<", pure)]
+    pub fn number_lt(a: &mut CNumber, b: CNumber) -> bool {
+        u128::from(a.clone()) < u128::from(b)
+    }
+
+    #[rhai_fn(name = "to_string", name = "to_debug", pure)]
+    pub fn number_to_string(n: &mut CNumber) -> String {
+        u128::from(n.clone()).to_string()
+    }
+
+    // ---- Operators
+
+    #[rhai_fn(name = "==", pure)]
+    pub fn bin_op_eq(a: &mut BinOp, b: BinOp) -> bool {
+        *a == b
+    }
+
+    #[rhai_fn(name = "!=", pure)]
+    pub fn bin_op_neq(a: &mut BinOp, b: BinOp) -> bool {
+        *a != b
+    }
+
+    #[rhai_fn(name = "to_string", name = "to_debug", pure)]
+    pub fn bin_op_to_string(op: &mut BinOp) -> String {
+        format!("{:?}", op)
+    }
+
+    #[rhai_fn(name = "==", pure)]
+    pub fn unary_op_eq(a: &mut UnaryOp, b: UnaryOp) -> bool {
+        *a == b
+    }
+
+    #[rhai_fn(name = "!=", pure)]
+    pub fn unary_op_neq(a: &mut UnaryOp, b: UnaryOp) -> bool {
+        *a != b
+    }
+
+    #[rhai_fn(name = "to_string", name = "to_debug", pure)]
+    pub fn unary_op_to_string(op: &mut UnaryOp) -> String {
+        format!("{:?}", op)
+    }
+}
+
+const BIN_OPS: [(&str, BinOp); 17] = [
+    ("Plus", BinOp::Plus),
+    ("Minus", BinOp::Minus),
+    ("Div", BinOp::Div),
+    ("Mul", BinOp::Mul),
+    ("Mod", BinOp::Mod),
+    ("Eq", BinOp::Eq),
+    ("Neq", BinOp::Neq),
+    ("Lt", BinOp::Lt),
+    ("Lte", BinOp::Lte),
+    ("Gt", BinOp::Gt),
+    ("Gte", BinOp::Gte),
+    ("BitOr", BinOp::BitOr),
+    ("BitAnd", BinOp::BitAnd),
+    ("BitXor", BinOp::BitXor),
+    ("LShift", BinOp::LShift),
+    ("RShift", BinOp::RShift),
+    ("Select", BinOp::Select),
+];
+
+const UNARY_OPS: [(&str, UnaryOp); 2] = [("Not", UnaryOp::Not), ("BitNot", UnaryOp::BitNot)];
+
+// The sentence API, to register as a global module
+pub fn sentence_module() -> Module {
+    exported_module!(sentence_api)
+}
+
+// Registers the sentence API and the `BinOp` and `UnaryOp` constants on `engine`
+pub fn register_sentence_api(engine: &mut Engine) {
+    engine.register_global_module(sentence_module().into());
+
+    let mut bin_ops = Module::new();
+    for (name, op) in BIN_OPS {
+        bin_ops.set_var(name, op);
+    }
+    engine.register_static_module("BinOp", bin_ops.into());
+
+    let mut unary_ops = Module::new();
+    for (name, op) in UNARY_OPS {
+        unary_ops.set_var(name, op);
+    }
+    engine.register_static_module("UnaryOp", unary_ops.into());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn eval<T: Clone + 'static>(script: &str) -> Result<T, Box<EvalAltResult>> {
+        let mut engine = Engine::new();
+        register_sentence_api(&mut engine);
+        engine.eval::<T>(script)
+    }
+
+    #[test]
+    fn test_constructors() {
+        let s: Sentence =
+            eval(r#"ite(sym_bool("b"), sym("x") + num(1), num(u8(255)) * num(2))"#).unwrap();
+        assert_eq!(s, "(ite (bool b) (+ x 1) (* (u8 255) 2))".parse().unwrap());
+
+        let s: Sentence = eval(
+            r#"bin_op(BinOp::Lt, unary_op(UnaryOp::BitNot, parse_sentence("x")), basic(num(3).value))"#,
+        )
+        .unwrap();
+        assert_eq!(s, "(< (bitnot x) 3)".parse().unwrap());
+
+        assert!(eval::<Sentence>("num(-1)").is_err());
+        assert!(eval::<Sentence>(r#"parse_sentence("(+ x")"#).is_err());
+    }
+
+    #[test]
+    fn test_inspection() {
+        let script = r#"
+            let s = parse_sentence("(ite (= x 1) (+ x (u8 250)) (not true))");
+            [
+                s.is_ternary_op, s.a.is_bin_op, s.a.op == BinOp::Eq, s.a.op != BinOp::Neq,
+                s.a.a.is_symbol, s.a.a.symbol == "x", s.a.a.value.is_symbolic,
+                s.b.b.is_number, s.b.b.number.width == 8, s.b.b.number.to_int() == 250,
+                (s.b.b.number + u8(10)).to_int() == 4, s.c.op == UnaryOp::Not, s.c.a.bool,
+                s.c.a.is_bool, s.a.b.symbol == (), s.a.b == num(1), s.to_string() == "x == 1 ? x + 250 : !true",
+            ]
+        "#;
+        let checks: rhai::Array = eval(script).unwrap();
+        for (i, check) in checks.into_iter().enumerate() {
+            assert!(check.as_bool().unwrap(), "check {} failed", i);
+        }
+
+        let err = eval::<Sentence>(r#"parse_sentence("x").a"#).unwrap_err();
+        assert!(err.to_string().contains("x has no operand a"));
+        assert!(eval::<CNumber>(r#"sym("x").number"#).is_err());
+        assert!(eval::<bool>(r#"num(1).bool"#).is_err());
+    }
+}
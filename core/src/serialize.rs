@@ -0,0 +1,479 @@
+// Serde support for sentences and constraints. Both are DAGs whose nodes can be shared
+// through `Rc`s, so they are written as a table of nodes in which children refer to
+// earlier entries by index, e.g. `x + x` with a shared `x` is
+//
+//     {"nodes": [{"Basic": ..}, {"BinOp": {"a": 0, "b": 0, "op": "Plus"}}], "roots": [1]}
+//
+// Each shared node is written once, and reading the table back shares it again.
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    constraint::{CmpType, Constraint, Node},
+    stack::StackVal,
+    value::{BinOp, Sentence, TernaryOp, UnaryOp, Val, Value},
+};
+
+#[derive(Serialize, Deserialize)]
+enum DagNode {
+    Basic(Value),
+    UnaryOp {
+        a: usize,
+        op: UnaryOp,
+    },
+    BinOp {
+        a: usize,
+        b: usize,
+        op: BinOp,
+    },
+    TernaryOp {
+        a: usize,
+        b: usize,
+        c: usize,
+        op: TernaryOp,
+    },
+    True,
+    False,
+    // Children of constraints are either sentences or constraints, depending on the entry
+    // they refer to
+    Assert(usize),
+    Not(usize),
+    And(usize, usize),
+    Or(usize, usize),
+    Cmp {
+        op: CmpOp,
+        l: usize,
+        r: usize,
+    },
+    Ite(usize, usize, usize),
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+enum CmpOp {
+    Gt,
+    Lt,
+    Gte,
+    Lte,
+    Eq,
+    Neq,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Dag {
+    nodes: Vec<DagNode>,
+    roots: Vec<usize>,
+}
+
+#[derive(Clone, Copy)]
+enum Item<'a> {
+    Sentence(&'a Sentence),
+    Constraint(&'a Constraint),
+}
+
+impl<'a> Item<'a> {
+    fn node(n: &'a Node) -> Self {
+        match n {
+            Node::Simple(s) => Item::Sentence(s),
+            Node::Compound(c) => Item::Constraint(c),
+        }
+    }
+
+    // A constraint and the sentence it asserts can share an address
+    fn key(&self) -> (*const (), bool) {
+        match self {
+            Item::Sentence(s) => (*s as *const Sentence as *const (), false),
+            Item::Constraint(c) => (*c as *const Constraint as *const (), true),
+        }
+    }
+
+    fn children(&self) -> Vec<Item<'a>> {
+        match self {
+            Item::Sentence(s) => match s {
+                Sentence::Basic(_) => vec![],
+                Sentence::UnaryOp { a, .. } => vec![Item::Sentence(&a.0)],
+                Sentence::BinOp { a, b, .. } => vec![Item::Sentence(&a.0), Item::Sentence(&b.0)],
+                Sentence::TernaryOp { a, b, c, .. } => vec![
+                    Item::Sentence(&a.0),
+                    Item::Sentence(&b.0),
+                    Item::Sentence(&c.0),
+                ],
+            },
+            Item::Constraint(c) => match c {
+                Constraint::True | Constraint::False => vec![],
+                Constraint::Assert(n) | Constraint::Not(n) => vec![Item::node(n)],
+                Constraint::And(l, r) | Constraint::Or(l, r) => vec![Item::node(l), Item::node(r)],
+                Constraint::BinCmp(cmp) => {
+                    let (l, r) = cmp.operands();
+                    vec![Item::node(l), Item::node(r)]
+                }
+                Constraint::Ite(c, t, e) => {
+                    vec![Item::Constraint(c), Item::node(t), Item::node(e)]
+                }
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct DagWriter {
+    nodes: Vec<DagNode>,
+    ids: HashMap<(*const (), bool), usize>,
+}
+
+impl DagWriter {
+    // Adds `root` and everything below it that isn't in the table yet, children first
+    fn write(&mut self, root: Item) -> usize {
+        let mut todo = vec![(root, false)];
+        while let Some((item, expanded)) = todo.pop() {
+            if self.ids.contains_key(&item.key()) {
+                continue;
+            }
+            if expanded {
+                let node = self.node(item);
+                self.ids.insert(item.key(), self.nodes.len());
+                self.nodes.push(node);
+            } else {
+                todo.push((item, true));
+                todo.extend(item.children().into_iter().rev().map(|c| (c, false)));
+            }
+        }
+        self.ids[&root.key()]
+    }
+
+    fn id(&self, item: Item) -> usize {
+        self.ids[&item.key()]
+    }
+
+    fn node(&self, item: Item) -> DagNode {
+        let s = |s: &Val<Sentence>| self.id(Item::Sentence(&s.0));
+        let n = |n: &Node| self.id(Item::node(n));
+        match item {
+            Item::Sentence(sentence) => match sentence {
+                Sentence::Basic(v) => DagNode::Basic(v.clone()),
+                Sentence::UnaryOp { a, op } => DagNode::UnaryOp {
+                    a: s(a),
+                    op: op.clone(),
+                },
+                Sentence::BinOp { a, b, op } => DagNode::BinOp {
+                    a: s(a),
+                    b: s(b),
+                    op: op.clone(),
+                },
+                Sentence::TernaryOp { a, b, c, op } => DagNode::TernaryOp {
+                    a: s(a),
+                    b: s(b),
+                    c: s(c),
+                    op: op.clone(),
+                },
+            },
+            Item::Constraint(c) => match c {
+                Constraint::True => DagNode::True,
+                Constraint::False => DagNode::False,
+                Constraint::Assert(a) => DagNode::Assert(n(a)),
+                Constraint::Not(a) => DagNode::Not(n(a)),
+                Constraint::And(l, r) => DagNode::And(n(l), n(r)),
+                Constraint::Or(l, r) => DagNode::Or(n(l), n(r)),
+                Constraint::BinCmp(cmp) => {
+                    let op = match cmp {
+                        CmpType::GT(..) => CmpOp::Gt,
+                        CmpType::LT(..) => CmpOp::Lt,
+                        CmpType::GTE(..) => CmpOp::Gte,
+                        CmpType::LTE(..) => CmpOp::Lte,
+                        CmpType::EQ(..) => CmpOp::Eq,
+                        CmpType::NEQ(..) => CmpOp::Neq,
+                    };
+                    let (l, r) = cmp.operands();
+                    DagNode::Cmp {
+                        op,
+                        l: n(l),
+                        r: n(r),
+                    }
+                }
+                Constraint::Ite(c, t, e) => DagNode::Ite(self.id(Item::Constraint(c)), n(t), n(e)),
+            },
+        }
+    }
+}
+
+pub(crate) enum Entry {
+    Sentence(Rc<Sentence>),
+    Constraint(Rc<Constraint>),
+}
+
+// Rebuilds the entries of a table in order, so each node can only refer to earlier ones
+fn read_entries(nodes: Vec<DagNode>) -> Result<Vec<Entry>, String> {
+    let mut entries: Vec<Entry> = Vec::with_capacity(nodes.len());
+    for (i, dag_node) in nodes.into_iter().enumerate() {
+        let get = |id: usize| {
+            entries
+                .get(id)
+                .ok_or_else(|| format!("node {} refers to node {}, which is not before it", i, id))
+        };
+        let sentence = |id: usize| match get(id)? {
+            Entry::Sentence(s) => Ok(Val(s.clone())),
+            Entry::Constraint(_) => Err(format!("node {} expects node {} to be a sentence", i, id)),
+        };
+        let constraint = |id: usize| match get(id)? {
+            Entry::Constraint(c) => Ok(c.clone()),
+            Entry::Sentence(_) => Err(format!("node {} expects node {} to be a constraint", i, id)),
+        };
+        let node = |id: usize| {
+            get(id).map(|e| match e {
+                Entry::Sentence(s) => Node::Simple((**s).clone()),
+                Entry::Constraint(c) => Node::Compound(c.clone()),
+            })
+        };
+
+        let entry = match dag_node {
+            DagNode::Basic(v) => Entry::Sentence(Rc::new(Sentence::Basic(v))),
+            DagNode::UnaryOp { a, op } => Entry::Sentence(Rc::new(Sentence::UnaryOp {
+                a: sentence(a)?,
+                op,
+            })),
+            DagNode::BinOp { a, b, op } => Entry::Sentence(Rc::new(Sentence::BinOp {
+                a: sentence(a)?,
+                b: sentence(b)?,
+                op,
+            })),
+            DagNode::TernaryOp { a, b, c, op } => Entry::Sentence(Rc::new(Sentence::TernaryOp {
+                a: sentence(a)?,
+                b: sentence(b)?,
+                c: sentence(c)?,
+                op,
+            })),
+            DagNode::True => Entry::Constraint(Rc::new(Constraint::True)),
+            DagNode::False => Entry::Constraint(Rc::new(Constraint::False)),
+            DagNode::Assert(a) => Entry::Constraint(Rc::new(Constraint::Assert(node(a)?))),
+            DagNode::Not(a) => Entry::Constraint(Rc::new(Constraint::Not(node(a)?))),
+            DagNode::And(l, r) => Entry::Constraint(Rc::new(Constraint::And(node(l)?, node(r)?))),
+            DagNode::Or(l, r) => Entry::Constraint(Rc::new(Constraint::Or(node(l)?, node(r)?))),
+            DagNode::Cmp { op, l, r } => {
+                let (l, r) = (node(l)?, node(r)?);
+                let cmp = match op {
+                    CmpOp::Gt => CmpType::GT(l, r),
+                    CmpOp::Lt => CmpType::LT(l, r),
+                    CmpOp::Gte => CmpType::GTE(l, r),
+                    CmpOp::Lte => CmpType::LTE(l, r),
+                    CmpOp::Eq => CmpType::EQ(l, r),
+                    CmpOp::Neq => CmpType::NEQ(l, r),
+                };
+                Entry::Constraint(Rc::new(Constraint::BinCmp(cmp)))
+            }
+            DagNode::Ite(c, t, e) => {
+                Entry::Constraint(Rc::new(Constraint::Ite(constraint(c)?, node(t)?, node(e)?)))
+            }
+        };
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+// Values stored as roots of a node table
+pub(crate) trait DagRoot: Sized {
+    fn write(&self, w: &mut DagWriter) -> usize;
+
+    fn read(entry: &Entry) -> Option<Self>;
+}
+
+impl DagRoot for Sentence {
+    fn write(&self, w: &mut DagWriter) -> usize {
+        w.write(Item::Sentence(self))
+    }
+
+    fn read(entry: &Entry) -> Option<Self> {
+        match entry {
+            Entry::Sentence(s) => Some((**s).clone()),
+            Entry::Constraint(_) => None,
+        }
+    }
+}
+
+impl DagRoot for Constraint {
+    fn write(&self, w: &mut DagWriter) -> usize {
+        w.write(Item::Constraint(self))
+    }
+
+    fn read(entry: &Entry) -> Option<Self> {
+        match entry {
+            Entry::Constraint(c) => Some((**c).clone()),
+            Entry::Sentence(_) => None,
+        }
+    }
+}
+
+impl DagRoot for StackVal {
+    fn write(&self, w: &mut DagWriter) -> usize {
+        self.0.write(w)
+    }
+
+    fn read(entry: &Entry) -> Option<Self> {
+        Sentence::read(entry).map(StackVal)
+    }
+}
+
+// For `#[serde(with = "crate::serialize::shared")]` on a `Vec` of sentences, constraints
+// or stack values: the whole list goes in one node table, so sharing between its items is
+// preserved too
+pub(crate) mod shared {
+    use super::*;
+
+    pub fn serialize<T: DagRoot, S: Serializer>(items: &[T], s: S) -> Result<S::Ok, S::Error> {
+        let mut w = DagWriter::default();
+        let roots = items.iter().map(|i| i.write(&mut w)).collect();
+        Dag {
+            nodes: w.nodes,
+            roots,
+        }
+        .serialize(s)
+    }
+
+    pub fn deserialize<'de, T: DagRoot, D: Deserializer<'de>>(d: D) -> Result<Vec<T>, D::Error> {
+        let dag = Dag::deserialize(d)?;
+        let entries = read_entries(dag.nodes).map_err(D::Error::custom)?;
+        dag.roots
+            .into_iter()
+            .map(|r| {
+                entries
+                    .get(r)
+                    .and_then(T::read)
+                    .ok_or_else(|| D::Error::custom(format!("invalid root {}", r)))
+            })
+            .collect()
+    }
+}
+
+fn serialize_one<T: DagRoot, S: Serializer>(item: &T, s: S) -> Result<S::Ok, S::Error> {
+    shared::serialize(std::slice::from_ref(item), s)
+}
+
+fn deserialize_one<'de, T: DagRoot, D: Deserializer<'de>>(d: D) -> Result<T, D::Error> {
+    let mut items = shared::deserialize(d)?;
+    match items.len() {
+        1 => Ok(items.remove(0)),
+        n => Err(D::Error::custom(format!(
+            "expected a single root, found {}",
+            n
+        ))),
+    }
+}
+
+impl Serialize for Sentence {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        serialize_one(self, s)
+    }
+}
+
+impl<'de> Deserialize<'de> for Sentence {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        deserialize_one(d)
+    }
+}
+
+impl Serialize for Val<Sentence> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        serialize_one(self.0.as_ref(), s)
+    }
+}
+
+impl<'de> Deserialize<'de> for Val<Sentence> {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        deserialize_one(d).map(Val::new)
+    }
+}
+
+impl Serialize for Constraint {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        serialize_one(self, s)
+    }
+}
+
+impl<'de> Deserialize<'de> for Constraint {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        deserialize_one(d)
+    }
+}
+
+impl Serialize for StackVal {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        serialize_one(self, s)
+    }
+}
+
+impl<'de> Deserialize<'de> for StackVal {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        deserialize_one(d)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stack::Stack;
+    use crate::test_util::{num, sym};
+
+    fn shared_sum() -> Sentence {
+        let x = Val::new(sym("x"));
+        Sentence::BinOp {
+            a: x.clone(),
+            b: x,
+            op: BinOp::Plus,
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let json = serde_json::to_string(&shared_sum()).unwrap();
+        assert_eq!(
+            json,
+            r#"{"nodes":[{"Basic":{"Symbolic":{"SymbolicNumber":["x",null]}}},{"BinOp":{"a":0,"b":0,"op":"Plus"}}],"roots":[1]}"#
+        );
+    }
+
+    #[test]
+    fn test_sharing_is_preserved() {
+        let s: Sentence =
+            serde_json::from_str(&serde_json::to_string(&shared_sum()).unwrap()).unwrap();
+        assert_eq!(s, shared_sum());
+        match &s {
+            Sentence::BinOp { a, b, .. } => assert!(Rc::ptr_eq(&a.0, &b.0)),
+            _ => unreachable!(),
+        }
+
+        let x_gt_1 = Constraint::gt(shared_sum(), num(1));
+        let c = Constraint::ite(x_gt_1.clone(), !x_gt_1.clone(), x_gt_1.or(Constraint::True));
+        let json = serde_json::to_string(&c).unwrap();
+        let back: Constraint = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, c);
+
+        // Stack values share one table
+        let stack = Stack::new(
+            vec![
+                StackVal(shared_sum()),
+                StackVal(num(3)),
+                StackVal(shared_sum()),
+            ],
+            Default::default(),
+        );
+        let json = serde_json::to_value(&stack).unwrap();
+        assert_eq!(json["inner"]["roots"], serde_json::json!([1, 2, 4]));
+        let back: Stack = serde_json::from_value(json).unwrap();
+        assert_eq!(back.peek(0), Some(&StackVal(shared_sum())));
+    }
+
+    #[test]
+    fn test_invalid_tables() {
+        let invalid = [
+            r#"{"nodes":[{"BinOp":{"a":0,"b":0,"op":"Plus"}}],"roots":[0]}"#,
+            r#"{"nodes":[{"Basic":{"Concrete":{"Number":{"U8":1}}}},{"Not":0},{"UnaryOp":{"a":1,"op":"Not"}}],"roots":[2]}"#,
+            r#"{"nodes":[{"Basic":{"Concrete":{"Number":{"U8":1}}}}],"roots":[0,0]}"#,
+            r#"{"nodes":[],"roots":[0]}"#,
+        ];
+        for json in invalid {
+            assert!(serde_json::from_str::<Sentence>(json).is_err());
+        }
+        let sentence = r#"{"nodes":[{"Basic":{"Concrete":{"Number":{"U8":1}}}}],"roots":[0]}"#;
+        assert!(serde_json::from_str::<Constraint>(sentence).is_err());
+    }
+}
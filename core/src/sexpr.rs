@@ -0,0 +1,506 @@
+// S-expressions, and a textual syntax for sentences built on them, e.g.
+// `(ite (= x 0) 1 (+ x y))`. Numbers without a width are 64 bits wide, and bare names
+// are numeric symbols:
+//
+//     5, (u8 5)                  concrete numbers
+//     true, false                concrete booleans
+//     #x0102                     concrete vectors
+//     x, (sym x 5)               numeric symbols, optionally with a value
+//     (bool b), (bool b true)    boolean symbols
+//     (vec v), (vec v #x01)      vector symbols
+//     _                          a value that is neither concrete nor symbolic
+//     (+ a b) (- a b) (* a b) (/ a b) (% a b)
+//     (= a b) (!= a b) (< a b) (<= a b) (> a b) (>= a b)
+//     (bitor a b) (bitand a b) (bitxor a b) (shl a b) (shr a b)
+//     (not a) (bitnot a) (ite a b c)
+//     (select m i) (store m i v)  byte arrays, indexed by number
+//
+// Names that could be read as something else are quoted as `|name|`. Printing does not
+// preserve sharing, so a DAG with many shared nodes prints as a much larger tree.
+use std::fmt::Display;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::value::{
+    BinOp, Bool, CNumber, CSimpleVal, SNumber, SSimpleVal, Sentence, SymbolId, SymbolicVecc,
+    TernaryOp, UnaryOp, Val, Value, Vecc,
+};
+
+#[derive(Error, Debug)]
+pub enum SExprError {
+    #[error("Unexpected end of input")]
+    UnexpectedEof,
+    #[error("Unexpected token {0}")]
+    UnexpectedToken(String),
+    #[error("Invalid sentence {0}")]
+    InvalidSentence(String),
+}
+
+pub type SExprResult<T> = Result<T, SExprError>;
+
+// A minimal s-expression tree, enough for the subset of SMT-LIB2 the solver backends
+// use and for the textual syntax of sentences
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SExpr {
+    Atom(String),
+    List(Vec<SExpr>),
+}
+
+impl SExpr {
+    pub fn parse_all(src: &str) -> SExprResult<Vec<SExpr>> {
+        let tokens = tokenize(src)?;
+        let mut pos = 0;
+        let mut exprs = vec![];
+        while pos < tokens.len() {
+            exprs.push(Self::parse_at(&tokens, &mut pos)?);
+        }
+        Ok(exprs)
+    }
+
+    fn parse_at(tokens: &[String], pos: &mut usize) -> SExprResult<SExpr> {
+        let tok = tokens.get(*pos).ok_or(SExprError::UnexpectedEof)?;
+        *pos += 1;
+        match tok.as_str() {
+            "(" => {
+                let mut items = vec![];
+                loop {
+                    match tokens.get(*pos).map(|t| t.as_str()) {
+                        Some(")") => {
+                            *pos += 1;
+                            return Ok(SExpr::List(items));
+                        }
+                        Some(_) => items.push(Self::parse_at(tokens, pos)?),
+                        None => return Err(SExprError::UnexpectedEof),
+                    }
+                }
+            }
+            ")" => Err(SExprError::UnexpectedToken(tok.clone())),
+            _ => Ok(SExpr::Atom(tok.clone())),
+        }
+    }
+
+    pub fn as_atom(&self) -> Option<&str> {
+        match self {
+            SExpr::Atom(a) => Some(a.as_str()),
+            SExpr::List(_) => None,
+        }
+    }
+}
+
+impl Display for SExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SExpr::Atom(a) => write!(f, "{}", a),
+            SExpr::List(items) => {
+                write!(f, "(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+fn tokenize(src: &str) -> SExprResult<Vec<String>> {
+    let mut tokens = vec![];
+    let mut chars = src.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | ')' => tokens.push(c.to_string()),
+            ';' => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '|' | '"' => {
+                let mut tok = c.to_string();
+                loop {
+                    let next = chars.next().ok_or(SExprError::UnexpectedEof)?;
+                    tok.push(next);
+                    if next == c {
+                        // Doubled quotes are an escaped quote inside string literals
+                        if c == '"' && chars.peek() == Some(&'"') {
+                            tok.push(chars.next().unwrap());
+                            continue;
+                        }
+                        break;
+                    }
+                }
+                tokens.push(tok);
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut tok = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || next == '(' || next == ')' || next == ';' {
+                        break;
+                    }
+                    tok.push(next);
+                    chars.next();
+                }
+                tokens.push(tok);
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+const UNARY_OPS: [(&str, UnaryOp); 2] = [("not", UnaryOp::Not), ("bitnot", UnaryOp::BitNot)];
+
+const BIN_OPS: [(&str, BinOp); 17] = [
+    ("+", BinOp::Plus),
+    ("-", BinOp::Minus),
+    ("*", BinOp::Mul),
+    ("/", BinOp::Div),
+    ("%", BinOp::Mod),
+    ("=", BinOp::Eq),
+    ("!=", BinOp::Neq),
+    ("<", BinOp::Lt),
+    ("<=", BinOp::Lte),
+    (">", BinOp::Gt),
+    (">=", BinOp::Gte),
+    ("bitor", BinOp::BitOr),
+    ("bitand", BinOp::BitAnd),
+    ("bitxor", BinOp::BitXor),
+    ("shl", BinOp::LShift),
+    ("shr", BinOp::RShift),
+    ("select", BinOp::Select),
+];
+
+const KEYWORDS: [&str; 13] = [
+    "true", "false", "_", "sym", "bool", "vec", "ite", "store", "u8", "u16", "u32", "u64", "u128",
+];
+
+fn atom(a: impl Into<String>) -> SExpr {
+    SExpr::Atom(a.into())
+}
+
+fn quote_name(name: &str) -> String {
+    let plain = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit() || c == '#')
+        && !name
+            .chars()
+            .any(|c| c.is_whitespace() || "()|;\"".contains(c))
+        && !KEYWORDS.contains(&name)
+        && !UNARY_OPS.iter().any(|(op, _)| *op == name)
+        && !BIN_OPS.iter().any(|(op, _)| *op == name);
+    if plain {
+        name.to_string()
+    } else {
+        format!("|{}|", name)
+    }
+}
+
+fn number_sexpr(n: &CNumber) -> SExpr {
+    match n {
+        CNumber::U64(v) => atom(v.to_string()),
+        n => SExpr::List(vec![
+            atom(format!("u{}", n.bit_width())),
+            atom(u128::from(n.clone()).to_string()),
+        ]),
+    }
+}
+
+fn bool_sexpr(b: &Bool) -> SExpr {
+    atom(bool::from(b.clone()).to_string())
+}
+
+fn vector_sexpr(v: &Vecc) -> SExpr {
+    atom(format!("#x{}", hex::encode(&v.0)))
+}
+
+fn symbol_sexpr(kind: Option<&str>, name: &str, item: Option<SExpr>) -> SExpr {
+    match (kind, item) {
+        (None, None) => atom(quote_name(name)),
+        (kind, item) => SExpr::List(
+            [atom(kind.unwrap_or("sym")), atom(quote_name(name))]
+                .into_iter()
+                .chain(item)
+                .collect(),
+        ),
+    }
+}
+
+fn value_sexpr(v: &Value) -> SExpr {
+    match v {
+        Value::Concrete(CSimpleVal::Boolean(b)) => bool_sexpr(b),
+        Value::Concrete(CSimpleVal::Number(n)) => number_sexpr(n),
+        Value::Concrete(CSimpleVal::Vector(v)) => vector_sexpr(v),
+        Value::Symbolic(SSimpleVal::SymbolicNumber(SNumber(SymbolId(name, item)))) => {
+            symbol_sexpr(None, name, item.as_ref().map(number_sexpr))
+        }
+        Value::Symbolic(SSimpleVal::SymbolicBool(SymbolId(name, item))) => {
+            symbol_sexpr(Some("bool"), name, item.as_ref().map(bool_sexpr))
+        }
+        Value::Symbolic(SSimpleVal::SymbolicVector(SymbolicVecc(SymbolId(name, item)))) => {
+            symbol_sexpr(Some("vec"), name, item.as_ref().map(vector_sexpr))
+        }
+        Value::Either(_) => atom("_"),
+    }
+}
+
+fn invalid(e: &SExpr) -> SExprError {
+    SExprError::InvalidSentence(e.to_string())
+}
+
+fn parse_name(e: &SExpr) -> SExprResult<String> {
+    let name = e.as_atom().ok_or_else(|| invalid(e))?;
+    let unquoted = name.strip_prefix('|').and_then(|n| n.strip_suffix('|'));
+    match unquoted {
+        Some(name) => Ok(name.to_string()),
+        None if quote_name(name) == name => Ok(name.to_string()),
+        None => Err(invalid(e)),
+    }
+}
+
+fn parse_number(e: &SExpr) -> SExprResult<CNumber> {
+    let parse = |digits: &str, width: u32| {
+        let v: u128 = digits.parse().map_err(|_| invalid(e))?;
+        let n = CNumber::from_u128_with_width(v, width);
+        if u128::from(n.clone()) == v {
+            Ok(n)
+        } else {
+            Err(invalid(e))
+        }
+    };
+    match e {
+        SExpr::Atom(a) if a.starts_with(|c: char| c.is_ascii_digit()) => parse(a, 64),
+        SExpr::List(items) => match items.as_slice() {
+            [SExpr::Atom(w), SExpr::Atom(v)] => {
+                let width = match w.as_str() {
+                    "u8" => 8,
+                    "u16" => 16,
+                    "u32" => 32,
+                    "u64" => 64,
+                    "u128" => 128,
+                    _ => return Err(invalid(e)),
+                };
+                parse(v, width)
+            }
+            _ => Err(invalid(e)),
+        },
+        _ => Err(invalid(e)),
+    }
+}
+
+fn parse_bool(e: &SExpr) -> SExprResult<Bool> {
+    match e.as_atom() {
+        Some("true") => Ok(Bool::True),
+        Some("false") => Ok(Bool::False),
+        _ => Err(invalid(e)),
+    }
+}
+
+fn parse_vector(e: &SExpr) -> SExprResult<Vecc> {
+    let digits = e
+        .as_atom()
+        .and_then(|a| a.strip_prefix("#x"))
+        .ok_or_else(|| invalid(e))?;
+    hex::decode(digits).map(Vecc).map_err(|_| invalid(e))
+}
+
+impl Sentence {
+    pub fn to_sexpr(&self) -> SExpr {
+        match self {
+            Sentence::Basic(v) => value_sexpr(v),
+            Sentence::UnaryOp { a, op } => {
+                let (name, _) = UNARY_OPS.iter().find(|(_, o)| o == op).unwrap();
+                SExpr::List(vec![atom(*name), a.0.to_sexpr()])
+            }
+            Sentence::BinOp { a, b, op } => {
+                let (name, _) = BIN_OPS.iter().find(|(_, o)| o == op).unwrap();
+                SExpr::List(vec![atom(*name), a.0.to_sexpr(), b.0.to_sexpr()])
+            }
+            Sentence::TernaryOp { a, b, c, op } => {
+                let name = match op {
+                    TernaryOp::Ite => "ite",
+                    TernaryOp::Store => "store",
+                };
+                SExpr::List(vec![
+                    atom(name),
+                    a.0.to_sexpr(),
+                    b.0.to_sexpr(),
+                    c.0.to_sexpr(),
+                ])
+            }
+        }
+    }
+
+    pub fn from_sexpr(e: &SExpr) -> SExprResult<Sentence> {
+        let basic = |v: Value| Ok(Sentence::Basic(v));
+        let items = match e {
+            SExpr::Atom(a) => {
+                return match a.as_str() {
+                    "true" | "false" => basic(Value::Concrete(CSimpleVal::Boolean(parse_bool(e)?))),
+                    "_" => basic(Value::default()),
+                    a if a.starts_with(|c: char| c.is_ascii_digit()) => {
+                        basic(Value::Concrete(CSimpleVal::Number(parse_number(e)?)))
+                    }
+                    a if a.starts_with('#') => {
+                        basic(Value::Concrete(CSimpleVal::Vector(parse_vector(e)?)))
+                    }
+                    _ => basic(Value::Symbolic(SSimpleVal::SymbolicNumber(SNumber(
+                        SymbolId(parse_name(e)?, None),
+                    )))),
+                }
+            }
+            SExpr::List(items) => items,
+        };
+
+        let head = items
+            .first()
+            .and_then(|h| h.as_atom())
+            .ok_or_else(|| invalid(e))?;
+        let args = &items[1..];
+        let sub = |e: &SExpr| Sentence::from_sexpr(e).map(Val::new);
+
+        if let Some((_, op)) = UNARY_OPS.iter().find(|(name, _)| *name == head) {
+            return match args {
+                [a] => Ok(Sentence::UnaryOp {
+                    a: sub(a)?,
+                    op: op.clone(),
+                }),
+                _ => Err(invalid(e)),
+            };
+        }
+        if let Some((_, op)) = BIN_OPS.iter().find(|(name, _)| *name == head) {
+            return match args {
+                [a, b] => Ok(Sentence::BinOp {
+                    a: sub(a)?,
+                    b: sub(b)?,
+                    op: op.clone(),
+                }),
+                _ => Err(invalid(e)),
+            };
+        }
+        match (head, args) {
+            ("ite", [a, b, c]) => Ok(Sentence::TernaryOp {
+                a: sub(a)?,
+                b: sub(b)?,
+                c: sub(c)?,
+                op: TernaryOp::Ite,
+            }),
+            ("store", [a, b, c]) => Ok(Sentence::TernaryOp {
+                a: sub(a)?,
+                b: sub(b)?,
+                c: sub(c)?,
+                op: TernaryOp::Store,
+            }),
+            ("u8" | "u16" | "u32" | "u64" | "u128", [_]) => {
+                basic(Value::Concrete(CSimpleVal::Number(parse_number(e)?)))
+            }
+            ("sym", [name, rest @ ..]) if rest.len() <= 1 => {
+                let item = rest.first().map(parse_number).transpose()?;
+                basic(Value::Symbolic(SSimpleVal::SymbolicNumber(SNumber(
+                    SymbolId(parse_name(name)?, item),
+                ))))
+            }
+            ("bool", [name, rest @ ..]) if rest.len() <= 1 => {
+                let item = rest.first().map(parse_bool).transpose()?;
+                basic(Value::Symbolic(SSimpleVal::SymbolicBool(SymbolId(
+                    parse_name(name)?,
+                    item,
+                ))))
+            }
+            ("vec", [name, rest @ ..]) if rest.len() <= 1 => {
+                let item = rest.first().map(parse_vector).transpose()?;
+                basic(Value::Symbolic(SSimpleVal::SymbolicVector(SymbolicVecc(
+                    SymbolId(parse_name(name)?, item),
+                ))))
+            }
+            _ => Err(invalid(e)),
+        }
+    }
+}
+
+impl FromStr for Sentence {
+    type Err = SExprError;
+
+    fn from_str(src: &str) -> SExprResult<Sentence> {
+        match SExpr::parse_all(src)?.as_slice() {
+            [e] => Sentence::from_sexpr(e),
+            [] => Err(SExprError::UnexpectedEof),
+            [_, e, ..] => Err(SExprError::UnexpectedToken(e.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip(src: &str) -> String {
+        src.parse::<Sentence>().unwrap().to_sexpr().to_string()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let sources = [
+            "(ite (= x 0) 1 (+ x y))",
+            "(bitnot (shl (u8 255) (u128 3)))",
+            "(not (bool b true))",
+            "(!= (vec v #x0102) #x)",
+            "(% (sym x 7) |ite|)",
+            "(bitxor |a b| _)",
+            "(select (store (vec m) 0 (bitand v 255)) |store|)",
+        ];
+        for src in sources {
+            assert_eq!(round_trip(src), src);
+        }
+        assert_eq!(round_trip(" (+ x\n ; comment\n (u64 2))"), "(+ x 2)");
+    }
+
+    #[test]
+    fn test_parse() {
+        let s: Sentence = "(ite (= x 0) 1 (+ x (u8 2)))".parse().unwrap();
+        let x = || {
+            Val::new(Sentence::Basic(Value::Symbolic(
+                SSimpleVal::SymbolicNumber(SNumber(SymbolId("x".to_string(), None))),
+            )))
+        };
+        let num = |n| Val::new(Sentence::Basic(Value::Concrete(CSimpleVal::Number(n))));
+        let expected = Sentence::TernaryOp {
+            a: Val::new(Sentence::BinOp {
+                a: x(),
+                b: num(CNumber::U64(0)),
+                op: BinOp::Eq,
+            }),
+            b: num(CNumber::U64(1)),
+            c: Val::new(Sentence::BinOp {
+                a: x(),
+                b: num(CNumber::U8(2)),
+                op: BinOp::Plus,
+            }),
+            op: TernaryOp::Ite,
+        };
+        assert_eq!(s, expected);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let invalid = [
+            "(+ x)",
+            "(ite 1 2)",
+            "(u8 256)",
+            "(foo 1 2)",
+            "ite",
+            "(bool b 1)",
+            "#xabc",
+            "(+ 1 2",
+            "1 2",
+            "",
+        ];
+        for src in invalid {
+            assert!(src.parse::<Sentence>().is_err(), "{}", src);
+        }
+    }
+}
@@ -1,5 +1,6 @@
 mod config;
 mod record;
+#[allow(clippy::module_inception)]
 mod stack;
 mod val;
 
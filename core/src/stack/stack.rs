@@ -1,11 +1,14 @@
+use serde::{Deserialize, Serialize};
+
 use super::{
     config::StackConfig,
     record::{StackOpRecord, StackRecord},
     StackVal,
 };
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct Stack {
+    #[serde(with = "crate::serialize::shared")]
     inner: Vec<StackVal>,
     // TODO(will) - should be a reference
     config: StackConfig,
@@ -19,13 +22,21 @@ impl Stack {
         }
     }
 
+    // `idx` counts from the top of the stack
     pub fn peek(&self, idx: usize) -> Option<&StackVal> {
-        let last_idx = self.inner.len() - 1;
-        let get_idx = last_idx - idx;
+        let get_idx = self.inner.len().checked_sub(idx + 1)?;
 
         self.inner.get(get_idx)
     }
 
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
     pub fn apply(&self, r: StackRecord) -> Self {
         // TODO(will) - we should use a copy on write data structure
         let mut inner = self.inner.clone();
@@ -1,4 +1,4 @@
-use crate::value::{Sentence, CNumber, CSimpleVal, SNumber, SSimpleVal, Value, Number, Val, BinOp, TernaryOp};
+use crate::value::{BinOp, CNumber, CSimpleVal, Sentence, TernaryOp, Val, Value};
 #[derive(Clone, PartialEq, Eq, Debug, Default)]
 pub struct StackVal(pub Sentence);
 
@@ -26,9 +26,13 @@ impl From<usize> for StackVal {
     }
 }
 
+// Panics unless the value is a concrete number
 impl From<StackVal> for usize {
     fn from(x: StackVal) -> Self {
-        todo!()
+        match x.as_cnumber() {
+            Some(n) => u128::from(n.clone()) as usize,
+            None => panic!("{} is not a concrete number", x),
+        }
     }
 }
 
@@ -45,6 +49,14 @@ impl From<StackVal> for usize {
 // }
 
 impl StackVal {
+    pub fn as_cnumber(&self) -> Option<&CNumber> {
+        if let Sentence::Basic(Value::Concrete(CSimpleVal::Number(n))) = &self.0 {
+            Some(n)
+        } else {
+            None
+        }
+    }
+
     pub fn _eq(&self, other: &Self) -> Self {
         StackVal(Sentence::BinOp { a: Val::new(self.0.clone()), b: Val::new(other.0.clone()), op: BinOp::Eq })
     }
@@ -66,6 +78,11 @@ impl std::ops::Add for StackVal {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self::Output {
+        if let (Some(a), Some(b)) = (self.as_cnumber(), rhs.as_cnumber()) {
+            return Self(Sentence::Basic(Value::Concrete(CSimpleVal::Number(
+                a.clone() + b.clone(),
+            ))));
+        }
         StackVal(Sentence::BinOp {
             a: Val::new(self.0),
             b: Val::new(rhs.0),
@@ -78,10 +95,36 @@ impl std::ops::Sub for StackVal {
     type Output = Self;
 
     fn sub(self, rhs: Self) -> Self::Output {
+        if let (Some(a), Some(b)) = (self.as_cnumber(), rhs.as_cnumber()) {
+            return Self(Sentence::Basic(Value::Concrete(CSimpleVal::Number(
+                a.clone() - b.clone(),
+            ))));
+        }
         StackVal(Sentence::BinOp {
             a: Val::new(self.0),
             b: Val::new(rhs.0),
             op: BinOp::Minus,
         })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::value::{SNumber, SSimpleVal, SymbolId};
+
+    #[test]
+    fn test_constant_folding() {
+        let sum = StackVal::from(2_u64) + StackVal::from(3_u64);
+        assert_eq!(sum, StackVal::from(5_u64));
+        let diff = StackVal::from(2_u64) - StackVal::from(3_u64);
+        assert_eq!(diff, StackVal::from(u64::MAX));
+
+        // Symbolic operands are left alone
+        let x = StackVal(Sentence::Basic(Value::Symbolic(
+            SSimpleVal::SymbolicNumber(SNumber(SymbolId("x".to_string(), None))),
+        )));
+        assert!((x.clone() + StackVal::from(1_u64)).0.is_bin_op());
+        assert!((StackVal::from(1_u64) - x).0.is_bin_op());
+    }
+}
@@ -0,0 +1,10 @@
+use thiserror::{self, Error};
+
+use crate::stack::StackVal;
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("Storage key {0} is not a concrete number")]
+    // Boxed to keep `MachineError` small
+    SymbolicKey(Box<StackVal>),
+}
@@ -0,0 +1,8 @@
+mod error;
+mod record;
+#[allow(clippy::module_inception)]
+mod storage;
+
+pub use error::StorageError;
+pub use record::{StorageOpRecord, StorageRecord};
+pub use storage::Storage;
@@ -0,0 +1,13 @@
+use crate::stack::StackVal;
+
+pub struct StorageRecord {
+    pub changed: Vec<StorageOpRecord>,
+}
+
+pub enum StorageOpRecord {
+    // The value at the key
+    Store(StackVal, StackVal),
+    // A read at the key. Symbolic storage remembers the keys read from its initial contents,
+    // so that later reads at keys equal to them agree; see `Storage::load`.
+    Load(StackVal),
+}
@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::stack::StackVal;
+use crate::value::{SNumber, SSimpleVal, Sentence, SymbolId, Value};
+
+use super::{
+    error::StorageError,
+    record::{StorageOpRecord, StorageRecord},
+};
+
+#[derive(Clone, Serialize, Deserialize)]
+enum Slots {
+    // Concrete keys, with values that may be symbolic. Keys never stored read zero.
+    Concrete(BTreeMap<u128, Sentence>),
+    // An uninterpreted map whose initial value at key `k` is the symbol `name[k]`
+    Symbolic {
+        name: String,
+        // In the order they were applied
+        stores: Vec<(Sentence, Sentence)>,
+        // Keys read from the initial contents
+        loads: Vec<Sentence>,
+    },
+}
+
+impl Default for Slots {
+    fn default() -> Self {
+        Slots::Concrete(BTreeMap::new())
+    }
+}
+
+// Key-value storage that persists across invocations of a program, unlike memory. See
+// `AbstractMachine::next_invocation`.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Storage {
+    slots: Slots,
+}
+
+impl Storage {
+    pub fn new(init: BTreeMap<u128, StackVal>) -> Self {
+        Self {
+            slots: Slots::Concrete(init.into_iter().map(|(k, v)| (k, v.0)).collect()),
+        }
+    }
+
+    // Storage whose initial contents are unknown. Keys and values may be symbolic.
+    pub fn symbolic(name: &str) -> Self {
+        Self {
+            slots: Slots::Symbolic {
+                name: name.to_string(),
+                stores: vec![],
+                loads: vec![],
+            },
+        }
+    }
+
+    // The value at `key`. In symbolic storage this is an ite chain over the stores whose keys
+    // may equal `key`, down to the initial value. Initial values at different keys are
+    // different symbols, tied together with ites over the keys of earlier loads, so keys that
+    // turn out equal read equal values as long as every load is recorded with
+    // `StorageOpRecord::Load`.
+    pub fn load(&self, key: &StackVal) -> Result<StackVal, StorageError> {
+        let (name, stores, loads) = match &self.slots {
+            Slots::Concrete(slots) => {
+                let k = concrete(&key.0)
+                    .ok_or_else(|| StorageError::SymbolicKey(Box::new(key.clone())))?;
+                return Ok(slots
+                    .get(&k)
+                    .map(|v| StackVal(v.clone()))
+                    .unwrap_or_else(|| StackVal::from(0_u64)));
+            }
+            Slots::Symbolic {
+                name,
+                stores,
+                loads,
+            } => (name, stores, loads),
+        };
+
+        let initial = loads.iter().fold(initial(name, &key.0), |v, k| {
+            select(key, k, || initial(name, k), v)
+        });
+        Ok(StackVal(stores.iter().fold(initial, |v, (k, stored)| {
+            select(key, k, || stored.clone(), v)
+        })))
+    }
+
+    pub fn apply(&self, r: StorageRecord) -> Result<Self, StorageError> {
+        let mut storage = self.clone();
+
+        for c in r.changed {
+            match (&mut storage.slots, c) {
+                (Slots::Concrete(slots), StorageOpRecord::Store(key, val)) => {
+                    let k =
+                        concrete(&key.0).ok_or_else(|| StorageError::SymbolicKey(Box::new(key)))?;
+                    slots.insert(k, val.0);
+                }
+                (Slots::Concrete(_), StorageOpRecord::Load(_)) => {}
+                (Slots::Symbolic { stores, .. }, StorageOpRecord::Store(key, val)) => {
+                    stores.push((key.0, val.0));
+                }
+                (Slots::Symbolic { loads, .. }, StorageOpRecord::Load(key)) => {
+                    if !loads.contains(&key.0) {
+                        loads.push(key.0);
+                    }
+                }
+            }
+        }
+
+        Ok(storage)
+    }
+}
+
+// `then` if `key` is `k`, `v` if it can't be, and `ite(key == k, then, v)` otherwise
+fn select(key: &StackVal, k: &Sentence, then: impl FnOnce() -> Sentence, v: Sentence) -> Sentence {
+    match (concrete(&key.0), concrete(k)) {
+        (Some(a), Some(b)) if a == b => then(),
+        (Some(_), Some(_)) => v,
+        _ if key.0 == *k => then(),
+        _ => {
+            key._eq(&StackVal(k.clone()))
+                .ite(StackVal(then()), StackVal(v))
+                .0
+        }
+    }
+}
+
+fn initial(name: &str, key: &Sentence) -> Sentence {
+    let key = match concrete(key) {
+        Some(k) => k.to_string(),
+        None => key.to_sexpr().to_string(),
+    };
+    Sentence::Basic(Value::Symbolic(SSimpleVal::SymbolicNumber(SNumber(
+        SymbolId(format!("{}[{}]", name, key), None),
+    ))))
+}
+
+fn concrete(s: &Sentence) -> Option<u128> {
+    StackVal(s.clone())
+        .as_cnumber()
+        .map(|n| u128::from(n.clone()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn store(storage: &Storage, key: StackVal, val: StackVal) -> Storage {
+        storage
+            .apply(StorageRecord {
+                changed: vec![StorageOpRecord::Store(key, val)],
+            })
+            .unwrap()
+    }
+
+    fn load(storage: &Storage, key: &StackVal) -> Storage {
+        storage
+            .apply(StorageRecord {
+                changed: vec![StorageOpRecord::Load(key.clone())],
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_concrete_storage() {
+        let x = StackVal("x".parse().unwrap());
+        let storage = Storage::new(BTreeMap::from([(1, StackVal::from(7_u64))]));
+        let storage = store(&storage, 2_u64.into(), x.clone());
+        assert_eq!(storage.load(&1_u64.into()).unwrap(), StackVal::from(7_u64));
+        assert_eq!(storage.load(&2_u64.into()).unwrap(), x);
+        assert_eq!(storage.load(&3_u64.into()).unwrap(), StackVal::from(0_u64));
+
+        assert!(matches!(
+            storage.load(&x),
+            Err(StorageError::SymbolicKey(k)) if *k == x
+        ));
+        assert!(storage
+            .apply(StorageRecord {
+                changed: vec![StorageOpRecord::Store(x, 1_u64.into())],
+            })
+            .is_err());
+
+        let json = serde_json::to_string(&storage).unwrap();
+        let back: Storage = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.load(&2_u64.into()).unwrap().to_string(), "x");
+    }
+
+    #[test]
+    fn test_symbolic_storage() {
+        let x = StackVal("x".parse().unwrap());
+        let y = StackVal("y".parse().unwrap());
+        let storage = Storage::symbolic("s");
+        assert_eq!(storage.load(&1_u64.into()).unwrap().to_string(), "s[1]");
+
+        let storage = store(&storage, 1_u64.into(), 5_u64.into());
+        let storage = store(&storage, x.clone(), 6_u64.into());
+        assert_eq!(
+            storage.load(&1_u64.into()).unwrap().to_string(),
+            "1 == x ? 6 : 5"
+        );
+        assert_eq!(
+            storage.load(&2_u64.into()).unwrap().to_string(),
+            "2 == x ? 6 : s[2]"
+        );
+        assert_eq!(storage.load(&x).unwrap().to_string(), "6");
+        assert_eq!(
+            storage.load(&y).unwrap().to_string(),
+            "y == x ? 6 : y == 1 ? 5 : s[y]"
+        );
+
+        // Reads of the initial contents agree at equal keys
+        let storage = load(&Storage::symbolic("s"), &y);
+        let storage = load(&storage, &2_u64.into());
+        assert_eq!(
+            storage.load(&x).unwrap().to_string(),
+            "x == 2 ? s[2] : x == y ? s[y] : s[x]"
+        );
+        assert_eq!(
+            storage.load(&3_u64.into()).unwrap().to_string(),
+            "3 == y ? s[y] : s[3]"
+        );
+    }
+}
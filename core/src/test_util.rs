@@ -0,0 +1,12 @@
+// Shorthands for the sentences tests build
+use crate::value::{CNumber, CSimpleVal, SNumber, SSimpleVal, Sentence, SymbolId, Value};
+
+pub fn sym(name: &str) -> Sentence {
+    Sentence::Basic(Value::Symbolic(SSimpleVal::SymbolicNumber(SNumber(
+        SymbolId(name.to_string(), None),
+    ))))
+}
+
+pub fn num(v: u64) -> Sentence {
+    Sentence::Basic(Value::Concrete(CSimpleVal::Number(CNumber::U64(v))))
+}
@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use std::rc::Rc;
 mod number;
 pub use number::*;
@@ -9,17 +10,22 @@ mod vecc;
 pub use vecc::*;
 mod sentence;
 pub use sentence::*;
+mod substitute;
+pub(crate) use substitute::{rename_value, Replace};
+mod visit;
+pub(crate) use visit::same_node;
+pub use visit::{Fold, Folder, Visitor};
 pub mod visitors;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct SymbolId<T>(pub String, pub Option<T>);
 
 impl<T> SymbolId<T> {
     pub fn new(item: Option<T>) -> Self {
-        Self (uuid::Uuid::new_v4().to_string(), item)
+        Self(uuid::Uuid::new_v4().to_string(), item)
     }
 }
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Val<T>(pub Rc<T>);
 impl<T> Val<T> {
     pub fn inner(&self) -> Rc<T> {
@@ -28,16 +34,16 @@ impl<T> Val<T> {
     pub fn new(v: T) -> Self {
         Self(Rc::new(v))
     }
-}
-
-pub trait Visitor<T> {
-    fn visit_sentence(&mut self, s: &Sentence) -> T;
-
-    fn visit_bin_op(&mut self, a: &Sentence, b: &Sentence) -> T;
 
-    fn visit_unary_op(&mut self, s: &Sentence) -> T;
-
-    fn visit_ternary_op(&mut self, a: &Sentence, b: &Sentence, c: &Sentence) -> T;
-
-    fn visit_val(&mut self, s: &Value) -> T;
+    // The shared node, unless it was already recorded in `visited`
+    pub(crate) fn unvisited<'a>(
+        &'a self,
+        visited: &mut std::collections::HashSet<*const T>,
+    ) -> Option<&'a T> {
+        if visited.insert(Rc::as_ptr(&self.0)) {
+            Some(self.0.as_ref())
+        } else {
+            None
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use super::SymbolId;
 use enum_as_inner::EnumAsInner;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -7,7 +8,9 @@ pub enum NumberError {
     #[error("Cannot convert {0:?} to {1:?}")]
     Convert(CNumber, CNumber),
 }
-#[derive(Clone, Debug, PartialEq, Eq, EnumAsInner, PartialOrd, Ord)]
+#[derive(
+    Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, EnumAsInner, PartialOrd, Ord,
+)]
 pub enum CNumber {
     U8(u8),
     U16(u16),
@@ -16,6 +19,36 @@ pub enum CNumber {
     U128(u128),
 }
 
+impl CNumber {
+    pub fn bit_width(&self) -> u32 {
+        match self {
+            CNumber::U8(_) => 8,
+            CNumber::U16(_) => 16,
+            CNumber::U32(_) => 32,
+            CNumber::U64(_) => 64,
+            CNumber::U128(_) => 128,
+        }
+    }
+
+    // Truncates `v` to `width` bits
+    pub fn from_u128_with_width(v: u128, width: u32) -> Self {
+        match width {
+            0..=8 => CNumber::U8(v as u8),
+            9..=16 => CNumber::U16(v as u16),
+            17..=32 => CNumber::U32(v as u32),
+            33..=64 => CNumber::U64(v as u64),
+            _ => CNumber::U128(v),
+        }
+    }
+
+    // Operands of different widths are widened to the larger of the two,
+    // and the result wraps around at that width
+    fn wrapping_op(self, rhs: Self, op: impl Fn(u128, u128) -> u128) -> Self {
+        let width = self.bit_width().max(rhs.bit_width());
+        Self::from_u128_with_width(op(self.into(), rhs.into()), width)
+    }
+}
+
 impl From<u8> for CNumber {
     fn from(v: u8) -> Self {
         CNumber::U8(v)
@@ -79,47 +112,77 @@ impl TryFrom<CNumber> for u64 {
 }
 
 impl From<CNumber> for u128 {
-    fn from(_: CNumber) -> Self {
-        todo!()
+    fn from(v: CNumber) -> Self {
+        match v {
+            CNumber::U8(v) => v.into(),
+            CNumber::U16(v) => v.into(),
+            CNumber::U32(v) => v.into(),
+            CNumber::U64(v) => v.into(),
+            CNumber::U128(v) => v,
+        }
     }
 }
 
 impl std::ops::Add for CNumber {
     type Output = Self;
 
-    fn add(self, _rhs: Self) -> Self::Output {
-        todo!()
+    fn add(self, rhs: Self) -> Self::Output {
+        self.wrapping_op(rhs, u128::wrapping_add)
     }
 }
 
 impl std::ops::Sub for CNumber {
     type Output = Self;
 
-    fn sub(self, _rhs: Self) -> Self::Output {
-        todo!()
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.wrapping_op(rhs, u128::wrapping_sub)
     }
 }
 
 impl std::ops::Mul for CNumber {
     type Output = Self;
 
-    fn mul(self, _rhs: Self) -> Self::Output {
-        todo!()
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.wrapping_op(rhs, u128::wrapping_mul)
     }
 }
 
+// Division by zero yields zero rather than panicking, as in the EVM
 impl std::ops::Div for CNumber {
     type Output = Self;
 
-    fn div(self, _rhs: Self) -> Self::Output {
-        todo!()
+    fn div(self, rhs: Self) -> Self::Output {
+        self.wrapping_op(rhs, |a, b| a.checked_div(b).unwrap_or(0))
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct SNumber(SymbolId<CNumber>);
-#[derive(Clone, Debug, PartialEq, Eq, EnumAsInner)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct SNumber(pub SymbolId<CNumber>);
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, EnumAsInner)]
 pub enum Number {
     Sym(SNumber),
     Con(CNumber),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wrapping_arithmetic() {
+        assert_eq!(CNumber::U8(250) + CNumber::U8(10), CNumber::U8(4));
+        assert_eq!(CNumber::U8(1) - CNumber::U64(2), CNumber::U64(u64::MAX));
+        assert_eq!(CNumber::U32(6) * CNumber::U8(7), CNumber::U32(42));
+        assert_eq!(
+            CNumber::U128(u128::MAX) * CNumber::U128(2),
+            CNumber::U128(u128::MAX - 1)
+        );
+    }
+
+    #[test]
+    fn test_division() {
+        assert_eq!(CNumber::U16(7) / CNumber::U16(2), CNumber::U16(3));
+        assert_eq!(CNumber::U16(7) / CNumber::U16(0), CNumber::U16(0));
+        assert_eq!(CNumber::U8(0) / CNumber::U64(0), CNumber::U64(0));
+    }
+}
@@ -1,7 +1,9 @@
 use super::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 
 // ------------- COMPOUND VALUES --------------
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Sentence {
     BinOp {
         a: Val<Sentence>,
@@ -21,29 +23,55 @@ pub enum Sentence {
     Basic(Value),
 }
 
-
 impl Sentence {
     pub fn is_bin_op(&self) -> bool {
-        if let Self::BinOp { a, b, op } = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Self::BinOp { .. })
     }
-  
+
     pub fn is_unary_op(&self) -> bool {
-        if let Self::UnaryOp { a, op } = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Self::UnaryOp { .. })
     }
 
     pub fn is_ternary_op(&self) -> bool {
-        if let Self::TernaryOp { a, b, c, op } = self {
-            true
-        } else {
-            false
+        matches!(self, Self::TernaryOp { .. })
+    }
+
+    // Names of all symbols the sentence depends on. Shared subtrees are visited once.
+    pub fn symbols(&self) -> BTreeSet<String> {
+        self.free_symbols().into_keys().collect()
+    }
+
+    // The symbols the sentence depends on, keyed by name
+    pub fn free_symbols(&self) -> BTreeMap<String, SSimpleVal> {
+        let mut symbols = BTreeMap::new();
+        self.collect_symbols(&mut symbols, &mut HashSet::new());
+        symbols
+    }
+
+    pub(crate) fn collect_symbols(
+        &self,
+        symbols: &mut BTreeMap<String, SSimpleVal>,
+        visited: &mut HashSet<*const Sentence>,
+    ) {
+        let mut todo = vec![self];
+        while let Some(s) = todo.pop() {
+            match s {
+                Sentence::Basic(v) => {
+                    if let (Some(name), Value::Symbolic(sym)) = (v.symbol(), v) {
+                        symbols.insert(name.to_string(), sym.clone());
+                    }
+                }
+                Sentence::UnaryOp { a, .. } => todo.extend(a.unvisited(visited)),
+                Sentence::BinOp { a, b, .. } => {
+                    todo.extend(a.unvisited(visited));
+                    todo.extend(b.unvisited(visited));
+                }
+                Sentence::TernaryOp { a, b, c, .. } => {
+                    todo.extend(a.unvisited(visited));
+                    todo.extend(b.unvisited(visited));
+                    todo.extend(c.unvisited(visited));
+                }
+            }
         }
     }
 }
@@ -52,12 +80,14 @@ impl Default for Sentence {
         Self::Basic(Default::default())
     }
 }
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum TernaryOp {
     Ite,
+    // Array `a` with the byte at index `b` replaced by the low byte of `c`
+    Store,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum BinOp {
     Plus,
     Minus,
@@ -77,9 +107,11 @@ pub enum BinOp {
     BitXor,
     LShift,
     RShift,
+    // The byte of array `a` at index `b`
+    Select,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum UnaryOp {
     Not,
     BitNot,
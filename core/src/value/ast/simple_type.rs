@@ -1,5 +1,6 @@
 use super::*;
 use enum_as_inner::EnumAsInner;
+use serde::{Deserialize, Serialize};
 
 // pub trait Valued {
 //     type SymbolicType;
@@ -23,23 +24,31 @@ use enum_as_inner::EnumAsInner;
 //     inner: Vec<&dyn Valued>,
 
 // }
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Default)]
 pub struct AnyVal {
     inner_sym: SSimpleVal,
-    inner_con: CSimpleVal
+    inner_con: CSimpleVal,
 }
 
-impl Default for AnyVal {
-    fn default() -> Self {
-        Self { inner_sym: Default::default(), inner_con: Default::default() }
-    }
-}
-
-#[derive(Clone, Debug, PartialEq, Eq, EnumAsInner)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, EnumAsInner)]
 pub enum Value {
     Symbolic(SSimpleVal),
     Concrete(CSimpleVal),
-    Either(AnyVal)
+    Either(AnyVal),
+}
+
+impl Value {
+    // Name of the symbol, if this is a symbolic value
+    pub fn symbol(&self) -> Option<&str> {
+        match self {
+            Value::Symbolic(SSimpleVal::SymbolicBool(SymbolId(name, _)))
+            | Value::Symbolic(SSimpleVal::SymbolicNumber(SNumber(SymbolId(name, _))))
+            | Value::Symbolic(SSimpleVal::SymbolicVector(SymbolicVecc(SymbolId(name, _)))) => {
+                Some(name)
+            }
+            _ => None,
+        }
+    }
 }
 
 impl Default for Value {
@@ -52,7 +61,7 @@ impl Default for Value {
 // Operations on this builds AST
 // whereas operations on its inner types simple
 // dispatches to its inner type's concrete type
-#[derive(Clone, Debug, PartialEq, Eq, EnumAsInner)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, EnumAsInner)]
 pub enum CSimpleVal {
     Boolean(Bool),
     Number(CNumber),
@@ -65,7 +74,7 @@ impl Default for CSimpleVal {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, EnumAsInner)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, EnumAsInner)]
 // Symbolic Simple Val
 pub enum SSimpleVal {
     SymbolicBool(SymbolId<Bool>),
@@ -77,4 +86,4 @@ impl Default for SSimpleVal {
     fn default() -> Self {
         Self::SymbolicBool(SymbolId::new(Some(Bool::True)))
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,108 @@
+use super::*;
+use std::collections::HashMap;
+
+// Replaces the leaves for which the closure gives a sentence
+pub(crate) struct Replace<F>(pub F);
+
+impl<F: FnMut(&Value) -> Option<Sentence>> Fold for Replace<F> {
+    fn fold_val(&mut self, v: &Value) -> Sentence {
+        (self.0)(v).unwrap_or_else(|| Sentence::Basic(v.clone()))
+    }
+}
+
+// The symbol renamed, keeping its kind and value
+pub(crate) fn rename_value(v: &Value, names: &HashMap<String, String>) -> Option<Sentence> {
+    let name = names.get(v.symbol()?)?.clone();
+    let renamed = match v {
+        Value::Symbolic(SSimpleVal::SymbolicBool(SymbolId(_, item))) => {
+            SSimpleVal::SymbolicBool(SymbolId(name, item.clone()))
+        }
+        Value::Symbolic(SSimpleVal::SymbolicNumber(SNumber(SymbolId(_, item)))) => {
+            SSimpleVal::SymbolicNumber(SNumber(SymbolId(name, item.clone())))
+        }
+        Value::Symbolic(SSimpleVal::SymbolicVector(SymbolicVecc(SymbolId(_, item)))) => {
+            SSimpleVal::SymbolicVector(SymbolicVecc(SymbolId(name, item.clone())))
+        }
+        _ => return None,
+    };
+    Some(Sentence::Basic(Value::Symbolic(renamed)))
+}
+
+impl Sentence {
+    // Replaces the symbols named in `subst`, all at once: symbols in the replacements are
+    // not substituted again. For concrete values from a solver, see `Model::substitution`.
+    pub fn substitute(&self, subst: &HashMap<String, Sentence>) -> Sentence {
+        self.fold(Replace(|v: &Value| subst.get(v.symbol()?).cloned()))
+    }
+
+    pub fn rename(&self, names: &HashMap<String, String>) -> Sentence {
+        self.fold(Replace(|v: &Value| rename_value(v, names)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::sym;
+
+    fn children(s: &Sentence) -> (Val<Sentence>, Val<Sentence>) {
+        match s {
+            Sentence::BinOp { a, b, .. } => (a.clone(), b.clone()),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_substitute_preserves_sharing() {
+        // (x * x) + ((x * x) - y), with `x * x` shared
+        let square: Sentence = "(* x x)".parse().unwrap();
+        let square = Val::new(square);
+        let s = Sentence::BinOp {
+            a: square.clone(),
+            b: Val::new(Sentence::BinOp {
+                a: square.clone(),
+                b: Val::new(sym("y")),
+                op: BinOp::Minus,
+            }),
+            op: BinOp::Plus,
+        };
+
+        let subst = HashMap::from([("y".to_string(), "(+ x 1)".parse().unwrap())]);
+        let s2 = s.substitute(&subst);
+        assert_eq!(s2.to_sexpr().to_string(), "(+ (* x x) (- (* x x) (+ x 1)))");
+        let (a, b) = children(&s2);
+        assert!(Rc::ptr_eq(&a.0, &square.0));
+        assert!(Rc::ptr_eq(&children(&b.0).0 .0, &square.0));
+
+        let subst = HashMap::from([("x".to_string(), sym("y")), ("y".to_string(), sym("x"))]);
+        let s3 = s.substitute(&subst);
+        assert_eq!(s3.to_sexpr().to_string(), "(+ (* y y) (- (* y y) x))");
+        let (a, b) = children(&s3);
+        assert!(Rc::ptr_eq(&a.0, &children(&b.0).0 .0));
+
+        assert!(std::ptr::eq(
+            children(&s.substitute(&HashMap::new())).0 .0.as_ref(),
+            square.0.as_ref()
+        ));
+    }
+
+    #[test]
+    fn test_rename() {
+        let s: Sentence = "(ite (bool b true) (sym x (u8 3)) (+ x 1))"
+            .parse()
+            .unwrap();
+        let names = HashMap::from([
+            ("b".to_string(), "c".to_string()),
+            ("x".to_string(), "|x 0|".to_string()),
+        ]);
+
+        assert_eq!(
+            s.rename(&names).to_sexpr().to_string(),
+            "(ite (bool c true) (sym ||x 0|| (u8 3)) (+ ||x 0|| 1))"
+        );
+        assert_eq!(
+            s.rename(&names).free_symbols().keys().collect::<Vec<_>>(),
+            vec!["c", "|x 0|"]
+        );
+    }
+}
@@ -1,9 +1,10 @@
 use super::SymbolId;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct Vecc(pub Vec<u8>);
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct SymbolicVecc(pub SymbolId<Vecc>);
 
 impl<T> From<T> for Vecc
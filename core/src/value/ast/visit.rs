@@ -0,0 +1,377 @@
+use super::*;
+use std::collections::HashMap;
+
+// Bottom-up traversal: each node is visited with its operator and the results for its
+// children. Results are memoized per shared node, so a subtree referenced from several
+// places is visited once and its result reused.
+pub trait Visitor<T> {
+    fn visit_val(&mut self, v: &Value) -> T;
+
+    fn visit_unary_op(&mut self, op: &UnaryOp, a: T) -> T;
+
+    fn visit_bin_op(&mut self, op: &BinOp, a: T, b: T) -> T;
+
+    fn visit_ternary_op(&mut self, op: &TernaryOp, a: T, b: T, c: T) -> T;
+
+    fn visit_sentence(&mut self, s: &Sentence) -> T
+    where
+        T: Clone,
+        Self: Sized,
+    {
+        s.accept(self)
+    }
+}
+
+// Rewrites a sentence bottom-up. Each method receives the already rewritten children;
+// the defaults rebuild the node unchanged, so a fold only overrides the cases it
+// rewrites. Run it with `Sentence::fold` or a `Folder`.
+pub trait Fold {
+    fn fold_val(&mut self, v: &Value) -> Sentence {
+        Sentence::Basic(v.clone())
+    }
+
+    fn fold_unary_op(&mut self, op: &UnaryOp, a: Val<Sentence>) -> Sentence {
+        Sentence::UnaryOp { a, op: op.clone() }
+    }
+
+    fn fold_bin_op(&mut self, op: &BinOp, a: Val<Sentence>, b: Val<Sentence>) -> Sentence {
+        Sentence::BinOp {
+            a,
+            b,
+            op: op.clone(),
+        }
+    }
+
+    fn fold_ternary_op(
+        &mut self,
+        op: &TernaryOp,
+        a: Val<Sentence>,
+        b: Val<Sentence>,
+        c: Val<Sentence>,
+    ) -> Sentence {
+        Sentence::TernaryOp {
+            a,
+            b,
+            c,
+            op: op.clone(),
+        }
+    }
+}
+
+// Runs a `Fold` over any number of sentences. Each shared node is rewritten once, and a
+// node whose rewrite is the same as the original keeps its original `Rc`, so the result
+// shares every unchanged subtree with the input.
+pub struct Folder<F> {
+    pub fold: F,
+    // Keyed by the original node, which is kept alive so its address isn't reused
+    memo: HashMap<*const Sentence, (Val<Sentence>, Val<Sentence>)>,
+}
+
+impl<F: Fold> Folder<F> {
+    pub fn new(fold: F) -> Self {
+        Self {
+            fold,
+            memo: HashMap::new(),
+        }
+    }
+
+    pub fn fold(&mut self, s: &Sentence) -> Sentence {
+        match s {
+            Sentence::Basic(v) => self.fold.fold_val(v),
+            Sentence::UnaryOp { a, op } => {
+                let a = self.fold_shared(a);
+                self.fold.fold_unary_op(op, a)
+            }
+            Sentence::BinOp { a, b, op } => {
+                let (a, b) = (self.fold_shared(a), self.fold_shared(b));
+                self.fold.fold_bin_op(op, a, b)
+            }
+            Sentence::TernaryOp { a, b, c, op } => {
+                let (a, b, c) = (
+                    self.fold_shared(a),
+                    self.fold_shared(b),
+                    self.fold_shared(c),
+                );
+                self.fold.fold_ternary_op(op, a, b, c)
+            }
+        }
+    }
+
+    fn fold_shared(&mut self, v: &Val<Sentence>) -> Val<Sentence> {
+        let key = Rc::as_ptr(&v.0);
+        if let Some((_, done)) = self.memo.get(&key) {
+            return done.clone();
+        }
+        let folded = self.fold(&v.0);
+        let done = if same_node(&folded, &v.0) {
+            v.clone()
+        } else {
+            Val::new(folded)
+        };
+        self.memo.insert(key, (v.clone(), done.clone()));
+        done
+    }
+}
+
+fn same_child(a: &Val<Sentence>, b: &Val<Sentence>) -> bool {
+    Rc::ptr_eq(&a.0, &b.0)
+}
+
+// Whether `a` and `b` are the same node over the same children, without comparing the
+// children themselves
+pub(crate) fn same_node(a: &Sentence, b: &Sentence) -> bool {
+    match (a, b) {
+        (Sentence::Basic(x), Sentence::Basic(y)) => x == y,
+        (Sentence::UnaryOp { a, op }, Sentence::UnaryOp { a: a2, op: op2 }) => {
+            op == op2 && same_child(a, a2)
+        }
+        (
+            Sentence::BinOp { a, b, op },
+            Sentence::BinOp {
+                a: a2,
+                b: b2,
+                op: op2,
+            },
+        ) => op == op2 && same_child(a, a2) && same_child(b, b2),
+        (
+            Sentence::TernaryOp { a, b, c, op },
+            Sentence::TernaryOp {
+                a: a2,
+                b: b2,
+                c: c2,
+                op: op2,
+            },
+        ) => op == op2 && same_child(a, a2) && same_child(b, b2) && same_child(c, c2),
+        _ => false,
+    }
+}
+
+struct Accept<'v, T, V: ?Sized> {
+    visitor: &'v mut V,
+    memo: HashMap<*const Sentence, T>,
+}
+
+impl<T: Clone, V: Visitor<T> + ?Sized> Accept<'_, T, V> {
+    fn visit(&mut self, s: &Sentence) -> T {
+        match s {
+            Sentence::Basic(v) => self.visitor.visit_val(v),
+            Sentence::UnaryOp { a, op } => {
+                let a = self.visit_shared(a);
+                self.visitor.visit_unary_op(op, a)
+            }
+            Sentence::BinOp { a, b, op } => {
+                let (a, b) = (self.visit_shared(a), self.visit_shared(b));
+                self.visitor.visit_bin_op(op, a, b)
+            }
+            Sentence::TernaryOp { a, b, c, op } => {
+                let (a, b, c) = (
+                    self.visit_shared(a),
+                    self.visit_shared(b),
+                    self.visit_shared(c),
+                );
+                self.visitor.visit_ternary_op(op, a, b, c)
+            }
+        }
+    }
+
+    fn visit_shared(&mut self, v: &Val<Sentence>) -> T {
+        let key = Rc::as_ptr(&v.0);
+        if let Some(done) = self.memo.get(&key) {
+            return done.clone();
+        }
+        let done = self.visit(&v.0);
+        self.memo.insert(key, done.clone());
+        done
+    }
+}
+
+impl Sentence {
+    pub fn accept<T: Clone, V: Visitor<T> + ?Sized>(&self, visitor: &mut V) -> T {
+        Accept {
+            visitor,
+            memo: HashMap::new(),
+        }
+        .visit(self)
+    }
+
+    pub fn fold(&self, fold: impl Fold) -> Sentence {
+        Folder::new(fold).fold(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Number of nodes in the sentence as a tree, counting shared subtrees each time
+    struct TreeSize;
+
+    impl Visitor<usize> for TreeSize {
+        fn visit_val(&mut self, _v: &Value) -> usize {
+            1
+        }
+
+        fn visit_unary_op(&mut self, _op: &UnaryOp, a: usize) -> usize {
+            1 + a
+        }
+
+        fn visit_bin_op(&mut self, _op: &BinOp, a: usize, b: usize) -> usize {
+            1 + a + b
+        }
+
+        fn visit_ternary_op(&mut self, _op: &TernaryOp, a: usize, b: usize, c: usize) -> usize {
+            1 + a + b + c
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Sort {
+        Bool,
+        Number,
+        Vector,
+    }
+
+    struct SortCheck {
+        visited: usize,
+    }
+
+    impl Visitor<Result<Sort, String>> for SortCheck {
+        fn visit_val(&mut self, v: &Value) -> Result<Sort, String> {
+            self.visited += 1;
+            Ok(match v {
+                Value::Symbolic(SSimpleVal::SymbolicBool(_))
+                | Value::Concrete(CSimpleVal::Boolean(_)) => Sort::Bool,
+                Value::Symbolic(SSimpleVal::SymbolicVector(_))
+                | Value::Concrete(CSimpleVal::Vector(_)) => Sort::Vector,
+                _ => Sort::Number,
+            })
+        }
+
+        fn visit_unary_op(
+            &mut self,
+            op: &UnaryOp,
+            a: Result<Sort, String>,
+        ) -> Result<Sort, String> {
+            self.visited += 1;
+            match (op, a?) {
+                (UnaryOp::Not, Sort::Bool) => Ok(Sort::Bool),
+                (UnaryOp::BitNot, Sort::Number) => Ok(Sort::Number),
+                (op, a) => Err(format!("{:?} of {:?}", op, a)),
+            }
+        }
+
+        fn visit_bin_op(
+            &mut self,
+            op: &BinOp,
+            a: Result<Sort, String>,
+            b: Result<Sort, String>,
+        ) -> Result<Sort, String> {
+            self.visited += 1;
+            let (a, b) = (a?, b?);
+            match op {
+                BinOp::Eq | BinOp::Neq if a == b => Ok(Sort::Bool),
+                BinOp::Lt | BinOp::Lte | BinOp::Gt | BinOp::Gte
+                    if (a, b) == (Sort::Number, Sort::Number) =>
+                {
+                    Ok(Sort::Bool)
+                }
+                BinOp::Eq | BinOp::Neq | BinOp::Lt | BinOp::Lte | BinOp::Gt | BinOp::Gte => {
+                    Err(format!("{:?} of {:?} and {:?}", op, a, b))
+                }
+                _ if (a, b) == (Sort::Number, Sort::Number) => Ok(Sort::Number),
+                _ => Err(format!("{:?} of {:?} and {:?}", op, a, b)),
+            }
+        }
+
+        fn visit_ternary_op(
+            &mut self,
+            op: &TernaryOp,
+            a: Result<Sort, String>,
+            b: Result<Sort, String>,
+            c: Result<Sort, String>,
+        ) -> Result<Sort, String> {
+            self.visited += 1;
+            match (a?, b?, c?) {
+                (Sort::Bool, b, c) if b == c => Ok(b),
+                (a, b, c) => Err(format!("{:?} of {:?}, {:?} and {:?}", op, a, b, c)),
+            }
+        }
+    }
+
+    // Replaces `x + 0` and `0 + x` by `x`
+    struct DropZeroes;
+
+    fn is_zero(s: &Sentence) -> bool {
+        matches!(s, Sentence::Basic(Value::Concrete(CSimpleVal::Number(n))) if u128::from(n.clone()) == 0)
+    }
+
+    impl Fold for DropZeroes {
+        fn fold_bin_op(&mut self, op: &BinOp, a: Val<Sentence>, b: Val<Sentence>) -> Sentence {
+            match op {
+                BinOp::Plus if is_zero(&b.0) => (*a.0).clone(),
+                BinOp::Plus if is_zero(&a.0) => (*b.0).clone(),
+                _ => Sentence::BinOp {
+                    a,
+                    b,
+                    op: op.clone(),
+                },
+            }
+        }
+    }
+
+    fn shared(s: &str) -> Sentence {
+        // ((s) * (s)), with both operands the same node
+        let s = Val::new(s.parse::<Sentence>().unwrap());
+        Sentence::BinOp {
+            a: s.clone(),
+            b: s,
+            op: BinOp::Mul,
+        }
+    }
+
+    #[test]
+    fn test_visitor() {
+        let s = shared("(+ x (ite (= y 1) 2 3))");
+        assert_eq!(s.accept(&mut TreeSize), 17);
+
+        let mut check = SortCheck { visited: 0 };
+        assert_eq!(s.accept(&mut check), Ok(Sort::Number));
+        // The shared operand is only visited once
+        assert_eq!(check.visited, 9);
+
+        let bad: Sentence = "(ite (+ y 1) 2 3)".parse().unwrap();
+        assert_eq!(
+            check.visit_sentence(&bad),
+            Err("Ite of Number, Number and Number".to_string())
+        );
+        let bad: Sentence = "(not (< (bool b) 1))".parse().unwrap();
+        assert!(bad.accept(&mut check).is_err());
+    }
+
+    #[test]
+    fn test_fold() {
+        let s = shared("(* (+ x 0) (+ 0 (- y 1)))");
+        let folded = s.fold(DropZeroes);
+        assert_eq!(
+            folded.to_sexpr().to_string(),
+            "(* (* x (- y 1)) (* x (- y 1)))"
+        );
+        match (&s, &folded) {
+            (Sentence::BinOp { a, b, .. }, Sentence::BinOp { a: a2, b: b2, .. }) => {
+                assert!(Rc::ptr_eq(&a.0, &b.0));
+                assert!(Rc::ptr_eq(&a2.0, &b2.0));
+            }
+            _ => unreachable!(),
+        }
+
+        // Nothing to rewrite: every node is kept
+        let s = shared("(+ x (- y 1))");
+        let mut folder = Folder::new(DropZeroes);
+        match (&s, &folder.fold(&s)) {
+            (Sentence::BinOp { a, .. }, Sentence::BinOp { a: a2, .. }) => {
+                assert!(Rc::ptr_eq(&a.0, &a2.0))
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -1,5 +1,24 @@
 use crate::value::ast::*;
-use rhai::{Engine, EvalAltResult, Scope};
+use rhai::{Engine, EvalAltResult, Scope, AST};
+use std::collections::HashMap;
+use std::rc::Rc;
+use thiserror::Error;
+
+// Scripts of an `Evaluate`, compiled once and reused for every node
+pub struct EvalScripts {
+    hook: AST,
+    post_process: AST,
+}
+
+#[derive(Error, Debug)]
+pub enum EvalError {
+    #[error("Failed to compile {0} script: {1}")]
+    Compile(&'static str, Box<EvalAltResult>),
+    #[error("Hook failed on {0}: {1}")]
+    Hook(Box<Sentence>, Box<EvalAltResult>),
+    #[error("Post processing failed on {0}: {1}")]
+    PostProcess(Box<Sentence>, Box<EvalAltResult>),
+}
 
 pub trait Evaluate {
     type FinalType: Clone + 'static;
@@ -8,80 +27,130 @@ pub trait Evaluate {
     fn interpreter_hook(&self) -> String;
     fn post_process_fn(&self) -> String;
     fn engine(&self) -> &Engine;
-    fn scope(&self) -> Scope;
-  
-    fn interpret(&self, sentence: Sentence) -> Self::FinalType {
-        let mut scope = self.scope().clone();
-        
-        let sentence = self.process_sentence(&sentence);
-        scope.push("pgm_final", sentence);
-        let res = self.engine()
-            .eval_with_scope::<Self::FinalType>(&mut scope, &self.post_process_fn())
-            .expect("Error transforming final sentence to value");
-        res
-        
+    fn scope(&self) -> Scope<'_>;
+
+    fn compile(&self) -> Result<EvalScripts, EvalError> {
+        let compile = |what, script: String| {
+            self.engine()
+                .compile(script)
+                .map_err(|e| EvalError::Compile(what, e.into()))
+        };
+        Ok(EvalScripts {
+            hook: compile("hook", self.interpreter_hook())?,
+            post_process: compile("post process", self.post_process_fn())?,
+        })
+    }
+
+    fn interpret(&self, sentence: Sentence) -> Result<Self::FinalType, EvalError> {
+        let scripts = self.compile()?;
+        self.interpret_compiled(&scripts, sentence)
     }
 
-    fn process_sentence(&self, sentence: &Sentence) -> Sentence {
+    // Like `interpret`, with scripts compiled beforehand so they can be reused across calls
+    fn interpret_compiled(
+        &self,
+        scripts: &EvalScripts,
+        sentence: Sentence,
+    ) -> Result<Self::FinalType, EvalError> {
+        let mut scope = self.scope().clone();
+
+        let mut memo = HashMap::new();
+        let sentence = self.process_sentence(&sentence, &scripts.hook, &mut memo)?;
+        scope.push("pgm_final", sentence.clone());
+        self.engine()
+            .eval_ast_with_scope::<Self::FinalType>(&mut scope, &scripts.post_process)
+            .map_err(|e| EvalError::PostProcess(Box::new(sentence), e))
+    }
 
+    // The hook sees each node once: a subtree shared by several parents is processed once
+    // and its result shared, as in `Interpreter`
+    fn process_sentence(
+        &self,
+        sentence: &Sentence,
+        hook: &AST,
+        memo: &mut Memo,
+    ) -> Result<Sentence, EvalError> {
         let res = match sentence {
-            Sentence::BinOp { a, b, op } => {
-                self.process_bin_op(&a.inner(), &b.inner(), op.clone())
+            Sentence::BinOp { a, b, op } => self.process_bin_op(a, b, op.clone(), hook, memo)?,
+            Sentence::UnaryOp { a, op } => self.process_unary_op(a, op.clone(), hook, memo)?,
+            Sentence::TernaryOp { a, b, c, op } => {
+                self.process_ternary_op(a, b, c, op.clone(), hook, memo)?
             }
-            Sentence::UnaryOp { a, op } => {
-                self.process_unary_op(&a.inner(), op.clone())
-            }
-            Sentence::TernaryOp { a, b, c, op } => self.process_ternary_op(
-                &a.inner(),
-                &b.inner(),
-                &c.inner(),
-                op.clone(),
-            ),
             Sentence::Basic(_v) => sentence.clone(),
         };
-        match self.exec_hook(&res) {
-            Some(s) => s,
-            None => res,
+        match self.exec_hook(&res, hook)? {
+            Some(s) => Ok(s),
+            None => Ok(res),
         }
     }
 
-    fn exec_hook(&self, sentence: &Sentence) -> Option<Sentence> {
+    fn process_shared(
+        &self,
+        v: &Val<Sentence>,
+        hook: &AST,
+        memo: &mut Memo,
+    ) -> Result<Val<Sentence>, EvalError> {
+        let key = Rc::as_ptr(&v.0);
+        if let Some(done) = memo.get(&key) {
+            return Ok(done.clone());
+        }
+        let done = Val::new(self.process_sentence(&v.0, hook, memo)?);
+        memo.insert(key, done.clone());
+        Ok(done)
+    }
+
+    fn exec_hook(&self, sentence: &Sentence, hook: &AST) -> Result<Option<Sentence>, EvalError> {
         let mut scope = self.scope().clone();
         scope.push("pgm", sentence.clone());
-        let res = self.engine()
-            .eval_with_scope::<Option<Sentence>>(&mut scope, &self.interpreter_hook())
-            .expect("Error interpreting value with hook");
-        res
+        self.engine()
+            .eval_ast_with_scope::<Option<Sentence>>(&mut scope, hook)
+            .map_err(|e| EvalError::Hook(Box::new(sentence.clone()), e))
     }
 
+    fn process_bin_op(
+        &self,
+        a: &Val<Sentence>,
+        b: &Val<Sentence>,
+        op: BinOp,
+        hook: &AST,
+        memo: &mut Memo,
+    ) -> Result<Sentence, EvalError> {
+        let aa = self.process_shared(a, hook, memo)?;
+        let bb = self.process_shared(b, hook, memo)?;
 
-    fn process_bin_op(&self,  a: &Sentence, b: &Sentence, op: BinOp) -> Sentence {
-        let aa = self.process_sentence(a);
-        let bb = self.process_sentence(b);
-
-        Sentence::BinOp {
-            a: Val::new(aa),
-            b: Val::new(bb),
-            op,
-        }
+        Ok(Sentence::BinOp { a: aa, b: bb, op })
     }
 
-    fn process_ternary_op(&self,  a: &Sentence, b: &Sentence, c: &Sentence, op: TernaryOp) -> Sentence {
-        let aa = self.process_sentence(a);
-        let bb = self.process_sentence(b);
-        let cc = self.process_sentence(c);
-        Sentence::TernaryOp {
-            a: Val::new(aa),
-            b: Val::new(bb),
-            c: Val::new(cc),
+    fn process_ternary_op(
+        &self,
+        a: &Val<Sentence>,
+        b: &Val<Sentence>,
+        c: &Val<Sentence>,
+        op: TernaryOp,
+        hook: &AST,
+        memo: &mut Memo,
+    ) -> Result<Sentence, EvalError> {
+        let aa = self.process_shared(a, hook, memo)?;
+        let bb = self.process_shared(b, hook, memo)?;
+        let cc = self.process_shared(c, hook, memo)?;
+        Ok(Sentence::TernaryOp {
+            a: aa,
+            b: bb,
+            c: cc,
             op,
-        }
+        })
     }
 
-    fn process_unary_op(&self, a: &Sentence, op: UnaryOp) -> Sentence {
-        let aa = self.process_sentence(a);
+    fn process_unary_op(
+        &self,
+        a: &Val<Sentence>,
+        op: UnaryOp,
+        hook: &AST,
+        memo: &mut Memo,
+    ) -> Result<Sentence, EvalError> {
+        let aa = self.process_shared(a, hook, memo)?;
 
-        Sentence::UnaryOp { a: Val::new(aa), op }
+        Ok(Sentence::UnaryOp { a: aa, op })
     }
 }
 pub enum InterpreterMode {
@@ -99,161 +168,108 @@ pub struct BaseInterpreter {
 pub struct AdHocInterpreter {}
 
 impl AdHocInterpreter {
-    pub fn interpret<T, Final>(post: Hook, final_hook: Final, sentence: Sentence) -> T 
+    pub fn interpret<T, Final>(
+        post: Hook,
+        final_hook: Final,
+        sentence: Sentence,
+    ) -> Result<T, HookError>
     where
         Final: Fn(Sentence) -> T,
     {
-        let interpreter = Interpreter { pgm: sentence};
-        let pre_hook = Box::new(|s: Sentence| -> Option<Sentence> {None});
+        let interpreter = Interpreter { pgm: sentence };
+        let pre_hook: Hook = Box::new(|_s: &Sentence| Ok(None));
         interpreter.interpret(pre_hook, post, final_hook)
     }
 }
 
+#[derive(Error, Debug)]
+pub enum HookError {
+    #[error("Pre hook failed on {0}: {1}")]
+    Pre(Box<Sentence>, String),
+    #[error("Post hook failed on {0}: {1}")]
+    Post(Box<Sentence>, String),
+}
 
-
-
-
-pub type Hook = Box<dyn Fn(Sentence) -> Option<Sentence>>;
-// Post hook is called after the AST has been processed
+// A hook either leaves the node alone (`Ok(None)`), replaces it, or fails with a message
+pub type Hook = Box<dyn Fn(&Sentence) -> Result<Option<Sentence>, String>>;
 // Pre hook is called at each node in the AST prior to potentially increased traversal.
 // If pre_hook returns None, then processing continues as normal. If it returns Some(Sentence), then the sentence returned
 // is used instead of the sentence that would result from further processing.
+// Post hook is called on each node after its children have been processed, including on the
+// replacement from a pre hook.
+// Each hook sees each node once: a subtree shared by several parents is processed once and its
+// result shared, and nodes the hooks leave unchanged keep their original `Rc`.
 // Final is only called at the end
 impl Interpreter {
-    pub fn interpret<T, Final>(&self, pre: Hook, post: Hook, final_hook: Final) -> T
+    pub fn interpret<T, Final>(
+        &self,
+        pre: Hook,
+        post: Hook,
+        final_hook: Final,
+    ) -> Result<T, HookError>
     where
         Final: Fn(Sentence) -> T,
     {
-        if let Some(s) = pre(self.pgm.clone()) {
-            let ret = match self.exec_hook(&post, &s) {
-                Some(sen) => sen,
-                None => s,
-            };
-            final_hook(ret)
-        } else {
-            let sentence = self.process_sentence(&self.pgm, &pre, &post);
-            let ret = match self.exec_hook(&post, &sentence) {
-                Some(sen) => sen,
-                None => sentence,
-            };
-            final_hook(ret)
-        }
+        let mut memo = HashMap::new();
+        let sentence = self.process_sentence(&self.pgm, &pre, &post, &mut memo)?;
+        Ok(final_hook(sentence))
     }
 
-    fn exec_hook(
+    fn process_sentence(
         &self,
-        hook: &impl Fn(Sentence) -> Option<Sentence>,
-        on: &Sentence,
-    ) -> Option<Sentence> {
-        hook(on.clone())
-    }
-
-    fn process_sentence(&self, s: &Sentence, pre_hook: &Hook, post_hook: &Hook) -> Sentence {
-        let res = match self.exec_hook(pre_hook, s) {
-            None => match s {
-                Sentence::BinOp { a, b, op } => {
-                    self.process_bin_op(&a.inner(), &b.inner(), op.clone(), pre_hook, post_hook)
-                }
-                Sentence::UnaryOp { a, op } => {
-                    self.process_unary_op(&a.inner(), op.clone(), pre_hook, post_hook)
-                }
-                Sentence::TernaryOp { a, b, c, op } => self.process_ternary_op(
-                    &a.inner(),
-                    &b.inner(),
-                    &c.inner(),
-                    op.clone(),
-                    pre_hook,
-                    post_hook,
-                ),
-                Sentence::Basic(_v) => s.clone(),
-            },
-            Some(s) => s,
-        };
-
-        // We only need to exec post_hook here , since every node in the tree is encapsulated by a sentence, so each subtree traversal
-        // will result in a call to process_sentence
-        match self.exec_hook(post_hook, &res) {
-            Some(s) => s,
-            None => res,
-        }
-    }
-
-    fn get_basic_val(s: &Sentence) -> Option<Value> {
-        if let Sentence::Basic(v) = s {
-            Some(v.clone())
-        } else {
-            None
-        }
-    }
-
-    fn process_bin_op(
-        &self,
-        a: &Sentence,
-        b: &Sentence,
-        op: BinOp,
+        s: &Sentence,
         pre_hook: &Hook,
         post_hook: &Hook,
-    ) -> Sentence {
-        let aa = match self.exec_hook(pre_hook, a) {
-            None => self.process_sentence(a, pre_hook, post_hook),
-            Some(s) => s,
-        };
-        let bb = match self.exec_hook(pre_hook, b) {
-            None => self.process_sentence(b, pre_hook, post_hook),
+        memo: &mut Memo,
+    ) -> Result<Sentence, HookError> {
+        let res = match pre_hook(s).map_err(|e| HookError::Pre(Box::new(s.clone()), e))? {
             Some(s) => s,
+            None => match s {
+                Sentence::BinOp { a, b, op } => Sentence::BinOp {
+                    a: self.process_shared(a, pre_hook, post_hook, memo)?,
+                    b: self.process_shared(b, pre_hook, post_hook, memo)?,
+                    op: op.clone(),
+                },
+                Sentence::UnaryOp { a, op } => Sentence::UnaryOp {
+                    a: self.process_shared(a, pre_hook, post_hook, memo)?,
+                    op: op.clone(),
+                },
+                Sentence::TernaryOp { a, b, c, op } => Sentence::TernaryOp {
+                    a: self.process_shared(a, pre_hook, post_hook, memo)?,
+                    b: self.process_shared(b, pre_hook, post_hook, memo)?,
+                    c: self.process_shared(c, pre_hook, post_hook, memo)?,
+                    op: op.clone(),
+                },
+                Sentence::Basic(_v) => s.clone(),
+            },
         };
 
-        Sentence::BinOp {
-            a: Val::new(aa),
-            b: Val::new(bb),
-            op,
+        match post_hook(&res).map_err(|e| HookError::Post(Box::new(res.clone()), e))? {
+            Some(s) => Ok(s),
+            None => Ok(res),
         }
     }
 
-    fn process_unary_op(
+    fn process_shared(
         &self,
-        a: &Sentence,
-        op: UnaryOp,
+        v: &Val<Sentence>,
         pre_hook: &Hook,
         post_hook: &Hook,
-    ) -> Sentence {
-        let aa = match self.exec_hook(pre_hook, a) {
-            None => self.process_sentence(a, pre_hook, post_hook),
-            Some(s) => s,
-        };
-        Sentence::UnaryOp {
-            a: Val::new(aa),
-            op,
+        memo: &mut Memo,
+    ) -> Result<Val<Sentence>, HookError> {
+        let key = Rc::as_ptr(&v.0);
+        if let Some(done) = memo.get(&key) {
+            return Ok(done.clone());
         }
-    }
-
-    fn process_ternary_op(
-        &self,
-        a: &Sentence,
-        b: &Sentence,
-        c: &Sentence,
-        op: TernaryOp,
-        pre_hook: &Hook,
-        post_hook: &Hook,
-    ) -> Sentence {
-        let aa = match self.exec_hook(pre_hook, a) {
-            None => self.process_sentence(a, pre_hook, post_hook),
-            Some(s) => s,
-        };
-        let bb = match self.exec_hook(pre_hook, b) {
-            None => self.process_sentence(b, pre_hook, post_hook),
-            Some(s) => s,
-        };
-        let cc = match self.exec_hook(pre_hook, c) {
-            None => self.process_sentence(b, pre_hook, post_hook),
-            Some(s) => s,
+        let res = self.process_sentence(&v.0, pre_hook, post_hook, memo)?;
+        let done = if same_node(&res, &v.0) {
+            v.clone()
+        } else {
+            Val::new(res)
         };
-
-        Sentence::TernaryOp {
-            a: Val::new(aa),
-            b: Val::new(bb),
-            c: Val::new(cc),
-            op,
-        }
+        memo.insert(key, done.clone());
+        Ok(done)
     }
 }
+
+type Memo = HashMap<*const Sentence, Val<Sentence>>;
@@ -4,49 +4,18 @@ pub mod base_interpreter;
 mod test {
     use super::base_interpreter::*;
     use crate::value::ast::*;
-    use rhai::{Engine, EvalAltResult, Scope, Dynamic};
+    use proptest::prelude::*;
+    use rhai::{Engine, Scope};
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
 
-    // TO DO: Need to add methods to Sentence & AST types to transform their data in Rhai arrays in order for a hook like this
-    // to work: https://github.com/rhaiscript/rhai/blob/a0e272750abe066f7cc7813848782fa6aba42fed/tests/switch.rs#L194
-    // Obviously, need to replace match with 'switch' etc as well
+    // The `hook` below, written against the Rhai sentence API
     const HOOK: &str = r###"
-    fn script_hook(pgm) {
-
-       if pgm.is_bin_op()
-        {
-            let a = pgm
-            match (a.inner().as_ref(), b.inner().as_ref()) {
-                (Sentence::Basic(aa), Sentence::Basic(bb)) => {
-                    let aa = aa
-                        .clone()
-                        .into_concrete()
-                        .ok()
-                        .and_then(|aa| aa.into_number().ok())
-                        .and_then(|n| n.into_u64().ok())
-                        .unwrap();
-                    let bb = bb
-                        .clone()
-                        .into_concrete()
-                        .ok()
-                        .and_then(|bb| bb.into_number().ok())
-                        .and_then(|n| n.into_u64().ok())
-                        .unwrap();
-
-                    let sum = aa + bb;
-                    return Some(Sentence::Basic(Value::Concrete(CSimpleVal::Number(
-                        CNumber::U64(sum),
-                    ))));
-                }
-                _ => {
-                    return None;
-                },
-            }
-        } else {
-            return None;
+    fn post(pgm) {
+        if pgm.is_bin_op && pgm.op == BinOp::Plus && pgm.a.is_number && pgm.b.is_number {
+            return num(pgm.a.number + pgm.b.number);
         }
     }
-
-    script_hook(pgm)
     "###;
 
     fn hook(pgm: Sentence) -> Option<Sentence> {
@@ -100,6 +69,8 @@ mod test {
     struct TestInterpreter<'a> {
         engine: Engine,
         scope: Scope<'a>,
+        hook: &'static str,
+        post_process: &'static str,
     }
 
 
@@ -113,7 +84,12 @@ mod test {
             engine.register_fn("hook", hook);
             engine.register_fn("final_hook", final_hook);
             
-            Self {engine, scope}
+            Self {
+                engine,
+                scope,
+                hook: "hook(pgm)",
+                post_process: "final_hook(pgm_final)",
+            }
         }
     }
     impl Evaluate for TestInterpreter<'_> {
@@ -124,19 +100,18 @@ mod test {
         }
 
         fn interpreter_hook(&self) -> String {
-          // HOOK.to_string()
-          "hook(pgm)".to_string()
+            self.hook.to_string()
         }
 
         fn post_process_fn(&self) -> String {
-            "final_hook(pgm_final)".to_string()
+            self.post_process.to_string()
         }
 
         fn engine(&self) -> &rhai::Engine {
             &self.engine
         }
 
-        fn scope(&self) -> rhai::Scope {
+        fn scope(&self) -> rhai::Scope<'_> {
             self.scope.clone()
         }
     }
@@ -169,12 +144,79 @@ mod test {
         };
 
         let interpreter = TestInterpreter::new(pgm.clone());
-        let result = interpreter.interpret(pgm);
+        let result = interpreter.interpret(pgm).unwrap();
         println!("Final pgm is: {:?}", interpreter.scope.get_value::<Sentence>("pgm_final"));
         assert_eq!(result, 40);
 
     }
 
+    #[test]
+    fn test_evaluate_errors() {
+        let pgm: Sentence = "(+ (+ 5 10) (+ 5 20))".parse().unwrap();
+        let mut interpreter = TestInterpreter::new(pgm.clone());
+        let scripts = interpreter.compile().unwrap();
+        assert_eq!(
+            interpreter
+                .interpret_compiled(&scripts, pgm.clone())
+                .unwrap(),
+            40
+        );
+        let pgm2: Sentence = "(+ 1 2)".parse().unwrap();
+        assert_eq!(interpreter.interpret_compiled(&scripts, pgm2).unwrap(), 3);
+
+        interpreter.hook = "hook(pgm";
+        assert!(matches!(
+            interpreter.interpret(pgm.clone()),
+            Err(EvalError::Compile("hook", _))
+        ));
+
+        // Leaves are processed first, left to right
+        interpreter.hook = "throw \"no\"";
+        match interpreter.interpret(pgm.clone()) {
+            Err(EvalError::Hook(s, _)) => assert_eq!(s.to_string(), "5"),
+            _ => panic!("expected a hook error"),
+        }
+
+        interpreter.hook = "hook(pgm)";
+        interpreter.post_process = "pgm_final";
+        match interpreter.interpret(pgm) {
+            Err(e @ EvalError::PostProcess(..)) => {
+                assert!(e.to_string().starts_with("Post processing failed on 40"))
+            }
+            _ => panic!("expected a post processing error"),
+        }
+    }
+
+    #[test]
+    fn test_evaluate_shared_subtrees() {
+        let sum: Sentence = "(+ 5 10)".parse().unwrap();
+        let sum = Val::new(sum);
+        let pgm = Sentence::BinOp {
+            a: sum.clone(),
+            b: sum,
+            op: BinOp::Plus,
+        };
+        let mut interpreter = TestInterpreter::new(pgm.clone());
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        interpreter
+            .engine
+            .register_fn("count", move || counter.set(counter.get() + 1));
+        interpreter.hook = "count(); hook(pgm)";
+
+        assert_eq!(interpreter.interpret(pgm).unwrap(), 30);
+        // 5, 10, their sum once, and the root
+        assert_eq!(calls.get(), 4);
+    }
+
+    #[test]
+    fn test_add_pgm_script_rules() {
+        let pgm: Sentence = "(+ (+ 5 10) (+ 5 20))".parse().unwrap();
+        let rules = crate::script::RewriteRules::compile(HOOK).unwrap();
+        let result = rules.apply(pgm).unwrap();
+        assert_eq!(final_hook(result), 40);
+    }
+
     #[test]
     fn test_add_pgm() {
         let five = Sentence::Basic(Value::Concrete(CSimpleVal::Number(CNumber::U64(5_u64))));
@@ -203,7 +245,7 @@ mod test {
 
         // Post hook to collapse binary addition between two literals.
         // This effectively simplifies (flattens) a tree of addition operations into a single SimpleVal
-        let post_hook = |s: Sentence| -> Option<Sentence> {
+        let post_hook = |s: &Sentence| -> Result<Option<Sentence>, String> {
             if let Sentence::BinOp {
                 a,
                 b,
@@ -228,14 +270,14 @@ mod test {
                             .unwrap();
 
                         let sum = aa + bb;
-                        Some(Sentence::Basic(Value::Concrete(CSimpleVal::Number(
+                        Ok(Some(Sentence::Basic(Value::Concrete(CSimpleVal::Number(
                             CNumber::U64(sum),
-                        ))))
+                        )))))
                     }
-                    _ => None,
+                    _ => Ok(None),
                 }
             } else {
-                None
+                Ok(None)
             }
         };
 
@@ -252,10 +294,214 @@ mod test {
                 0
             }
         };
-        let pre_hook = |_s: Sentence| -> Option<Sentence> { None };
+        let pre_hook = |_s: &Sentence| -> Result<Option<Sentence>, String> { Ok(None) };
 
         let interpreter = Interpreter { pgm };
-        let result = interpreter.interpret(Box::new(pre_hook), Box::new(post_hook), final_hook);
+        let result = interpreter
+            .interpret(Box::new(pre_hook), Box::new(post_hook), final_hook)
+            .unwrap();
         assert_eq!(result, 40);
     }
+
+    fn recording_hook(log: &Rc<RefCell<Vec<String>>>) -> Hook {
+        let log = log.clone();
+        Box::new(move |s: &Sentence| {
+            log.borrow_mut().push(s.to_sexpr().to_string());
+            Ok(None)
+        })
+    }
+
+    fn identity() -> Hook {
+        Box::new(|_s: &Sentence| Ok(None))
+    }
+
+    #[test]
+    fn test_each_node_once() {
+        let pgm: Sentence = "(ite (= x 1) (+ y 2) (- z 3))".parse().unwrap();
+        let (pre, post) = (Rc::default(), Rc::default());
+        let interpreter = Interpreter { pgm };
+        interpreter
+            .interpret(recording_hook(&pre), recording_hook(&post), |_| ())
+            .unwrap();
+
+        let nodes = [
+            "(ite (= x 1) (+ y 2) (- z 3))",
+            "(= x 1)",
+            "x",
+            "1",
+            "(+ y 2)",
+            "y",
+            "2",
+            "(- z 3)",
+            "z",
+            "3",
+        ];
+        assert_eq!(*pre.borrow(), nodes);
+        let mut post = post.borrow().clone();
+        post.sort();
+        let mut nodes = nodes.map(String::from).to_vec();
+        nodes.sort();
+        assert_eq!(post, nodes);
+
+        // A shared subtree is processed once, and stays shared
+        let square = Val::new("(+ x 1)".parse::<Sentence>().unwrap());
+        let pgm = Sentence::BinOp {
+            a: square.clone(),
+            b: square,
+            op: BinOp::Mul,
+        };
+        let pre = Rc::default();
+        let rename: Hook = Box::new(|s: &Sentence| match s {
+            Sentence::Basic(v) if v.symbol().is_some() => Ok(Some("w".parse().unwrap())),
+            _ => Ok(None),
+        });
+        let result = Interpreter { pgm }
+            .interpret(recording_hook(&pre), rename, |s| s)
+            .unwrap();
+        assert_eq!(pre.borrow().len(), 4);
+        match result {
+            Sentence::BinOp { a, b, .. } => {
+                assert_eq!(a.0.to_sexpr().to_string(), "(+ w 1)");
+                assert!(Rc::ptr_eq(&a.0, &b.0));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_hook_errors() {
+        let pgm: Sentence = "(ite c (/ x 0) (+ z 3))".parse().unwrap();
+        let no_division: Hook = Box::new(|s: &Sentence| match s {
+            Sentence::BinOp { op: BinOp::Div, .. } => Err("division".to_string()),
+            _ => Ok(None),
+        });
+        let err = Interpreter { pgm: pgm.clone() }
+            .interpret(identity(), no_division, |s| s)
+            .unwrap_err();
+        assert!(
+            matches!(&err, HookError::Post(s, m) if s.to_sexpr().to_string() == "(/ x 0)" && m == "division")
+        );
+        assert_eq!(err.to_string(), "Post hook failed on x / 0: division");
+
+        // A pre hook replacing the division means it's never built
+        let replace: Hook = Box::new(|s: &Sentence| match s {
+            Sentence::BinOp { op: BinOp::Div, .. } => Ok(Some("0".parse().unwrap())),
+            _ => Ok(None),
+        });
+        let no_division: Hook = Box::new(|s: &Sentence| match s {
+            Sentence::BinOp { op: BinOp::Div, .. } => Err("division".to_string()),
+            _ => Ok(None),
+        });
+        let result = Interpreter { pgm }
+            .interpret(replace, no_division, |s| s)
+            .unwrap();
+        assert_eq!(result.to_sexpr().to_string(), "(ite c 0 (+ z 3))");
+    }
+
+    fn arb_sentence() -> impl Strategy<Value = Sentence> {
+        let leaf = prop_oneof![
+            any::<u64>().prop_map(|n| Sentence::Basic(Value::Concrete(CSimpleVal::Number(
+                CNumber::U64(n)
+            )))),
+            "[a-d]".prop_map(|name| name.parse::<Sentence>().unwrap()),
+            any::<bool>().prop_map(|b| b.to_string().parse::<Sentence>().unwrap()),
+        ];
+        leaf.prop_recursive(6, 64, 3, |inner| {
+            prop_oneof![
+                (
+                    inner.clone(),
+                    prop_oneof![Just(UnaryOp::Not), Just(UnaryOp::BitNot)]
+                )
+                    .prop_map(|(a, op)| Sentence::UnaryOp { a: Val::new(a), op }),
+                (
+                    inner.clone(),
+                    inner.clone(),
+                    prop_oneof![
+                        Just(BinOp::Plus),
+                        Just(BinOp::Mul),
+                        Just(BinOp::Lt),
+                        Just(BinOp::BitXor),
+                        Just(BinOp::RShift)
+                    ]
+                )
+                    .prop_map(|(a, b, op)| Sentence::BinOp {
+                        a: Val::new(a),
+                        b: Val::new(b),
+                        op
+                    }),
+                (inner.clone(), inner.clone(), inner).prop_map(|(a, b, c)| {
+                    Sentence::TernaryOp {
+                        a: Val::new(a),
+                        b: Val::new(b),
+                        c: Val::new(c),
+                        op: TernaryOp::Ite,
+                    }
+                }),
+            ]
+        })
+    }
+
+    fn same_children(a: &Sentence, b: &Sentence) -> bool {
+        match (a, b) {
+            (Sentence::UnaryOp { a, .. }, Sentence::UnaryOp { a: a2, .. }) => {
+                Rc::ptr_eq(&a.0, &a2.0)
+            }
+            (Sentence::BinOp { a, b, .. }, Sentence::BinOp { a: a2, b: b2, .. }) => {
+                Rc::ptr_eq(&a.0, &a2.0) && Rc::ptr_eq(&b.0, &b2.0)
+            }
+            (
+                Sentence::TernaryOp { a, b, c, .. },
+                Sentence::TernaryOp {
+                    a: a2,
+                    b: b2,
+                    c: c2,
+                    ..
+                },
+            ) => Rc::ptr_eq(&a.0, &a2.0) && Rc::ptr_eq(&b.0, &b2.0) && Rc::ptr_eq(&c.0, &c2.0),
+            (Sentence::Basic(_), Sentence::Basic(_)) => true,
+            _ => false,
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn prop_identity_hooks_change_nothing(pgm in arb_sentence()) {
+            let result = Interpreter { pgm: pgm.clone() }
+                .interpret(identity(), identity(), |s| s)
+                .unwrap();
+            prop_assert_eq!(&result, &pgm);
+            prop_assert!(same_children(&result, &pgm));
+        }
+
+        #[test]
+        fn prop_hooks_see_every_node(pgm in arb_sentence()) {
+            let (pre, post) = (Rc::default(), Rc::default());
+            Interpreter { pgm: pgm.clone() }
+                .interpret(recording_hook(&pre), recording_hook(&post), |_| ())
+                .unwrap();
+            prop_assert_eq!(pre.borrow().len(), pgm.accept(&mut NodeCount));
+            prop_assert_eq!(post.borrow().len(), pre.borrow().len());
+        }
+    }
+
+    // Nodes of a sentence as a tree; the generated sentences have no sharing
+    struct NodeCount;
+
+    impl Visitor<usize> for NodeCount {
+        fn visit_val(&mut self, _v: &Value) -> usize {
+            1
+        }
+
+        fn visit_unary_op(&mut self, _op: &UnaryOp, a: usize) -> usize {
+            1 + a
+        }
+
+        fn visit_bin_op(&mut self, _op: &BinOp, a: usize, b: usize) -> usize {
+            1 + a + b
+        }
+
+        fn visit_ternary_op(&mut self, _op: &TernaryOp, a: usize, b: usize, c: usize) -> usize {
+            1 + a + b + c
+        }
+    }
 }
@@ -3,7 +3,7 @@ pub mod core {
     pub use symbolic_stack_machines_core::*;
 }
 
-#[cfg(feature = "full")]
+#[cfg(feature = "contrib")]
 pub mod contrib {
     pub use symbolic_stack_machines_contrib::*;
 }